@@ -0,0 +1,101 @@
+//! Attribute macros for `stats_alloc`, re-exported by it behind the `macros`
+//! feature. See the `stats_alloc` documentation for usage.
+
+#![deny(missing_docs)]
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{meta::ParseNestedMeta, Error, Expr, Ident, ItemFn, ReturnType};
+
+/// Arguments given to `#[stats_alloc::test]`.
+#[derive(Default)]
+struct TestArgs {
+    alloc: Option<Expr>,
+    limits: Vec<(Ident, Expr)>,
+}
+
+impl TestArgs {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        let name = meta.path.require_ident()?.clone();
+        let value: Expr = meta.value()?.parse()?;
+        let field = match name.to_string().as_str() {
+            "alloc" => {
+                self.alloc = Some(value);
+                return Ok(());
+            },
+            "max_allocs" => Ident::new("allocations", name.span()),
+            "max_bytes" => Ident::new("bytes_allocated", name.span()),
+            _ => name,
+        };
+        self.limits.push((field, value));
+        Ok(())
+    }
+}
+
+/// Runs a test within a region of an instrumented allocator, failing it if
+/// any declared budget is exceeded and printing its statistics on failure.
+///
+/// Budgets are written as `max_allocs = 10` or `max_bytes = 4096`, or with
+/// the name of any `Stats` field, such as `reallocations = 0`. The allocator
+/// defaults to `stats_alloc::INSTRUMENTED_SYSTEM` and may be given as
+/// `alloc = GLOBAL`.
+///
+/// The allocator's counters are shared by the whole process, so tests using
+/// this attribute wait for one another rather than run in parallel. Other
+/// tests in the same binary are not held back, and their allocations are
+/// counted against any of these tests running at the same time.
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut test_args = TestArgs::default();
+    let parser = syn::meta::parser(|meta| test_args.parse(meta));
+    syn::parse_macro_input!(args with parser);
+    let function = syn::parse_macro_input!(item as ItemFn);
+    expand(test_args, function)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(args: TestArgs, function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let ItemFn { attrs, vis, sig, block } = function;
+    if sig.asyncness.is_some() {
+        return Err(Error::new_spanned(sig.fn_token, "async tests are not supported"));
+    }
+    if !sig.inputs.is_empty() {
+        return Err(Error::new_spanned(sig.inputs, "tests take no arguments"));
+    }
+    let name = &sig.ident;
+    let alloc = match args.alloc {
+        Some(alloc) => quote!(#alloc),
+        None => quote!(&::stats_alloc::INSTRUMENTED_SYSTEM),
+    };
+    let fields = args.limits.iter().map(|(field, _)| field);
+    let values = args.limits.iter().map(|(_, value)| value);
+    let body = match sig.output {
+        ReturnType::Default => quote!(move || #block),
+        ReturnType::Type(_, ref ty) => quote!(move || -> #ty #block),
+    };
+    let scope = Ident::new("__stats_alloc_scope", Span::mixed_site());
+    let result = Ident::new("__stats_alloc_result", Span::mixed_site());
+    Ok(quote! {
+        #[test]
+        #(#attrs)*
+        #vis #sig {
+            let #scope = ::stats_alloc::__private::TestScope::new(
+                #alloc,
+                stringify!(#name),
+                ::stats_alloc::StatsBuilder::from(::stats_alloc::Stats::unlimited())
+                    #(.#fields(#values))*
+                    .build(),
+            );
+            let #result = (#body)();
+            #scope.finish();
+            #result
+        }
+    })
+}
@@ -0,0 +1,249 @@
+//! Summaries of the statistics collected over repeated runs of a region.
+
+use std::{alloc::GlobalAlloc, fmt, iter::FromIterator};
+
+use crate::{report::write_bytes, FieldFn, Region, Stats, StatsAlloc, MAXIMUM_FIELDS, STATS_FIELDS};
+
+/// The distribution of a single counter across a set of runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CounterSummary {
+    /// Smallest value seen in any run
+    pub min: i128,
+    /// Largest value seen in any run
+    pub max: i128,
+    /// Arithmetic mean of the values
+    pub mean: f64,
+    /// Sample standard deviation of the values, or zero for a single run
+    pub stddev: f64,
+}
+
+/// Collects the `Stats` changes from repeated runs of the same region, so
+/// that noisy measurements can be summarized rather than asserted on from a
+/// single run.
+///
+/// ```
+/// # use stats_alloc::{StatsAccumulator, INSTRUMENTED_SYSTEM};
+/// let mut runs = StatsAccumulator::new();
+/// for _ in 0..10 {
+///     runs.measure(&INSTRUMENTED_SYSTEM, || {
+///         let _buffer: Vec<u8> = Vec::with_capacity(64);
+///     });
+/// }
+/// let allocations = runs.counter("allocations").unwrap();
+/// assert!(allocations.max >= allocations.min);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsAccumulator {
+    samples: Vec<Stats>,
+}
+
+impl StatsAccumulator {
+    /// Creates an accumulator without any runs.
+    pub fn new() -> Self {
+        StatsAccumulator::default()
+    }
+
+    /// Records the change in statistics from a single run.
+    pub fn push(&mut self, change: Stats) {
+        self.samples.push(change);
+    }
+
+    /// Runs `f` within a region of `alloc` and records the resulting change.
+    pub fn measure<T: GlobalAlloc, F: FnOnce() -> R, R>(&mut self, alloc: &StatsAlloc<T>, f: F) -> R {
+        let region = Region::new(alloc);
+        let result = f();
+        self.push(region.change());
+        result
+    }
+
+    /// Returns the changes recorded for each run.
+    pub fn samples(&self) -> &[Stats] {
+        &self.samples
+    }
+
+    /// Returns the number of runs recorded.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if no runs have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Summarizes the named `Stats` field across all runs.
+    ///
+    /// Returns `None` if no runs have been recorded or no such field exists.
+    /// The maxima `peak_live_allocations` and `max_allocation_size` are not
+    /// summarized either, as the change over a region carries them through
+    /// from the whole life of the allocator rather than the run.
+    pub fn counter(&self, name: &str) -> Option<CounterSummary> {
+        fields()
+            .find(|&&(field, _)| field == name)
+            .and_then(|&(_, value)| self.summarize(value))
+    }
+
+    /// Summarizes every `Stats` field but the maxima across all runs, in
+    /// declaration order.
+    pub fn counters(&self) -> Vec<(&'static str, CounterSummary)> {
+        fields()
+            .filter_map(|&(name, value)| self.summarize(value).map(|summary| (name, summary)))
+            .collect()
+    }
+
+    fn summarize(&self, value: fn(&Stats) -> i128) -> Option<CounterSummary> {
+        let mut values = self.samples.iter().map(value);
+        let first = values.next()?;
+        let (mut min, mut max, mut sum) = (first, first, first as f64);
+        for v in values {
+            min = min.min(v);
+            max = max.max(v);
+            sum += v as f64;
+        }
+        let n = self.samples.len() as f64;
+        let mean = sum / n;
+        let stddev = if self.samples.len() > 1 {
+            let squares: f64 = self
+                .samples
+                .iter()
+                .map(|s| {
+                    let d = value(s) as f64 - mean;
+                    d * d
+                })
+                .sum();
+            (squares / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Some(CounterSummary { min, max, mean, stddev })
+    }
+}
+
+/// Returns the fields which can be summarized over runs.
+fn fields() -> impl Iterator<Item = &'static (&'static str, FieldFn)> {
+    STATS_FIELDS
+        .iter()
+        .filter(|&&(name, _)| !MAXIMUM_FIELDS.contains(&name))
+}
+
+impl fmt::Display for StatsAccumulator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} runs", self.len())?;
+        for (name, summary) in self.counters() {
+            writeln!(
+                f,
+                "{:<26} min {:>10} max {:>10} mean {:>12.1} stddev {:>10.1}",
+                name, summary.min, summary.max, summary.mean, summary.stddev
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Totals of the `Stats` changes over many regions, such as one per request,
+/// for reporting them together.
+///
+/// Unlike [`StatsAccumulator`], which keeps every run to summarize its
+/// distribution, a report keeps only the count and totals, so it can be
+/// updated indefinitely and merged with other reports.
+///
+/// ```
+/// # use stats_alloc::{RegionReport, Stats};
+/// let report: RegionReport = vec![
+///     Stats::builder().allocations(2).bytes_allocated(64).build(),
+///     Stats::builder().allocations(4).bytes_allocated(192).build(),
+/// ]
+/// .into_iter()
+/// .collect();
+/// assert_eq!(report.total().allocations, 6);
+/// assert_eq!(report.mean("bytes_allocated"), Some(128.0));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegionReport {
+    regions: u64,
+    total: Stats,
+}
+
+impl RegionReport {
+    /// Creates a report without any regions.
+    pub fn new() -> Self {
+        RegionReport::default()
+    }
+
+    /// Adds the change in statistics from a single region.
+    pub fn push(&mut self, change: Stats) {
+        self.regions += 1;
+        self.total += change;
+    }
+
+    /// Adds the regions of another report to this one.
+    pub fn merge(&mut self, other: &RegionReport) {
+        self.regions += other.regions;
+        self.total += other.total;
+    }
+
+    /// Runs `f` within a region of `alloc` and adds the resulting change.
+    pub fn measure<T: GlobalAlloc, F: FnOnce() -> R, R>(&mut self, alloc: &StatsAlloc<T>, f: F) -> R {
+        let region = Region::new(alloc);
+        let result = f();
+        self.push(region.change());
+        result
+    }
+
+    /// Returns the number of regions added.
+    pub fn len(&self) -> u64 {
+        self.regions
+    }
+
+    /// Returns `true` if no regions have been added.
+    pub fn is_empty(&self) -> bool {
+        self.regions == 0
+    }
+
+    /// Returns the sum of the changes over every region.
+    pub fn total(&self) -> Stats {
+        self.total
+    }
+
+    /// Returns the mean of the named `Stats` field per region.
+    ///
+    /// Returns `None` if no regions have been added or no such field exists.
+    pub fn mean(&self, name: &str) -> Option<f64> {
+        if self.regions == 0 {
+            return None;
+        }
+        STATS_FIELDS
+            .iter()
+            .find(|&&(field, _)| field == name)
+            .map(|&(_, value)| value(&self.total) as f64 / self.regions as f64)
+    }
+}
+
+impl Extend<Stats> for RegionReport {
+    fn extend<I: IntoIterator<Item = Stats>>(&mut self, iter: I) {
+        for change in iter {
+            self.push(change);
+        }
+    }
+}
+
+impl FromIterator<Stats> for RegionReport {
+    fn from_iter<I: IntoIterator<Item = Stats>>(iter: I) -> Self {
+        let mut report = RegionReport::new();
+        report.extend(iter);
+        report
+    }
+}
+
+/// Summarizes the totals and the mean operations and bytes per region, e.g.
+/// `12 regions, total alloc: 48 ops / 3.0 KiB, ..., mean 4.0 allocs / 256 B`.
+impl fmt::Display for RegionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} regions, total {}", self.regions, self.total)?;
+        if let (Some(allocations), Some(bytes)) = (self.mean("allocations"), self.mean("bytes_allocated")) {
+            write!(f, ", mean {:.1} allocs / ", allocations)?;
+            write_bytes(f, bytes as i128, false)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+//! Generation of arbitrary `Stats` for property-based tests, behind the
+//! `proptest` and `quickcheck` features.
+
+use crate::Stats;
+
+/// Builds statistics from one raw value per field, in declaration order.
+/// Signed fields take the value's two's complement interpretation.
+fn from_values<I: IntoIterator<Item = u64>>(values: I) -> Stats {
+    let mut values = values.into_iter();
+    Stats {
+        allocations: values.next().unwrap_or_default(),
+        deallocations: values.next().unwrap_or_default(),
+        reallocations: values.next().unwrap_or_default(),
+        bytes_allocated: values.next().unwrap_or_default(),
+        bytes_deallocated: values.next().unwrap_or_default(),
+        bytes_reallocated: values.next().unwrap_or_default() as i64,
+        page_aligned_allocations: values.next().unwrap_or_default(),
+        huge_allocations: values.next().unwrap_or_default(),
+        huge_bytes_allocated: values.next().unwrap_or_default(),
+        bytes_current: values.next().unwrap_or_default() as i64,
+        live_allocations: values.next().unwrap_or_default() as i64,
+        peak_live_allocations: values.next().unwrap_or_default(),
+        allocation_failures: values.next().unwrap_or_default(),
+        reallocations_grow: values.next().unwrap_or_default(),
+        reallocations_shrink: values.next().unwrap_or_default(),
+        bytes_grown: values.next().unwrap_or_default(),
+        bytes_shrunk: values.next().unwrap_or_default(),
+        max_allocation_size: values.next().unwrap_or_default(),
+        allocations_align_8: values.next().unwrap_or_default(),
+        allocations_align_16: values.next().unwrap_or_default(),
+        allocations_align_32: values.next().unwrap_or_default(),
+        allocations_align_64: values.next().unwrap_or_default(),
+        allocations_align_over_64: values.next().unwrap_or_default(),
+        zero_size_allocations: values.next().unwrap_or_default(),
+        overflows: values.next().unwrap_or_default(),
+    }
+}
+
+/// Values at the edges of the counters' ranges, where wrapping and overflow
+/// handling are most likely to go wrong.
+const EDGE_VALUES: &[u64] = &[
+    0,
+    1,
+    u32::MAX as u64,
+    i64::MAX as u64,
+    i64::MIN as u64,
+    u64::MAX - 1,
+    u64::MAX,
+];
+
+#[cfg(feature = "proptest")]
+mod proptest_impl {
+    use proptest::{
+        arbitrary::Arbitrary,
+        collection,
+        prelude::{any, prop_oneof, BoxedStrategy, Just, Strategy},
+        sample,
+    };
+
+    use super::{from_values, EDGE_VALUES};
+    use crate::{Stats, STATS_FIELDS};
+
+    /// Generates statistics whose fields are small, uniformly random, or at
+    /// the edges of their ranges, shrinking towards zero.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "proptest")))]
+    impl Arbitrary for Stats {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Stats>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            let value = prop_oneof![
+                4 => 0..4096u64,
+                2 => any::<u64>(),
+                1 => sample::select(EDGE_VALUES),
+                1 => Just(0u64),
+            ];
+            collection::vec(value, STATS_FIELDS.len()).prop_map(from_values).boxed()
+        }
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+mod quickcheck_impl {
+    use quickcheck::{Arbitrary, Gen};
+
+    use super::{from_values, EDGE_VALUES};
+    use crate::{Stats, STATS_FIELDS};
+
+    /// Generates statistics whose fields are sized by the generator or at the
+    /// edges of their ranges, shrinking one field at a time.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "quickcheck")))]
+    impl Arbitrary for Stats {
+        fn arbitrary(g: &mut Gen) -> Self {
+            from_values((0..STATS_FIELDS.len()).map(|_| {
+                if u8::arbitrary(g) < 32 {
+                    *g.choose(EDGE_VALUES).unwrap_or(&0)
+                } else {
+                    u64::arbitrary(g)
+                }
+            }))
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let values = super::values(self);
+            Box::new((0..values.len()).flat_map(move |i| {
+                let values = values.clone();
+                values[i].shrink().map(move |shrunk| {
+                    let mut values = values.clone();
+                    values[i] = shrunk;
+                    from_values(values)
+                })
+            }))
+        }
+    }
+}
+
+/// Returns the raw value of every field, in declaration order.
+#[cfg(feature = "quickcheck")]
+fn values(stats: &Stats) -> Vec<u64> {
+    crate::STATS_FIELDS
+        .iter()
+        .map(|&(_, value)| value(stats) as u64)
+        .collect()
+}
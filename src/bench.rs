@@ -0,0 +1,135 @@
+//! Per-iteration allocation statistics for benchmarks.
+
+use std::{alloc::GlobalAlloc, fmt, hint::black_box};
+
+use crate::{Region, Stats, StatsAlloc, MAXIMUM_FIELDS, STATS_FIELDS};
+
+/// The per-iteration values of a single counter over a benchmark, after the
+/// baseline has been subtracted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BenchCounter {
+    /// Smallest value in any iteration
+    pub min: i128,
+    /// Median value, taking the lower of the two middle values for an even
+    /// number of iterations
+    pub median: i128,
+}
+
+/// The result of [`bench_allocs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BenchReport {
+    /// Number of measured iterations
+    pub iterations: u32,
+    /// Median of each counter over iterations of an empty closure, which was
+    /// subtracted from the measured values, or zero for the maxima
+    pub baseline: Vec<(&'static str, i128)>,
+    counters: Vec<(&'static str, BenchCounter)>,
+}
+
+impl BenchReport {
+    /// Returns the named `Stats` field per iteration, or `None` if no such
+    /// field exists.
+    pub fn counter(&self, name: &str) -> Option<BenchCounter> {
+        self.counters
+            .iter()
+            .find(|&&(field, _)| field == name)
+            .map(|&(_, counter)| counter)
+    }
+
+    /// Returns every `Stats` field per iteration, in declaration order.
+    pub fn counters(&self) -> &[(&'static str, BenchCounter)] {
+        &self.counters
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} iterations", self.iterations)?;
+        for &(name, counter) in &self.counters {
+            writeln!(f, "{:<26} min {:>10} median {:>10}", name, counter.min, counter.median)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` repeatedly, each time in its own region of `alloc`, and reports
+/// the minimum and median of each counter per iteration.
+///
+/// `f` is run once beforehand as a warm-up, so that one-time initialization
+/// is not measured. The median counts over the same number of iterations of
+/// an empty closure are subtracted, removing whatever the measurement itself
+/// or other threads allocate in the background. Any value `f` returns is
+/// dropped within its iteration.
+///
+/// ```
+/// # use stats_alloc::{bench_allocs, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let report = bench_allocs(GLOBAL, 100, || vec![0u8; 64]);
+///     println!("{}", report);
+/// }
+/// ```
+pub fn bench_allocs<T, F, R>(alloc: &StatsAlloc<T>, iterations: u32, mut f: F) -> BenchReport
+where
+    T: GlobalAlloc,
+    F: FnMut() -> R,
+{
+    let iterations = iterations.max(1);
+    drop(black_box(f()));
+    let baseline = sample(alloc, iterations, || black_box(()));
+    let measured = sample(alloc, iterations, || drop(black_box(f())));
+    // The maxima are those of the allocator over its lifetime, so the
+    // baseline is not subtracted from them.
+    let baseline: Vec<_> = STATS_FIELDS
+        .iter()
+        .map(|&(name, value)| {
+            if MAXIMUM_FIELDS.contains(&name) {
+                (name, 0)
+            } else {
+                (name, median(&mut baseline.iter().map(value).collect::<Vec<_>>()))
+            }
+        })
+        .collect();
+    let counters = STATS_FIELDS
+        .iter()
+        .zip(&baseline)
+        .map(|(&(name, value), &(_, base))| {
+            let mut values: Vec<_> = measured.iter().map(|s| value(s) - base).collect();
+            let min = values.iter().copied().min().unwrap_or_default();
+            (
+                name,
+                BenchCounter {
+                    min,
+                    median: median(&mut values),
+                },
+            )
+        })
+        .collect();
+    BenchReport {
+        iterations,
+        baseline,
+        counters,
+    }
+}
+
+fn sample<T: GlobalAlloc, F: FnMut()>(alloc: &StatsAlloc<T>, iterations: u32, mut f: F) -> Vec<Stats> {
+    // Reserved up front so that recording a sample never allocates.
+    let mut samples = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        let region = Region::new(alloc);
+        f();
+        samples.push(region.change());
+    }
+    samples
+}
+
+fn median(values: &mut [i128]) -> i128 {
+    values.sort_unstable();
+    values
+        .get(values.len().saturating_sub(1) / 2)
+        .copied()
+        .unwrap_or_default()
+}
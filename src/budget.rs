@@ -0,0 +1,117 @@
+//! Declarative allocation budgets.
+
+use std::{error::Error, fmt};
+
+use crate::{Stats, STATS_FIELDS};
+
+/// A single statistic which exceeded its budget.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BudgetViolation {
+    /// Name of the `Stats` field
+    pub field: &'static str,
+    /// Value which was measured
+    pub actual: i128,
+    /// Largest value allowed by the budget
+    pub limit: i128,
+}
+
+impl fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} was {}, exceeding the budget of {}",
+            self.field, self.actual, self.limit
+        )
+    }
+}
+
+/// The error returned by [`Stats::check_budget`], listing every statistic
+/// which exceeded its budget.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BudgetExceeded {
+    /// Violations in `Stats` field order
+    pub violations: Vec<BudgetViolation>,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("allocation budget exceeded: ")?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BudgetExceeded {}
+
+impl Stats {
+    /// Returns statistics with every field at its maximum, for use as the base
+    /// of a budget which limits only some fields.
+    ///
+    /// ```
+    /// # use stats_alloc::{Stats, StatsBuilder};
+    /// let budget = StatsBuilder::from(Stats::unlimited())
+    ///     .allocations(3)
+    ///     .bytes_allocated(4 * 1024)
+    ///     .build();
+    /// let measured = Stats::builder().allocations(2).bytes_allocated(512).build();
+    /// assert!(measured.fits_within(&budget));
+    /// ```
+    pub fn unlimited() -> Self {
+        Stats {
+            allocations: u64::MAX,
+            deallocations: u64::MAX,
+            reallocations: u64::MAX,
+            bytes_allocated: u64::MAX,
+            bytes_deallocated: u64::MAX,
+            bytes_reallocated: i64::MAX,
+            page_aligned_allocations: u64::MAX,
+            huge_allocations: u64::MAX,
+            huge_bytes_allocated: u64::MAX,
+            bytes_current: i64::MAX,
+            live_allocations: i64::MAX,
+            peak_live_allocations: u64::MAX,
+            allocation_failures: u64::MAX,
+            reallocations_grow: u64::MAX,
+            reallocations_shrink: u64::MAX,
+            bytes_grown: u64::MAX,
+            bytes_shrunk: u64::MAX,
+            max_allocation_size: u64::MAX,
+            allocations_align_8: u64::MAX,
+            allocations_align_16: u64::MAX,
+            allocations_align_32: u64::MAX,
+            allocations_align_64: u64::MAX,
+            allocations_align_over_64: u64::MAX,
+            zero_size_allocations: u64::MAX,
+            overflows: u64::MAX,
+        }
+    }
+
+    /// Returns `true` if no field exceeds the corresponding field of `budget`.
+    pub fn fits_within(&self, budget: &Stats) -> bool {
+        STATS_FIELDS.iter().all(|&(_, value)| value(self) <= value(budget))
+    }
+
+    /// Checks every field against the corresponding field of `budget`,
+    /// returning all those which exceed it.
+    pub fn check_budget(&self, budget: &Stats) -> Result<(), BudgetExceeded> {
+        let violations: Vec<_> = STATS_FIELDS
+            .iter()
+            .filter(|&&(_, value)| value(self) > value(budget))
+            .map(|&(field, value)| BudgetViolation {
+                field,
+                actual: value(self),
+                limit: value(budget),
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(BudgetExceeded { violations })
+        }
+    }
+}
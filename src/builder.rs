@@ -0,0 +1,195 @@
+//! Construction of `Stats` values field by field.
+
+use crate::Stats;
+
+/// A builder for [`Stats`], which cannot be constructed with a struct
+/// literal outside of this crate.
+///
+/// Every field starts at zero unless the builder is created from existing
+/// statistics.
+///
+/// ```
+/// # use stats_alloc::Stats;
+/// let expected = Stats::builder().allocations(2).bytes_allocated(64).build();
+/// assert_eq!(expected.allocations, 2);
+/// assert_eq!(expected.deallocations, 0);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StatsBuilder {
+    stats: Stats,
+}
+
+impl StatsBuilder {
+    /// Creates a builder with every field at zero.
+    pub fn new() -> Self {
+        StatsBuilder::default()
+    }
+
+    /// Returns the statistics built so far.
+    pub fn build(self) -> Stats {
+        self.stats
+    }
+
+    /// Sets [`Stats::allocations`].
+    pub fn allocations(mut self, value: u64) -> Self {
+        self.stats.allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::deallocations`].
+    pub fn deallocations(mut self, value: u64) -> Self {
+        self.stats.deallocations = value;
+        self
+    }
+
+    /// Sets [`Stats::reallocations`].
+    pub fn reallocations(mut self, value: u64) -> Self {
+        self.stats.reallocations = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_allocated`].
+    pub fn bytes_allocated(mut self, value: u64) -> Self {
+        self.stats.bytes_allocated = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_deallocated`].
+    pub fn bytes_deallocated(mut self, value: u64) -> Self {
+        self.stats.bytes_deallocated = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_reallocated`].
+    pub fn bytes_reallocated(mut self, value: i64) -> Self {
+        self.stats.bytes_reallocated = value;
+        self
+    }
+
+    /// Sets [`Stats::page_aligned_allocations`].
+    pub fn page_aligned_allocations(mut self, value: u64) -> Self {
+        self.stats.page_aligned_allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::huge_allocations`].
+    pub fn huge_allocations(mut self, value: u64) -> Self {
+        self.stats.huge_allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::huge_bytes_allocated`].
+    pub fn huge_bytes_allocated(mut self, value: u64) -> Self {
+        self.stats.huge_bytes_allocated = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_current`].
+    pub fn bytes_current(mut self, value: i64) -> Self {
+        self.stats.bytes_current = value;
+        self
+    }
+
+    /// Sets [`Stats::live_allocations`].
+    pub fn live_allocations(mut self, value: i64) -> Self {
+        self.stats.live_allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::peak_live_allocations`].
+    pub fn peak_live_allocations(mut self, value: u64) -> Self {
+        self.stats.peak_live_allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::allocation_failures`].
+    pub fn allocation_failures(mut self, value: u64) -> Self {
+        self.stats.allocation_failures = value;
+        self
+    }
+
+    /// Sets [`Stats::reallocations_grow`].
+    pub fn reallocations_grow(mut self, value: u64) -> Self {
+        self.stats.reallocations_grow = value;
+        self
+    }
+
+    /// Sets [`Stats::reallocations_shrink`].
+    pub fn reallocations_shrink(mut self, value: u64) -> Self {
+        self.stats.reallocations_shrink = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_grown`].
+    pub fn bytes_grown(mut self, value: u64) -> Self {
+        self.stats.bytes_grown = value;
+        self
+    }
+
+    /// Sets [`Stats::bytes_shrunk`].
+    pub fn bytes_shrunk(mut self, value: u64) -> Self {
+        self.stats.bytes_shrunk = value;
+        self
+    }
+
+    /// Sets [`Stats::max_allocation_size`].
+    pub fn max_allocation_size(mut self, value: u64) -> Self {
+        self.stats.max_allocation_size = value;
+        self
+    }
+
+    /// Sets [`Stats::allocations_align_8`].
+    pub fn allocations_align_8(mut self, value: u64) -> Self {
+        self.stats.allocations_align_8 = value;
+        self
+    }
+
+    /// Sets [`Stats::allocations_align_16`].
+    pub fn allocations_align_16(mut self, value: u64) -> Self {
+        self.stats.allocations_align_16 = value;
+        self
+    }
+
+    /// Sets [`Stats::allocations_align_32`].
+    pub fn allocations_align_32(mut self, value: u64) -> Self {
+        self.stats.allocations_align_32 = value;
+        self
+    }
+
+    /// Sets [`Stats::allocations_align_64`].
+    pub fn allocations_align_64(mut self, value: u64) -> Self {
+        self.stats.allocations_align_64 = value;
+        self
+    }
+
+    /// Sets [`Stats::allocations_align_over_64`].
+    pub fn allocations_align_over_64(mut self, value: u64) -> Self {
+        self.stats.allocations_align_over_64 = value;
+        self
+    }
+
+    /// Sets [`Stats::zero_size_allocations`].
+    pub fn zero_size_allocations(mut self, value: u64) -> Self {
+        self.stats.zero_size_allocations = value;
+        self
+    }
+
+    /// Sets [`Stats::overflows`].
+    pub fn overflows(mut self, value: u64) -> Self {
+        self.stats.overflows = value;
+        self
+    }
+}
+
+impl From<Stats> for StatsBuilder {
+    fn from(stats: Stats) -> Self {
+        StatsBuilder { stats }
+    }
+}
+
+impl Stats {
+    /// Returns a builder with every field at zero.
+    pub fn builder() -> StatsBuilder {
+        StatsBuilder::new()
+    }
+}
@@ -0,0 +1,99 @@
+//! Output of allocator statistics in the Chrome Trace Event format.
+
+use std::{
+    io::{self, Write},
+    process,
+    time::SystemTime,
+};
+
+use crate::{schema::write_json_str, Snapshot, StatsExporter, STATS_FIELDS};
+
+/// Exports snapshots as counter events in the JSON array form of the Chrome
+/// Trace Event format, for viewing in `chrome://tracing` or Perfetto
+/// alongside other traces.
+///
+/// Each field of [`Stats`] becomes a counter track named after it, sampled
+/// once per snapshot. Region begin and end markers may be interleaved with
+/// the samples, and show as slices on a single track, so they must nest.
+///
+/// Timestamps are microseconds since the Unix epoch, so traces from other
+/// sources using wall-clock time line up with the samples.
+///
+/// ```
+/// # use stats_alloc::{ChromeTraceExporter, Snapshot, StatsExporter, INSTRUMENTED_SYSTEM};
+/// let mut exporter = ChromeTraceExporter::new();
+/// let mut out = Vec::new();
+/// exporter.begin(&mut out).unwrap();
+/// exporter.begin_region(&mut out, "parse").unwrap();
+/// exporter.export(&mut out, &Snapshot::new(INSTRUMENTED_SYSTEM.stats())).unwrap();
+/// exporter.end_region(&mut out, "parse").unwrap();
+/// exporter.finish(&mut out).unwrap();
+/// ```
+///
+/// [`Stats`]: crate::Stats
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChromeTraceExporter {
+    events: u64,
+}
+
+impl ChromeTraceExporter {
+    /// Creates an exporter which has not yet written any events.
+    pub fn new() -> Self {
+        ChromeTraceExporter::default()
+    }
+
+    /// Writes a marker for the beginning of the region `name`, timestamped
+    /// now.
+    pub fn begin_region(&mut self, out: &mut dyn Write, name: &str) -> io::Result<()> {
+        self.marker(out, name, "B")
+    }
+
+    /// Writes a marker for the end of the region `name`, timestamped now.
+    pub fn end_region(&mut self, out: &mut dyn Write, name: &str) -> io::Result<()> {
+        self.marker(out, name, "E")
+    }
+
+    fn marker(&mut self, out: &mut dyn Write, name: &str, phase: &str) -> io::Result<()> {
+        self.event(out, name, phase, SystemTime::now())?;
+        out.write_all(b"}")
+    }
+
+    /// Writes the fields common to every event, leaving the object open.
+    fn event(&mut self, out: &mut dyn Write, name: &str, phase: &str, time: SystemTime) -> io::Result<()> {
+        if self.events > 0 {
+            out.write_all(b",\n")?;
+        }
+        self.events += 1;
+        let micros = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        out.write_all(b"{\"name\":")?;
+        write_json_str(out, name)?;
+        write!(
+            out,
+            ",\"cat\":\"stats_alloc\",\"ph\":\"{}\",\"ts\":{},\"pid\":{},\"tid\":0",
+            phase,
+            micros,
+            process::id()
+        )
+    }
+}
+
+impl StatsExporter for ChromeTraceExporter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(b"[\n")
+    }
+
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        for &(name, value) in STATS_FIELDS {
+            self.event(out, name, "C", snapshot.time)?;
+            write!(out, ",\"args\":{{\"value\":{}}}}}", value(&snapshot.stats))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(b"\n]\n")
+    }
+}
@@ -0,0 +1,148 @@
+//! Side-by-side measurement of a workload against several backing allocators.
+
+use std::{
+    alloc::GlobalAlloc,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{ScopedAlloc, ScopedAllocGuard, Stats, StatsAlloc};
+
+/// The outcome of running a workload against a single backing allocator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Measurement {
+    /// Name given to the backing allocator
+    pub name: String,
+    /// Allocation statistics recorded while the workload ran
+    pub stats: Stats,
+    /// Wall-clock time taken by the workload
+    pub elapsed: Duration,
+}
+
+/// The measurements taken by [`Comparison::run`], one per candidate, which
+/// display as a side-by-side table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComparisonReport {
+    /// Measurements in the order in which the candidates were added
+    pub measurements: Vec<Measurement>,
+}
+
+struct Candidate {
+    name: String,
+    backing: Option<&'static dyn ScopedAlloc>,
+    stats: Box<dyn Fn() -> Stats>,
+}
+
+/// A harness which runs the same workload against several instrumented
+/// backing allocators in turn.
+///
+/// Each candidate is installed as the current thread's backing allocator with
+/// a [`ScopedAllocGuard`] for the duration of its run, and is measured through
+/// its own `StatsAlloc` so that candidates do not observe one another.
+///
+/// ```no_run
+/// # use stats_alloc::{Comparison, ScopedAlloc, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # fn example<A: ScopedAlloc>(arena: &'static StatsAlloc<A>) {
+/// let report = unsafe {
+///     Comparison::new()
+///         .baseline("system", &INSTRUMENTED_SYSTEM)
+///         .candidate("arena", arena)
+///         .run(|| {
+///             let _buffer: Vec<u8> = Vec::with_capacity(1_024);
+///         })
+/// };
+/// println!("{}", report);
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Comparison {
+    candidates: Vec<Candidate>,
+}
+
+impl Comparison {
+    /// Creates a harness without any candidates.
+    pub fn new() -> Self {
+        Comparison::default()
+    }
+
+    /// Adds a run made without overriding the backing allocator, measured
+    /// through the given instrumented allocator.
+    ///
+    /// Typically this is the global allocator, in which case allocations made
+    /// concurrently by other threads are included in the measurement.
+    pub fn baseline<T: GlobalAlloc>(mut self, name: &str, alloc: &'static StatsAlloc<T>) -> Self {
+        self.candidates.push(Candidate {
+            name: name.to_owned(),
+            backing: None,
+            stats: Box::new(move || alloc.stats()),
+        });
+        self
+    }
+
+    /// Adds a run made with `alloc` as the current thread's backing allocator.
+    pub fn candidate<T: ScopedAlloc>(mut self, name: &str, alloc: &'static StatsAlloc<T>) -> Self {
+        self.candidates.push(Candidate {
+            name: name.to_owned(),
+            backing: Some(alloc),
+            stats: Box::new(move || alloc.stats()),
+        });
+        self
+    }
+
+    /// Runs `work` once against each candidate in turn.
+    ///
+    /// # Safety
+    ///
+    /// The workload must not retain any memory that it allocates beyond the
+    /// end of each run, as required by [`ScopedAllocGuard::new`].
+    pub unsafe fn run<F: FnMut()>(&self, mut work: F) -> ComparisonReport {
+        let mut measurements = Vec::with_capacity(self.candidates.len());
+        for candidate in &self.candidates {
+            let guard = candidate.backing.map(|backing| ScopedAllocGuard::new(backing));
+            let initial = (candidate.stats)();
+            let start = Instant::now();
+            work();
+            let elapsed = start.elapsed();
+            let stats = (candidate.stats)() - initial;
+            drop(guard);
+            measurements.push(Measurement {
+                name: candidate.name.clone(),
+                stats,
+                elapsed,
+            });
+        }
+        ComparisonReport { measurements }
+    }
+}
+
+impl fmt::Debug for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.candidates.iter().map(|candidate| &candidate.name))
+            .finish()
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>12} {:>12} {:>12} {:>14} {:>14} {:>12}",
+            "allocator", "allocs", "deallocs", "reallocs", "bytes alloc", "bytes dealloc", "time (µs)"
+        )?;
+        for m in &self.measurements {
+            writeln!(
+                f,
+                "{:<16} {:>12} {:>12} {:>12} {:>14} {:>14} {:>12}",
+                m.name,
+                m.stats.allocations,
+                m.stats.deallocations,
+                m.stats.reallocations,
+                m.stats.bytes_allocated,
+                m.stats.bytes_deallocated,
+                m.elapsed.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+}
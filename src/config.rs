@@ -0,0 +1,236 @@
+//! Configuration of diagnostics from the environment.
+//!
+//! Settings may be given together in `STATS_ALLOC_CONFIG`, as `key=value`
+//! pairs separated by semicolons, or individually in variables named
+//! `STATS_ALLOC_<KEY>` in upper case, which take precedence:
+//!
+//! | Key                  | Value                                               |
+//! |----------------------|-----------------------------------------------------|
+//! | `report_interval`    | Enables periodic reporting, e.g. `500ms`, `10s`, `1m` |
+//! | `report_format`      | A [`Template`] for each report line                 |
+//! | `report_to`          | `stderr` (default), `stdout`, or a file to append to |
+//! | `huge_threshold`     | Bytes at or above which allocations are huge        |
+//! | `huge_warning`       | Bytes at or above which allocations are warned of   |
+//! | `realloc_accounting` | `net` (default) or `pair`                           |
+//! | `sampling`           | Counts one in this many operations                  |
+//!
+//! For example, `STATS_ALLOC_CONFIG="report_interval=10s;report_to=/tmp/alloc.log"`.
+
+use std::{
+    alloc::GlobalAlloc,
+    env,
+    error::Error,
+    fmt,
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{ReallocAccounting, Reporter, StatsAlloc, Template, TemplateError};
+
+/// The template used for periodic reports when no format is configured.
+pub const DEFAULT_REPORT_FORMAT: &str =
+    "stats_alloc: {allocations} allocs ({allocations/s}/s), {bytes_allocated} B in, {bytes_deallocated} B out";
+
+const KEYS: &[&str] = &[
+    "report_interval",
+    "report_format",
+    "report_to",
+    "huge_threshold",
+    "huge_warning",
+    "realloc_accounting",
+    "sampling",
+];
+
+/// Where periodic reports are written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReportDestination {
+    /// The standard error stream
+    #[default]
+    Stderr,
+    /// The standard output stream
+    Stdout,
+    /// A file, which is appended to
+    File(PathBuf),
+}
+
+/// Diagnostic settings, typically read from the environment with
+/// [`Config::from_env`] so that they can be changed without rebuilding.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    /// Interval at which to report, if periodic reporting is enabled
+    pub report_interval: Option<Duration>,
+    /// Template for each report line
+    pub report_format: Option<Template>,
+    /// Where reports are written
+    pub report_to: ReportDestination,
+    /// Size at or above which allocations are counted as huge
+    pub huge_threshold: Option<usize>,
+    /// Size at or above which a warning is written for each allocation
+    pub huge_warning: Option<usize>,
+    /// How reallocations are counted
+    pub realloc_accounting: Option<ReallocAccounting>,
+    /// Interval at which operations are counted, if sampling
+    pub sampling: Option<usize>,
+}
+
+/// An error in a configuration setting.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The setting is not recognized
+    UnknownKey(String),
+    /// The setting's value could not be parsed
+    InvalidValue {
+        /// Name of the setting
+        key: String,
+        /// The value which was given
+        value: String,
+    },
+    /// The report format is not a valid template
+    Template(TemplateError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::UnknownKey(key) => write!(f, "unknown stats_alloc setting `{}`", key),
+            ConfigError::InvalidValue { key, value } => {
+                write!(f, "invalid value `{}` for stats_alloc setting `{}`", value, key)
+            },
+            ConfigError::Template(e) => write!(f, "invalid stats_alloc report format: {}", e),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Template(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TemplateError> for ConfigError {
+    fn from(e: TemplateError) -> Self {
+        ConfigError::Template(e)
+    }
+}
+
+impl Config {
+    /// Reads the configuration from `STATS_ALLOC_CONFIG` and the individual
+    /// `STATS_ALLOC_<KEY>` variables.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut config = match env::var("STATS_ALLOC_CONFIG") {
+            Ok(settings) => Config::parse(&settings)?,
+            Err(_) => Config::default(),
+        };
+        for key in KEYS {
+            if let Ok(value) = env::var(format!("STATS_ALLOC_{}", key.to_uppercase())) {
+                config.set(key, &value)?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Parses semicolon-separated `key=value` settings, as found in
+    /// `STATS_ALLOC_CONFIG`.
+    pub fn parse(settings: &str) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        for setting in settings.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+            match setting.find('=') {
+                Some(i) => config.set(setting[..i].trim(), setting[i + 1..].trim())?,
+                None => {
+                    return Err(ConfigError::InvalidValue {
+                        key: setting.to_owned(),
+                        value: String::new(),
+                    })
+                },
+            }
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            key: key.to_owned(),
+            value: value.to_owned(),
+        };
+        match key {
+            "report_interval" => self.report_interval = Some(parse_duration(value).ok_or_else(invalid)?),
+            "report_format" => self.report_format = Some(Template::parse(value)?),
+            "report_to" => {
+                self.report_to = match value {
+                    "stderr" => ReportDestination::Stderr,
+                    "stdout" => ReportDestination::Stdout,
+                    "" => return Err(invalid()),
+                    path => ReportDestination::File(PathBuf::from(path)),
+                }
+            },
+            "huge_threshold" => self.huge_threshold = Some(value.parse().map_err(|_| invalid())?),
+            "huge_warning" => self.huge_warning = Some(value.parse().map_err(|_| invalid())?),
+            "realloc_accounting" => {
+                self.realloc_accounting = Some(match value {
+                    "net" => ReallocAccounting::Net,
+                    "pair" => ReallocAccounting::Pair,
+                    _ => return Err(invalid()),
+                })
+            },
+            "sampling" => self.sampling = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(ConfigError::UnknownKey(key.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// Applies the settings to `alloc`, starting a [`Reporter`] if periodic
+    /// reporting is enabled.
+    pub fn apply<T: GlobalAlloc + Sync>(&self, alloc: &'static StatsAlloc<T>) -> io::Result<Option<Reporter>> {
+        if let Some(threshold) = self.huge_threshold {
+            alloc.set_huge_threshold(threshold);
+        }
+        if let Some(bytes) = self.huge_warning {
+            alloc.warn_on_huge_allocation(bytes);
+        }
+        if let Some(accounting) = self.realloc_accounting {
+            alloc.set_realloc_accounting(accounting);
+        }
+        if let Some(every) = self.sampling {
+            alloc.set_sampling(every);
+        }
+        let interval = match self.report_interval {
+            Some(interval) => interval,
+            None => return Ok(None),
+        };
+        let template = match self.report_format {
+            Some(ref template) => template.clone(),
+            None => Template::parse(DEFAULT_REPORT_FORMAT).expect("default template is valid"),
+        };
+        let out: Box<dyn Write + Send> = match self.report_to {
+            ReportDestination::Stderr => Box::new(io::stderr()),
+            ReportDestination::Stdout => Box::new(io::stdout()),
+            ReportDestination::File(ref path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+        };
+        Reporter::spawn(alloc, interval, template, out).map(Some)
+    }
+}
+
+fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => (&value[..i], value[i..].trim()),
+        None => (value, "s"),
+    };
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => number / 1_000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3_600.0,
+        _ => return None,
+    };
+    if seconds > 0.0 && seconds.is_finite() {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,491 @@
+//! The atomic counters underlying a `StatsAlloc`.
+
+#[cfg(feature = "sharded")]
+use std::cell::Cell;
+#[cfg(not(target_has_atomic = "64"))]
+use std::sync::atomic::AtomicIsize;
+#[cfg(target_has_atomic = "64")]
+use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::{
+    hint,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::Stats;
+
+/// The atomic type of counters, which is 64 bits wide wherever 64-bit atomics
+/// are available so that totals do not overflow on 32-bit targets.
+#[cfg(target_has_atomic = "64")]
+pub(crate) type AtomicCount = AtomicU64;
+#[cfg(target_has_atomic = "64")]
+pub(crate) type AtomicSignedCount = AtomicI64;
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) type AtomicCount = AtomicUsize;
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) type AtomicSignedCount = AtomicIsize;
+
+/// Converts a size to the width of [`AtomicCount`].
+#[cfg(target_has_atomic = "64")]
+#[inline]
+pub(crate) fn count(n: usize) -> u64 {
+    n as u64
+}
+
+/// Converts a signed size to the width of [`AtomicSignedCount`].
+#[cfg(target_has_atomic = "64")]
+#[inline]
+pub(crate) fn signed_count(n: isize) -> i64 {
+    n as i64
+}
+
+#[cfg(target_has_atomic = "64")]
+#[inline]
+fn widen(n: u64) -> u64 {
+    n
+}
+
+#[cfg(target_has_atomic = "64")]
+#[inline]
+fn widen_signed(n: i64) -> i64 {
+    n
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+#[inline]
+pub(crate) fn count(n: usize) -> usize {
+    n
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+#[inline]
+pub(crate) fn signed_count(n: isize) -> isize {
+    n
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+#[inline]
+fn widen(n: usize) -> u64 {
+    n as u64
+}
+
+#[cfg(not(target_has_atomic = "64"))]
+#[inline]
+fn widen_signed(n: isize) -> i64 {
+    n as i64
+}
+
+/// A counter which, with the `cache-padded` feature, occupies a cache line of
+/// its own.
+///
+/// Without padding, neighbouring counters share cache lines, and updates from
+/// different cores contend even when they touch different counters.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "cache-padded", repr(align(64)))]
+pub(crate) struct Padded<T>(T);
+
+impl<T> Padded<T> {
+    const fn new(value: T) -> Self {
+        Padded(value)
+    }
+}
+
+impl<T> Deref for Padded<T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Number of stripes into which the totals are split.
+#[cfg(feature = "sharded")]
+const SHARDS: usize = 16;
+#[cfg(not(feature = "sharded"))]
+const SHARDS: usize = 1;
+
+/// Returns the index of the stripe assigned to the current thread.
+///
+/// Threads are assigned stripes in turn as they first allocate, which spreads
+/// them more evenly than hashing their identifiers.
+#[cfg(feature = "sharded")]
+#[inline]
+fn shard_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static INDEX: Cell<usize> = const { Cell::new(usize::MAX) };
+    }
+    INDEX
+        .try_with(|index| {
+            if index.get() == usize::MAX {
+                index.set(NEXT.fetch_add(1, Ordering::Relaxed) % SHARDS);
+            }
+            index.get()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(not(feature = "sharded"))]
+#[inline]
+fn shard_index() -> usize {
+    0
+}
+
+/// One stripe of the counters which are totals over every thread.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "sharded", repr(align(64)))]
+pub(crate) struct Shard {
+    pub(crate) allocations: Padded<AtomicCount>,
+    pub(crate) deallocations: Padded<AtomicCount>,
+    pub(crate) reallocations: Padded<AtomicCount>,
+    pub(crate) bytes_allocated: Padded<AtomicCount>,
+    pub(crate) bytes_deallocated: Padded<AtomicCount>,
+    pub(crate) bytes_reallocated: Padded<AtomicSignedCount>,
+    pub(crate) page_aligned_allocations: Padded<AtomicCount>,
+    pub(crate) huge_allocations: Padded<AtomicCount>,
+    pub(crate) huge_bytes_allocated: Padded<AtomicCount>,
+    pub(crate) bytes_current: Padded<AtomicSignedCount>,
+    pub(crate) live_allocations: Padded<AtomicSignedCount>,
+    pub(crate) allocation_failures: Padded<AtomicCount>,
+    pub(crate) reallocations_grow: Padded<AtomicCount>,
+    pub(crate) reallocations_shrink: Padded<AtomicCount>,
+    pub(crate) bytes_grown: Padded<AtomicCount>,
+    pub(crate) bytes_shrunk: Padded<AtomicCount>,
+    pub(crate) max_allocation_size: Padded<AtomicCount>,
+    pub(crate) allocations_align_8: Padded<AtomicCount>,
+    pub(crate) allocations_align_16: Padded<AtomicCount>,
+    pub(crate) allocations_align_32: Padded<AtomicCount>,
+    pub(crate) allocations_align_64: Padded<AtomicCount>,
+    pub(crate) allocations_align_over_64: Padded<AtomicCount>,
+    pub(crate) zero_size_allocations: Padded<AtomicCount>,
+    pub(crate) overflows: Padded<AtomicCount>,
+}
+
+impl Shard {
+    /// Adds `n` to a running total, counting any wrap-around in `overflows`.
+    #[inline]
+    pub(crate) fn add(&self, counter: &AtomicCount, n: usize) {
+        let n = count(n);
+        if counter.fetch_add(n, Ordering::SeqCst).checked_add(n).is_none() {
+            self.overflows.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // Only used to initialize the array of stripes, each as a distinct value.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NEW: Shard = Shard {
+        allocations: Padded::new(AtomicCount::new(0)),
+        deallocations: Padded::new(AtomicCount::new(0)),
+        reallocations: Padded::new(AtomicCount::new(0)),
+        bytes_allocated: Padded::new(AtomicCount::new(0)),
+        bytes_deallocated: Padded::new(AtomicCount::new(0)),
+        bytes_reallocated: Padded::new(AtomicSignedCount::new(0)),
+        page_aligned_allocations: Padded::new(AtomicCount::new(0)),
+        huge_allocations: Padded::new(AtomicCount::new(0)),
+        huge_bytes_allocated: Padded::new(AtomicCount::new(0)),
+        bytes_current: Padded::new(AtomicSignedCount::new(0)),
+        live_allocations: Padded::new(AtomicSignedCount::new(0)),
+        allocation_failures: Padded::new(AtomicCount::new(0)),
+        reallocations_grow: Padded::new(AtomicCount::new(0)),
+        reallocations_shrink: Padded::new(AtomicCount::new(0)),
+        bytes_grown: Padded::new(AtomicCount::new(0)),
+        bytes_shrunk: Padded::new(AtomicCount::new(0)),
+        max_allocation_size: Padded::new(AtomicCount::new(0)),
+        allocations_align_8: Padded::new(AtomicCount::new(0)),
+        allocations_align_16: Padded::new(AtomicCount::new(0)),
+        allocations_align_32: Padded::new(AtomicCount::new(0)),
+        allocations_align_64: Padded::new(AtomicCount::new(0)),
+        allocations_align_over_64: Padded::new(AtomicCount::new(0)),
+        zero_size_allocations: Padded::new(AtomicCount::new(0)),
+        overflows: Padded::new(AtomicCount::new(0)),
+    };
+}
+
+/// Read-only access to the live counters of a [`StatsAlloc`](crate::StatsAlloc).
+///
+/// Each method performs a single atomic load, which lets high-frequency
+/// exporters read just the counters they need without assembling a whole
+/// [`Stats`] snapshot. Values read by separate calls may reflect different
+/// points in time.
+///
+/// With the `sharded` feature, totals are split across several stripes, each
+/// updated by a subset of threads, so that threads allocating concurrently
+/// rarely contend on the same counter. Loading a total then sums the
+/// stripes. An allocation or deallocation touches only the stripe of the
+/// calling thread, so the peak of live allocations, which would need the
+/// total on every allocation, is instead raised to the total each time it is
+/// loaded. It is then approximate, missing any peak between two loads.
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    shards: [Shard; SHARDS],
+    pub(crate) peak_live_allocations: Padded<AtomicCount>,
+    updating: Padded<AtomicUsize>,
+    version: Padded<AtomicUsize>,
+}
+
+impl StatsCounters {
+    pub(crate) const fn new() -> Self {
+        StatsCounters {
+            shards: [Shard::NEW; SHARDS],
+            peak_live_allocations: Padded::new(AtomicCount::new(0)),
+            updating: Padded::new(AtomicUsize::new(0)),
+            version: Padded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Returns the stripe of counters updated by the current thread.
+    #[inline]
+    pub(crate) fn shard(&self) -> &Shard {
+        &self.shards[shard_index()]
+    }
+
+    #[inline]
+    fn sum(&self, counter: fn(&Shard) -> &AtomicCount) -> u64 {
+        self.shards.iter().fold(0, |total, shard| {
+            total.wrapping_add(widen(counter(shard).load(Ordering::SeqCst)))
+        })
+    }
+
+    #[inline]
+    fn sum_signed(&self, counter: fn(&Shard) -> &AtomicSignedCount) -> i64 {
+        self.shards.iter().fold(0, |total, shard| {
+            total.wrapping_add(widen_signed(counter(shard).load(Ordering::SeqCst)))
+        })
+    }
+
+    /// Loads the count of allocation operations.
+    #[inline]
+    pub fn allocations(&self) -> u64 {
+        self.sum(|shard| &shard.allocations)
+    }
+
+    /// Loads the count of deallocation operations.
+    #[inline]
+    pub fn deallocations(&self) -> u64 {
+        self.sum(|shard| &shard.deallocations)
+    }
+
+    /// Loads the count of reallocation operations.
+    #[inline]
+    pub fn reallocations(&self) -> u64 {
+        self.sum(|shard| &shard.reallocations)
+    }
+
+    /// Loads the total bytes requested by allocations.
+    #[inline]
+    pub fn bytes_allocated(&self) -> u64 {
+        self.sum(|shard| &shard.bytes_allocated)
+    }
+
+    /// Loads the total bytes freed by deallocations.
+    #[inline]
+    pub fn bytes_deallocated(&self) -> u64 {
+        self.sum(|shard| &shard.bytes_deallocated)
+    }
+
+    /// Loads the total of bytes requested minus bytes freed by reallocations.
+    #[inline]
+    pub fn bytes_reallocated(&self) -> i64 {
+        self.sum_signed(|shard| &shard.bytes_reallocated)
+    }
+
+    /// Loads the count of allocations requesting at least page alignment.
+    #[inline]
+    pub fn page_aligned_allocations(&self) -> u64 {
+        self.sum(|shard| &shard.page_aligned_allocations)
+    }
+
+    /// Loads the count of huge allocations.
+    #[inline]
+    pub fn huge_allocations(&self) -> u64 {
+        self.sum(|shard| &shard.huge_allocations)
+    }
+
+    /// Loads the total bytes requested by huge allocations.
+    #[inline]
+    pub fn huge_bytes_allocated(&self) -> u64 {
+        self.sum(|shard| &shard.huge_bytes_allocated)
+    }
+
+    /// Loads the bytes currently allocated.
+    #[inline]
+    pub fn bytes_current(&self) -> i64 {
+        self.sum_signed(|shard| &shard.bytes_current)
+    }
+
+    /// Loads the count of allocations currently outstanding.
+    #[inline]
+    pub fn live_allocations(&self) -> i64 {
+        self.sum_signed(|shard| &shard.live_allocations)
+    }
+
+    /// Loads the highest count of allocations outstanding at any one time.
+    ///
+    /// With the `sharded` feature, this is the highest count seen by any
+    /// load of the counters, including this one.
+    #[inline]
+    pub fn peak_live_allocations(&self) -> u64 {
+        #[cfg(feature = "sharded")]
+        {
+            let live = self.live_allocations();
+            if live > 0 {
+                self.peak_live_allocations
+                    .fetch_max(count(live as usize), Ordering::SeqCst);
+            }
+        }
+        widen(self.peak_live_allocations.load(Ordering::SeqCst))
+    }
+
+    /// Loads the count of failed allocation and reallocation requests.
+    #[inline]
+    pub fn allocation_failures(&self) -> u64 {
+        self.sum(|shard| &shard.allocation_failures)
+    }
+
+    /// Loads the count of reallocations to a larger size.
+    #[inline]
+    pub fn reallocations_grow(&self) -> u64 {
+        self.sum(|shard| &shard.reallocations_grow)
+    }
+
+    /// Loads the count of reallocations to a smaller size.
+    #[inline]
+    pub fn reallocations_shrink(&self) -> u64 {
+        self.sum(|shard| &shard.reallocations_shrink)
+    }
+
+    /// Loads the total bytes by which reallocations grew their blocks.
+    #[inline]
+    pub fn bytes_grown(&self) -> u64 {
+        self.sum(|shard| &shard.bytes_grown)
+    }
+
+    /// Loads the total bytes by which reallocations shrank their blocks.
+    #[inline]
+    pub fn bytes_shrunk(&self) -> u64 {
+        self.sum(|shard| &shard.bytes_shrunk)
+    }
+
+    /// Loads the largest size requested by any single allocation or
+    /// reallocation.
+    #[inline]
+    pub fn max_allocation_size(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| widen(shard.max_allocation_size.load(Ordering::SeqCst)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Loads the count of allocations requesting an alignment of at most 8.
+    #[inline]
+    pub fn allocations_align_8(&self) -> u64 {
+        self.sum(|shard| &shard.allocations_align_8)
+    }
+
+    /// Loads the count of allocations requesting an alignment of 16.
+    #[inline]
+    pub fn allocations_align_16(&self) -> u64 {
+        self.sum(|shard| &shard.allocations_align_16)
+    }
+
+    /// Loads the count of allocations requesting an alignment of 32.
+    #[inline]
+    pub fn allocations_align_32(&self) -> u64 {
+        self.sum(|shard| &shard.allocations_align_32)
+    }
+
+    /// Loads the count of allocations requesting an alignment of 64.
+    #[inline]
+    pub fn allocations_align_64(&self) -> u64 {
+        self.sum(|shard| &shard.allocations_align_64)
+    }
+
+    /// Loads the count of allocations requesting an alignment above 64 but below a page.
+    #[inline]
+    pub fn allocations_align_over_64(&self) -> u64 {
+        self.sum(|shard| &shard.allocations_align_over_64)
+    }
+
+    /// Loads the count of allocation requests for zero bytes.
+    #[inline]
+    pub fn zero_size_allocations(&self) -> u64 {
+        self.sum(|shard| &shard.zero_size_allocations)
+    }
+
+    /// Loads the count of times any running total wrapped around on overflow.
+    #[inline]
+    pub fn overflows(&self) -> u64 {
+        self.sum(|shard| &shard.overflows)
+    }
+
+    /// Loads every counter into `out`.
+    #[inline]
+    pub(crate) fn load_into(&self, out: &mut Stats) {
+        out.allocations = self.allocations();
+        out.deallocations = self.deallocations();
+        out.reallocations = self.reallocations();
+        out.bytes_allocated = self.bytes_allocated();
+        out.bytes_deallocated = self.bytes_deallocated();
+        out.bytes_reallocated = self.bytes_reallocated();
+        out.page_aligned_allocations = self.page_aligned_allocations();
+        out.huge_allocations = self.huge_allocations();
+        out.huge_bytes_allocated = self.huge_bytes_allocated();
+        out.bytes_current = self.bytes_current();
+        out.live_allocations = self.live_allocations();
+        out.peak_live_allocations = self.peak_live_allocations();
+        out.allocation_failures = self.allocation_failures();
+        out.reallocations_grow = self.reallocations_grow();
+        out.reallocations_shrink = self.reallocations_shrink();
+        out.bytes_grown = self.bytes_grown();
+        out.bytes_shrunk = self.bytes_shrunk();
+        out.max_allocation_size = self.max_allocation_size();
+        out.allocations_align_8 = self.allocations_align_8();
+        out.allocations_align_16 = self.allocations_align_16();
+        out.allocations_align_32 = self.allocations_align_32();
+        out.allocations_align_64 = self.allocations_align_64();
+        out.allocations_align_over_64 = self.allocations_align_over_64();
+        out.zero_size_allocations = self.zero_size_allocations();
+        out.overflows = self.overflows();
+    }
+
+    /// Marks the start of an update made up of several counters.
+    #[inline]
+    pub(crate) fn begin_update(&self) {
+        self.updating.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Marks the end of an update begun with `begin_update`.
+    #[inline]
+    pub(crate) fn end_update(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+        self.updating.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Loads every counter into `out`, retrying until no update overlapped
+    /// the loads.
+    ///
+    /// An update which began after the first check of `updating` is either
+    /// still in progress at the second check, or has completed and advanced
+    /// `version`.
+    pub(crate) fn load_consistent_into(&self, out: &mut Stats) {
+        let mut attempts = 0u32;
+        loop {
+            let version = self.version.load(Ordering::SeqCst);
+            if self.updating.load(Ordering::SeqCst) == 0 {
+                self.load_into(out);
+                if self.updating.load(Ordering::SeqCst) == 0 && self.version.load(Ordering::SeqCst) == version {
+                    return;
+                }
+            }
+            if attempts < 64 {
+                attempts += 1;
+                hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Recording of allocator statistics as CSV time series.
+
+use std::{
+    alloc::GlobalAlloc,
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{Reporter, Snapshot, StatsAlloc, StatsExporter, STATS_FIELDS};
+
+/// Exports each snapshot as a row of comma-separated values.
+///
+/// Each row holds the time of the snapshot in seconds since the Unix epoch,
+/// followed by every field of [`Stats`] in declaration order. A header row
+/// naming the columns is written first.
+///
+/// [`Stats`]: crate::Stats
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvExporter {
+    header: bool,
+}
+
+impl CsvExporter {
+    /// Creates an exporter which writes a header row before the first record.
+    pub fn new() -> Self {
+        CsvExporter { header: true }
+    }
+
+    /// Creates an exporter which writes only records, for appending to output
+    /// which already has a header.
+    pub fn without_header() -> Self {
+        CsvExporter { header: false }
+    }
+}
+
+impl Default for CsvExporter {
+    fn default() -> Self {
+        CsvExporter::new()
+    }
+}
+
+impl StatsExporter for CsvExporter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.header {
+            write!(out, "time")?;
+            for &(name, _) in STATS_FIELDS {
+                write!(out, ",{}", name)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        let time = snapshot
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        write!(out, "{}", time)?;
+        for &(_, value) in STATS_FIELDS {
+            write!(out, ",{}", value(&snapshot.stats))?;
+        }
+        writeln!(out)
+    }
+}
+
+/// A background thread which appends a row of allocator statistics to a CSV
+/// file once every interval, as a time series of a long-running process.
+///
+/// A header row is written only if the file is new or empty, so recording
+/// may resume into the file of an earlier run. The recorder stops when it is
+/// dropped.
+///
+/// ```no_run
+/// # use stats_alloc::{CsvRecorder, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::{alloc::System, time::Duration};
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let _recorder = CsvRecorder::start(GLOBAL, "soak.csv", Duration::from_secs(1)).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CsvRecorder {
+    reporter: Reporter,
+}
+
+impl CsvRecorder {
+    /// Starts appending the statistics of `alloc` to the file at `path` once
+    /// every `interval`, creating the file if it does not exist.
+    pub fn start<T, P>(alloc: &'static StatsAlloc<T>, path: P, interval: Duration) -> io::Result<Self>
+    where
+        T: GlobalAlloc + Sync,
+        P: AsRef<Path>,
+    {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let exporter = if file.metadata()?.len() == 0 {
+            CsvExporter::new()
+        } else {
+            CsvExporter::without_header()
+        };
+        let reporter = Reporter::spawn(alloc, interval, exporter, BufWriter::new(file))?;
+        Ok(CsvRecorder { reporter })
+    }
+
+    /// Stops the recorder, waiting for its last row to be written.
+    pub fn stop(self) {
+        self.reporter.stop();
+    }
+}
@@ -0,0 +1,101 @@
+//! Signed differences between arbitrary snapshots.
+
+use crate::Stats;
+
+/// The signed, field-by-field difference between two [`Stats`] snapshots, as
+/// returned by [`Stats::delta`].
+///
+/// Unlike subtracting `Stats`, which wraps, a delta is exact for any pair of
+/// snapshots: a field is negative when the first snapshot's value is smaller,
+/// e.g. because the snapshots were taken from different allocators or in the
+/// opposite order.
+///
+/// Fields may be added in minor releases, alongside those of `Stats`.
+#[derive(Clone, Copy, Default, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StatsDelta {
+    /// Change in [`Stats::allocations`]
+    pub allocations: i128,
+    /// Change in [`Stats::deallocations`]
+    pub deallocations: i128,
+    /// Change in [`Stats::reallocations`]
+    pub reallocations: i128,
+    /// Change in [`Stats::bytes_allocated`]
+    pub bytes_allocated: i128,
+    /// Change in [`Stats::bytes_deallocated`]
+    pub bytes_deallocated: i128,
+    /// Change in [`Stats::bytes_reallocated`]
+    pub bytes_reallocated: i128,
+    /// Change in [`Stats::page_aligned_allocations`]
+    pub page_aligned_allocations: i128,
+    /// Change in [`Stats::huge_allocations`]
+    pub huge_allocations: i128,
+    /// Change in [`Stats::huge_bytes_allocated`]
+    pub huge_bytes_allocated: i128,
+    /// Change in [`Stats::bytes_current`]
+    pub bytes_current: i128,
+    /// Change in [`Stats::live_allocations`]
+    pub live_allocations: i128,
+    /// Change in [`Stats::peak_live_allocations`]
+    pub peak_live_allocations: i128,
+    /// Change in [`Stats::allocation_failures`]
+    pub allocation_failures: i128,
+    /// Change in [`Stats::reallocations_grow`]
+    pub reallocations_grow: i128,
+    /// Change in [`Stats::reallocations_shrink`]
+    pub reallocations_shrink: i128,
+    /// Change in [`Stats::bytes_grown`]
+    pub bytes_grown: i128,
+    /// Change in [`Stats::bytes_shrunk`]
+    pub bytes_shrunk: i128,
+    /// Change in [`Stats::max_allocation_size`]
+    pub max_allocation_size: i128,
+    /// Change in [`Stats::allocations_align_8`]
+    pub allocations_align_8: i128,
+    /// Change in [`Stats::allocations_align_16`]
+    pub allocations_align_16: i128,
+    /// Change in [`Stats::allocations_align_32`]
+    pub allocations_align_32: i128,
+    /// Change in [`Stats::allocations_align_64`]
+    pub allocations_align_64: i128,
+    /// Change in [`Stats::allocations_align_over_64`]
+    pub allocations_align_over_64: i128,
+    /// Change in [`Stats::zero_size_allocations`]
+    pub zero_size_allocations: i128,
+    /// Change in [`Stats::overflows`]
+    pub overflows: i128,
+}
+
+impl Stats {
+    /// Returns the signed difference from `other` to `self`, that is
+    /// `self - other` for each field.
+    pub fn delta(&self, other: &Stats) -> StatsDelta {
+        StatsDelta {
+            allocations: self.allocations as i128 - other.allocations as i128,
+            deallocations: self.deallocations as i128 - other.deallocations as i128,
+            reallocations: self.reallocations as i128 - other.reallocations as i128,
+            bytes_allocated: self.bytes_allocated as i128 - other.bytes_allocated as i128,
+            bytes_deallocated: self.bytes_deallocated as i128 - other.bytes_deallocated as i128,
+            bytes_reallocated: self.bytes_reallocated as i128 - other.bytes_reallocated as i128,
+            page_aligned_allocations: self.page_aligned_allocations as i128 - other.page_aligned_allocations as i128,
+            huge_allocations: self.huge_allocations as i128 - other.huge_allocations as i128,
+            huge_bytes_allocated: self.huge_bytes_allocated as i128 - other.huge_bytes_allocated as i128,
+            bytes_current: self.bytes_current as i128 - other.bytes_current as i128,
+            live_allocations: self.live_allocations as i128 - other.live_allocations as i128,
+            peak_live_allocations: self.peak_live_allocations as i128 - other.peak_live_allocations as i128,
+            allocation_failures: self.allocation_failures as i128 - other.allocation_failures as i128,
+            reallocations_grow: self.reallocations_grow as i128 - other.reallocations_grow as i128,
+            reallocations_shrink: self.reallocations_shrink as i128 - other.reallocations_shrink as i128,
+            bytes_grown: self.bytes_grown as i128 - other.bytes_grown as i128,
+            bytes_shrunk: self.bytes_shrunk as i128 - other.bytes_shrunk as i128,
+            max_allocation_size: self.max_allocation_size as i128 - other.max_allocation_size as i128,
+            allocations_align_8: self.allocations_align_8 as i128 - other.allocations_align_8 as i128,
+            allocations_align_16: self.allocations_align_16 as i128 - other.allocations_align_16 as i128,
+            allocations_align_32: self.allocations_align_32 as i128 - other.allocations_align_32 as i128,
+            allocations_align_64: self.allocations_align_64 as i128 - other.allocations_align_64 as i128,
+            allocations_align_over_64: self.allocations_align_over_64 as i128 - other.allocations_align_over_64 as i128,
+            zero_size_allocations: self.zero_size_allocations as i128 - other.zero_size_allocations as i128,
+            overflows: self.overflows as i128 - other.overflows as i128,
+        }
+    }
+}
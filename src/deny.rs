@@ -0,0 +1,134 @@
+//! Scopes in which allocating on the current thread is an error.
+
+use std::{
+    cell::Cell,
+    io::{self, Write},
+    marker::PhantomData,
+    process, thread,
+};
+
+/// What happens when a thread allocates within a [`DenyAllocGuard`] scope.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum DenyAction {
+    /// Panic when the scope ends, reporting the first offending allocation
+    ///
+    /// The allocator itself must not unwind, so the allocation succeeds and
+    /// the panic is raised by the guard instead.
+    #[default]
+    Panic,
+    /// Write a message to standard error and abort the process immediately
+    Abort,
+}
+
+thread_local! {
+    static DENIED: Cell<Option<DenyAction>> = const { Cell::new(None) };
+    /// Size of the first allocation made while denied with `Panic`.
+    static VIOLATION: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Checks an allocation of `size` bytes against the current thread's scope.
+#[inline]
+pub(crate) fn check(size: usize) {
+    match DENIED.try_with(Cell::get) {
+        Ok(Some(DenyAction::Panic)) => {
+            let _ = VIOLATION.try_with(|violation| {
+                if violation.get().is_none() {
+                    violation.set(Some(size));
+                }
+            });
+        },
+        Ok(Some(DenyAction::Abort)) => {
+            // Formatting an integer to stderr does not allocate.
+            let _ = writeln!(
+                io::stderr(),
+                "stats_alloc: allocation of {} bytes in a no-allocation scope",
+                size
+            );
+            process::abort();
+        },
+        _ => {},
+    }
+}
+
+/// A guard which makes any allocation or reallocation on the current thread
+/// through a `StatsAlloc` an error until it is dropped.
+///
+/// Only allocations through a `StatsAlloc` are checked, whether or not it is
+/// counting. Deallocations are permitted. Guards may be nested, and each
+/// restores the previous scope when dropped.
+///
+/// ```should_panic
+/// # use stats_alloc::{DenyAllocGuard, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+/// #   if cfg!(feature = "noop") { panic!("allocations are not checked") }
+///     let _guard = DenyAllocGuard::new();
+///     let _ = vec![0u8; 16];
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DenyAllocGuard {
+    previous: Option<DenyAction>,
+    /// The guard must be dropped on the thread whose scope it changed.
+    _thread: PhantomData<*const ()>,
+}
+
+impl DenyAllocGuard {
+    /// Denies allocation on the current thread, panicking when the guard is
+    /// dropped if any allocation occurred.
+    pub fn new() -> Self {
+        DenyAllocGuard::with_action(DenyAction::default())
+    }
+
+    /// Denies allocation on the current thread, handling any allocation as
+    /// `action` specifies.
+    pub fn with_action(action: DenyAction) -> Self {
+        DenyAllocGuard {
+            previous: DENIED.with(|denied| denied.replace(Some(action))),
+            _thread: PhantomData,
+        }
+    }
+}
+
+impl Default for DenyAllocGuard {
+    fn default() -> Self {
+        DenyAllocGuard::new()
+    }
+}
+
+impl Drop for DenyAllocGuard {
+    fn drop(&mut self) {
+        let _ = DENIED.try_with(|denied| denied.set(self.previous));
+        let violation = VIOLATION.try_with(Cell::take).ok().flatten();
+        if let Some(size) = violation {
+            if !thread::panicking() {
+                // Panicking allocates, so every enclosing scope is lifted
+                // until the panic unwinds out of this guard.
+                let _restore = Restore(self.previous);
+                let _ = DENIED.try_with(|denied| denied.set(None));
+                panic!("allocation of {} bytes in a no-allocation scope", size);
+            }
+        }
+    }
+}
+
+/// Restores the scope enclosing a guard once the guard's panic unwinds.
+struct Restore(Option<DenyAction>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let _ = DENIED.try_with(|denied| denied.set(self.0));
+    }
+}
+
+/// Runs `f`, panicking afterwards if it allocated through a `StatsAlloc` on
+/// the current thread.
+///
+/// See [`DenyAllocGuard`] to abort instead or to guard a scope directly.
+pub fn assert_no_alloc<R, F: FnOnce() -> R>(f: F) -> R {
+    let _guard = DenyAllocGuard::new();
+    f()
+}
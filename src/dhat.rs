@@ -0,0 +1,134 @@
+//! Output of call site profiles in the JSON format of DHAT's viewer.
+
+use std::{
+    alloc::GlobalAlloc,
+    collections::HashMap,
+    env,
+    io::{self, Write},
+    process,
+};
+
+use crate::{schema::write_json_str, stack::Resolver, StatsAlloc};
+
+/// The table of distinct frames referred to by each program point.
+struct FrameTable {
+    entries: Vec<String>,
+    indices: HashMap<String, usize>,
+    resolver: Resolver,
+}
+
+impl FrameTable {
+    fn new() -> Self {
+        FrameTable {
+            // The viewer expects the first entry to be the root of the tree.
+            entries: vec!["[root]".to_owned()],
+            indices: HashMap::new(),
+            resolver: Resolver::default(),
+        }
+    }
+
+    /// Returns the entries for a call stack, innermost first, without the
+    /// frames of the allocator itself.
+    fn stack(&mut self, frames: &[usize]) -> Vec<usize> {
+        let FrameTable {
+            entries,
+            indices,
+            resolver,
+        } = self;
+        resolver
+            .symbols(frames)
+            .into_iter()
+            .map(|(ip, (name, location))| {
+                let name = name.as_deref().unwrap_or("???");
+                let entry = match location {
+                    Some((file, line)) => format!("{:#x}: {} ({}:{})", ip, name, file, line),
+                    None => format!("{:#x}: {}", ip, name),
+                };
+                *indices.entry(entry).or_insert_with_key(|entry| {
+                    entries.push(entry.clone());
+                    entries.len() - 1
+                })
+            })
+            .collect()
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Writes the allocations attributed to call sites as a heap profile in
+    /// the JSON format of Valgrind's DHAT tool, for exploring with its
+    /// viewer, `dh_view.html`.
+    ///
+    /// Each call site is a program point, and times are in microseconds since
+    /// [call site tracking] was enabled. The profile has no access counts.
+    /// Symbols are resolved while writing, which may take some time for a
+    /// large profile.
+    ///
+    /// ```no_run
+    /// # use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
+    /// # use std::{alloc::System, fs::File};
+    /// #[global_allocator]
+    /// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+    ///
+    /// fn main() {
+    ///     GLOBAL.set_call_site_tracking(true);
+    ///     // ...
+    ///     let mut out = File::create("dhat-heap.json").unwrap();
+    ///     GLOBAL.write_dhat(&mut out).unwrap();
+    /// }
+    /// ```
+    ///
+    /// [call site tracking]: StatsAlloc::set_call_site_tracking
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn write_dhat(&self, out: &mut dyn Write) -> io::Result<()> {
+        let profile = self.sites.profile();
+        let mut frames = FrameTable::new();
+        let cmd: Vec<_> = env::args().collect();
+        writeln!(out, "{{\"dhatFileVersion\":2")?;
+        writeln!(out, ",\"mode\":\"rust-heap\"")?;
+        writeln!(out, ",\"verb\":\"Allocated\"")?;
+        writeln!(out, ",\"bklt\":true")?;
+        writeln!(out, ",\"bkacc\":false")?;
+        writeln!(out, ",\"tu\":\"µs\"")?;
+        writeln!(out, ",\"Mtu\":\"s\"")?;
+        writeln!(out, ",\"tuth\":10")?;
+        write!(out, ",\"cmd\":")?;
+        write_json_str(out, &cmd.join(" "))?;
+        writeln!(out)?;
+        writeln!(out, ",\"pid\":{}", process::id())?;
+        writeln!(out, ",\"tg\":{}", profile.peaked.saturating_sub(profile.started))?;
+        writeln!(out, ",\"te\":{}", profile.ended.saturating_sub(profile.started))?;
+        writeln!(out, ",\"pps\":")?;
+        for (i, site) in profile.sites.iter().enumerate() {
+            write!(
+                out,
+                "{}{{\"tb\":{},\"tbk\":{},\"tl\":{},\"mb\":{},\"mbk\":{},\"gb\":{},\"gbk\":{},\"eb\":{},\"ebk\":{},\"fs\":[",
+                if i == 0 { "[" } else { "," },
+                site.bytes_allocated,
+                site.allocations,
+                site.total_lifetime(profile.ended),
+                site.peak_bytes,
+                site.peak_live_allocations,
+                site.global_peak_bytes,
+                site.global_peak_live_allocations,
+                site.bytes_current,
+                site.live_allocations,
+            )?;
+            for (j, index) in frames.stack(site.frames()).into_iter().enumerate() {
+                write!(out, "{}{}", if j == 0 { "" } else { "," }, index)?;
+            }
+            writeln!(out, "]}}")?;
+        }
+        if profile.sites.is_empty() {
+            write!(out, "[")?;
+        }
+        writeln!(out, "]")?;
+        writeln!(out, ",\"ftbl\":")?;
+        for (i, entry) in frames.entries.iter().enumerate() {
+            write!(out, "{}", if i == 0 { "[" } else { "," })?;
+            write_json_str(out, entry)?;
+            writeln!(out)?;
+        }
+        writeln!(out, "]")?;
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,267 @@
+//! A common interface for the formats in which statistics are exported.
+
+use std::{
+    io::{self, Write},
+    time::{Duration, SystemTime},
+};
+
+use crate::{schema::write_counters, Stats, Template, STATS_FIELDS};
+
+/// A snapshot of allocator statistics, as handed to a [`StatsExporter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// The statistics at the time of the snapshot
+    pub stats: Stats,
+    /// The statistics at the time of the previous snapshot
+    pub previous: Stats,
+    /// Time elapsed since the previous snapshot
+    pub elapsed: Duration,
+    /// Wall-clock time at which the snapshot was taken
+    pub time: SystemTime,
+}
+
+impl Snapshot {
+    /// Creates a snapshot taken now, with no earlier snapshot to compare to.
+    pub fn new(stats: Stats) -> Self {
+        Snapshot {
+            stats,
+            previous: stats,
+            elapsed: Duration::from_secs(0),
+            time: SystemTime::now(),
+        }
+    }
+
+    /// Returns the change in statistics since the previous snapshot.
+    pub fn change(&self) -> Stats {
+        self.stats - self.previous
+    }
+}
+
+/// A format which turns snapshots of allocator statistics into records.
+///
+/// Every built-in output format implements this trait, and a [`Reporter`]
+/// can periodically export through any of them, including user-defined
+/// formats.
+///
+/// [`Reporter`]: crate::Reporter
+pub trait StatsExporter {
+    /// Writes anything which must precede the first record, such as a header.
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let _ = out;
+        Ok(())
+    }
+
+    /// Writes the record for a single snapshot.
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()>;
+
+    /// Writes anything which must follow the last record.
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let _ = out;
+        Ok(())
+    }
+}
+
+impl<E: StatsExporter + ?Sized> StatsExporter for &mut E {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        (**self).begin(out)
+    }
+
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        (**self).export(out, snapshot)
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        (**self).finish(out)
+    }
+}
+
+impl<E: StatsExporter + ?Sized> StatsExporter for Box<E> {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        (**self).begin(out)
+    }
+
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        (**self).export(out, snapshot)
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        (**self).finish(out)
+    }
+}
+
+/// Human-readable output: one line per snapshot, rendered from the template.
+impl StatsExporter for Template {
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        writeln!(
+            out,
+            "{}",
+            self.render(&snapshot.stats, &snapshot.previous, snapshot.elapsed)
+        )
+    }
+}
+
+/// Exports each snapshot as a single line of JSON.
+///
+/// Every line is an object of the form
+/// `{"time":1700000000.25,"elapsed":1.0,"counters":{...}}`, where `time` is
+/// seconds since the Unix epoch, `elapsed` is seconds since the previous
+/// snapshot, and `counters` holds every field of [`Stats`] as in the
+/// [results format](crate::RESULTS_SCHEMA).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct JsonLinesExporter;
+
+impl StatsExporter for JsonLinesExporter {
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        let time = snapshot
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        write!(
+            out,
+            "{{\"time\":{},\"elapsed\":{},\"counters\":",
+            time,
+            snapshot.elapsed.as_secs_f64()
+        )?;
+        write_counters(out, &snapshot.stats)?;
+        out.write_all(b"}\n")
+    }
+}
+
+/// Exports snapshots in the Prometheus text exposition format.
+///
+/// Each snapshot is written as a complete exposition, with every field of
+/// [`Stats`] as a metric named with the configured prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrometheusExporter {
+    prefix: String,
+}
+
+impl PrometheusExporter {
+    /// Creates an exporter whose metric names begin with `prefix` followed by
+    /// an underscore.
+    pub fn new(prefix: &str) -> Self {
+        PrometheusExporter {
+            prefix: prefix.to_owned(),
+        }
+    }
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> Self {
+        PrometheusExporter::new("stats_alloc")
+    }
+}
+
+impl StatsExporter for PrometheusExporter {
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        for &(name, value) in STATS_FIELDS {
+            let kind = if is_gauge(name) { "gauge" } else { "counter" };
+            writeln!(out, "# TYPE {}_{} {}", self.prefix, name, kind)?;
+            writeln!(out, "{}_{} {}", self.prefix, name, value(&snapshot.stats))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if the named `Stats` field may decrease, and so is exported
+/// as a gauge rather than a counter.
+pub(crate) fn is_gauge(name: &str) -> bool {
+    matches!(
+        name,
+        "bytes_reallocated" | "bytes_current" | "live_allocations" | "peak_live_allocations" | "max_allocation_size"
+    )
+}
+
+/// Exports each snapshot as a single line of the InfluxDB line protocol.
+///
+/// Each line is a point in the configured measurement, tagged with any tags
+/// added to the exporter, with every field of [`Stats`] and the time of the
+/// snapshot as a timestamp in nanoseconds. Unsigned fields are written as
+/// unsigned integers, which InfluxDB supports from version 1.8, and signed
+/// fields such as `bytes_current` as integers:
+///
+/// ```text
+/// stats_alloc,thread=main allocations=12u,deallocations=10u,... 1700000000000000000
+/// ```
+///
+/// Writing through a [`Reporter`] to a file or socket appends a point once
+/// every interval.
+///
+/// ```
+/// # use stats_alloc::{InfluxExporter, Snapshot, StatsExporter, INSTRUMENTED_SYSTEM};
+/// # use std::thread;
+/// let mut exporter = InfluxExporter::new("stats_alloc")
+///     .tag("thread", thread::current().name().unwrap_or("unnamed"))
+///     .tag("region", "parse");
+/// let mut out = Vec::new();
+/// exporter.export(&mut out, &Snapshot::new(INSTRUMENTED_SYSTEM.stats())).unwrap();
+/// ```
+///
+/// [`Reporter`]: crate::Reporter
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfluxExporter {
+    measurement: String,
+    tags: String,
+}
+
+impl InfluxExporter {
+    /// Creates an exporter writing points to `measurement`, without tags.
+    pub fn new(measurement: &str) -> Self {
+        InfluxExporter {
+            measurement: escape_influx(measurement, &[',', ' ']),
+            tags: String::new(),
+        }
+    }
+
+    /// Adds a tag to every point written by the exporter.
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.push(',');
+        self.tags.push_str(&escape_influx(key, &[',', '=', ' ']));
+        self.tags.push('=');
+        self.tags.push_str(&escape_influx(value, &[',', '=', ' ']));
+        self
+    }
+}
+
+impl Default for InfluxExporter {
+    fn default() -> Self {
+        InfluxExporter::new("stats_alloc")
+    }
+}
+
+impl StatsExporter for InfluxExporter {
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        write!(out, "{}{}", self.measurement, self.tags)?;
+        for (i, &(name, value)) in STATS_FIELDS.iter().enumerate() {
+            let separator = if i == 0 { ' ' } else { ',' };
+            let suffix = if is_signed(name) { 'i' } else { 'u' };
+            write!(out, "{}{}={}{}", separator, name, value(&snapshot.stats), suffix)?;
+        }
+        let time = snapshot
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        writeln!(out, " {}", time)
+    }
+}
+
+/// Returns `true` if the named `Stats` field is signed.
+fn is_signed(name: &str) -> bool {
+    matches!(name, "bytes_reallocated" | "bytes_current" | "live_allocations")
+}
+
+/// Escapes `special` characters with a backslash, as the line protocol
+/// requires of measurement names and tags. Other characters, backslashes
+/// included, are written as they are.
+fn escape_influx(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
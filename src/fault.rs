@@ -0,0 +1,127 @@
+//! Fault injection confined to a scope on the current thread.
+
+use std::{cell::Cell, marker::PhantomData};
+
+/// Returns the threshold below which a hashed value fails a request, for a
+/// failure `probability`.
+pub(crate) fn threshold(probability: f64) -> u32 {
+    (probability.clamp(0.0, 1.0) * 4_294_967_296.0).min(u32::MAX as f64) as u32
+}
+
+/// Returns `true` if the request numbered `n` in a sequence fails with the
+/// given `threshold`.
+#[inline]
+pub(crate) fn fails(threshold: u32, n: u32) -> bool {
+    // The "lowbias32" integer hash, applied to a Weyl sequence.
+    let mut x = n;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    threshold == u32::MAX || x < threshold
+}
+
+/// Step between successive values of the sequence.
+pub(crate) const GAMMA: u32 = 0x9e37_79b9;
+
+/// Folds a 64-bit seed into the 32-bit sequence state.
+pub(crate) fn fold_seed(seed: u64) -> u32 {
+    (seed ^ (seed >> 32)) as u32
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Plan {
+    countdown: usize,
+    threshold: u32,
+    state: u32,
+}
+
+thread_local! {
+    static PLAN: Cell<Option<Plan>> = const { Cell::new(None) };
+}
+
+/// Returns `true` if the current thread's scope fails this request.
+#[inline]
+pub(crate) fn inject() -> bool {
+    let mut plan = match PLAN.try_with(Cell::get) {
+        Ok(Some(plan)) => plan,
+        _ => return false,
+    };
+    let countdown = plan.countdown != 0 && {
+        plan.countdown -= 1;
+        plan.countdown == 0
+    };
+    let random = plan.threshold != 0 && {
+        let n = plan.state;
+        plan.state = plan.state.wrapping_add(GAMMA);
+        fails(plan.threshold, n)
+    };
+    let _ = PLAN.try_with(|cell| cell.set(Some(plan)));
+    countdown || random
+}
+
+/// A guard which fails allocation requests made through any `StatsAlloc` on
+/// the current thread until it is dropped, leaving other threads unaffected.
+///
+/// Failed requests return null without reaching the backing allocator, and
+/// are counted in `allocation_failures`. Guards may be nested, and each
+/// restores the enclosing scope when dropped.
+///
+/// ```
+/// # use stats_alloc::{FaultGuard, StatsAlloc};
+/// # use std::alloc::{GlobalAlloc, Layout};
+/// # if cfg!(feature = "noop") { return; }
+/// let alloc = StatsAlloc::system();
+/// let layout = Layout::new::<u64>();
+/// {
+///     let _faults = FaultGuard::fail_nth(1);
+///     assert!(unsafe { alloc.alloc(layout) }.is_null());
+/// }
+/// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+/// ```
+#[derive(Debug)]
+pub struct FaultGuard {
+    previous: Option<Plan>,
+    /// The guard must be dropped on the thread whose scope it changed.
+    _thread: PhantomData<*const ()>,
+}
+
+impl FaultGuard {
+    /// Fails the `n`th allocation, zeroed allocation or reallocation made on
+    /// this thread within the scope, with `1` failing the very next one.
+    pub fn fail_nth(n: usize) -> Self {
+        FaultGuard::enter(Plan {
+            countdown: n,
+            ..Plan::default()
+        })
+    }
+
+    /// Fails each request made on this thread within the scope with the given
+    /// `probability`, drawn from a sequence starting from `seed`.
+    ///
+    /// See [`StatsAlloc::set_failure_probability`] for the allocator-wide
+    /// equivalent.
+    ///
+    /// [`StatsAlloc::set_failure_probability`]: crate::StatsAlloc::set_failure_probability
+    pub fn fail_with_probability(probability: f64, seed: u64) -> Self {
+        FaultGuard::enter(Plan {
+            threshold: threshold(probability),
+            state: fold_seed(seed),
+            ..Plan::default()
+        })
+    }
+
+    fn enter(plan: Plan) -> Self {
+        FaultGuard {
+            previous: PLAN.with(|cell| cell.replace(Some(plan))),
+            _thread: PhantomData,
+        }
+    }
+}
+
+impl Drop for FaultGuard {
+    fn drop(&mut self) {
+        let _ = PLAN.try_with(|cell| cell.set(self.previous));
+    }
+}
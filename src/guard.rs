@@ -0,0 +1,88 @@
+//! Scope guards which check a region for leaks when it ends.
+
+use std::{alloc::GlobalAlloc, thread};
+
+use crate::{Region, StatsAlloc};
+
+/// What a [`RegionGuard`] does when its region was not balanced.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum LeakAction {
+    /// Panic, failing the enclosing test
+    #[default]
+    Panic,
+    /// Write a message to standard error and continue
+    Log,
+}
+
+/// A guard over a [`Region`] which checks, when dropped, that every
+/// allocation made within the region was released.
+///
+/// The region covers every thread using the allocator, so allocations by
+/// other threads while the guard is held are also checked. Nothing is checked
+/// if the guard is dropped while already panicking.
+///
+/// ```
+/// # use stats_alloc::{Region, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let _guard = Region::enter_checked(GLOBAL);
+///     let buffer = vec![0u8; 64];
+///     drop(buffer);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RegionGuard<'a, T: GlobalAlloc + 'a> {
+    region: Region<'a, T>,
+    action: LeakAction,
+}
+
+impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
+    /// Creates a region which panics when it is dropped unless every
+    /// allocation made within it was released.
+    ///
+    /// See [`RegionGuard::on_leak`] to log instead.
+    pub fn enter_checked(alloc: &'a StatsAlloc<T>) -> RegionGuard<'a, T> {
+        RegionGuard {
+            region: Region::new(alloc),
+            action: LeakAction::default(),
+        }
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> RegionGuard<'a, T> {
+    /// Sets what happens if the region is not balanced when dropped.
+    pub fn on_leak(mut self, action: LeakAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Returns the region being checked.
+    pub fn region(&self) -> &Region<'a, T> {
+        &self.region
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> Drop for RegionGuard<'a, T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+        let change = self.region.change();
+        if change.is_balanced() {
+            return;
+        }
+        let message = format!(
+            "region leaked {} allocations and {} bytes: {}",
+            change.outstanding_allocations(),
+            change.leaked_bytes(),
+            change
+        );
+        match self.action {
+            LeakAction::Panic => panic!("{}", message),
+            LeakAction::Log => eprintln!("stats_alloc: {}", message),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! An adapter for custom test harnesses built with `libtest-mimic`, which
+//! measures every test and enforces per-test allocation limits.
+
+use std::{
+    alloc::GlobalAlloc,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use libtest_mimic::{Arguments, Conclusion, Failed, Trial};
+
+use crate::{Region, Stats, StatsAlloc};
+
+/// A test to be run under allocation measurement by [`run_tests`].
+pub struct AllocTest {
+    name: String,
+    max_allocations: Option<u64>,
+    max_bytes_allocated: Option<u64>,
+    ignored: bool,
+    runner: Box<dyn FnOnce() -> Result<(), Failed> + Send>,
+}
+
+impl AllocTest {
+    /// Creates a test with the given name and body, without any limits.
+    pub fn new<F>(name: &str, runner: F) -> Self
+    where
+        F: FnOnce() -> Result<(), Failed> + Send + 'static,
+    {
+        AllocTest {
+            name: name.to_owned(),
+            max_allocations: None,
+            max_bytes_allocated: None,
+            ignored: false,
+            runner: Box::new(runner),
+        }
+    }
+
+    /// Fails the test if it makes more than `limit` allocations.
+    pub fn max_allocations(mut self, limit: u64) -> Self {
+        self.max_allocations = Some(limit);
+        self
+    }
+
+    /// Fails the test if it requests more than `limit` bytes in total.
+    pub fn max_bytes_allocated(mut self, limit: u64) -> Self {
+        self.max_bytes_allocated = Some(limit);
+        self
+    }
+
+    /// Marks the test as ignored unless ignored tests are requested.
+    pub fn ignored(mut self, ignored: bool) -> Self {
+        self.ignored = ignored;
+        self
+    }
+}
+
+impl fmt::Debug for AllocTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AllocTest")
+            .field("name", &self.name)
+            .field("max_allocations", &self.max_allocations)
+            .field("max_bytes_allocated", &self.max_bytes_allocated)
+            .field("ignored", &self.ignored)
+            .finish()
+    }
+}
+
+/// Runs the given tests with `libtest-mimic`, measuring each against `alloc`
+/// and printing a summary of every test's allocations once all have run.
+///
+/// Tests are run one at a time, as measurements are taken from the shared
+/// allocator and would otherwise include the allocations of concurrent tests.
+///
+/// ```no_run
+/// # extern crate libtest_mimic;
+/// # extern crate stats_alloc;
+/// use libtest_mimic::Arguments;
+/// use stats_alloc::{run_tests, AllocTest, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let tests = vec![AllocTest::new("no_allocations", || Ok(())).max_allocations(0)];
+///     run_tests(GLOBAL, &Arguments::from_args(), tests).exit();
+/// }
+/// ```
+pub fn run_tests<T: GlobalAlloc + Sync>(
+    alloc: &'static StatsAlloc<T>,
+    args: &Arguments,
+    tests: Vec<AllocTest>,
+) -> Conclusion {
+    let mut args = args.clone();
+    args.test_threads = Some(1);
+
+    let results = Arc::new(Mutex::new(Vec::with_capacity(tests.len())));
+    let trials = tests
+        .into_iter()
+        .map(|test| {
+            let results = Arc::clone(&results);
+            let AllocTest {
+                name,
+                max_allocations,
+                max_bytes_allocated,
+                ignored,
+                runner,
+            } = test;
+            Trial::test(name.clone(), move || {
+                let region = Region::new(alloc);
+                let outcome = runner();
+                let change = region.change();
+                results
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push((name.clone(), change));
+                outcome?;
+                check_limits(&name, max_allocations, max_bytes_allocated, &change)
+            })
+            .with_ignored_flag(ignored)
+        })
+        .collect();
+
+    let conclusion = libtest_mimic::run(&args, trials);
+
+    let results = results.lock().unwrap_or_else(|e| e.into_inner());
+    if !results.is_empty() {
+        println!("allocation summary:");
+        for (name, change) in results.iter() {
+            println!(
+                "    {}: {} allocations, {} bytes allocated, {} deallocations, {} bytes deallocated",
+                name, change.allocations, change.bytes_allocated, change.deallocations, change.bytes_deallocated
+            );
+        }
+        println!();
+    }
+    conclusion
+}
+
+fn check_limits(
+    name: &str,
+    max_allocations: Option<u64>,
+    max_bytes_allocated: Option<u64>,
+    change: &Stats,
+) -> Result<(), Failed> {
+    if let Some(limit) = max_allocations {
+        if change.allocations > limit {
+            return Err(format!(
+                "{} made {} allocations, exceeding its limit of {}",
+                name, change.allocations, limit
+            )
+            .into());
+        }
+    }
+    if let Some(limit) = max_bytes_allocated {
+        if change.bytes_allocated > limit {
+            return Err(format!(
+                "{} allocated {} bytes, exceeding its limit of {}",
+                name, change.bytes_allocated, limit
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
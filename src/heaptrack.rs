@@ -0,0 +1,226 @@
+//! Output of allocation events in the trace format of heaptrack.
+
+use std::{
+    alloc::GlobalAlloc,
+    collections::HashMap,
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    site::{ignoring, now, Event},
+    stack::{in_allocator, resolve},
+    Reporter, Snapshot, StatsAlloc, StatsExporter,
+};
+
+/// Version of heaptrack whose output is written, as its major, minor and
+/// patch numbers in successive bytes.
+const HEAPTRACK_VERSION: u32 = 0x01_02_00;
+
+/// Version of the trace format written.
+const FILE_FORMAT_VERSION: u32 = 2;
+
+/// Writes the events logged by an allocator in heaptrack's trace format,
+/// interning the strings, instruction pointers, stacks and allocation kinds
+/// which the events refer to as they are first needed.
+struct HeaptrackWriter<T: GlobalAlloc + 'static> {
+    alloc: &'static StatsAlloc<T>,
+    started: u64,
+    /// Time of the last timestamp written, in milliseconds
+    time: u64,
+    strings: HashMap<String, usize>,
+    /// Index of each instruction pointer, and whether it lies within the
+    /// allocator
+    ips: HashMap<usize, (usize, bool)>,
+    /// Index of each node of the tree of stacks, by instruction pointer and
+    /// parent node
+    nodes: HashMap<(usize, usize), usize>,
+    /// Node of each stack, keyed by its innermost frames
+    stacks: HashMap<Vec<usize>, usize>,
+    /// Index of each allocation kind, by size and node
+    kinds: HashMap<(usize, usize), usize>,
+}
+
+impl<T: GlobalAlloc> HeaptrackWriter<T> {
+    /// Returns the index of `s`, writing it to the string table if needed.
+    fn string(&mut self, out: &mut dyn Write, s: &str) -> io::Result<usize> {
+        if let Some(&index) = self.strings.get(s) {
+            return Ok(index);
+        }
+        let index = self.strings.len() + 1;
+        writeln!(out, "s {}", s.replace('\n', " "))?;
+        self.strings.insert(s.to_owned(), index);
+        Ok(index)
+    }
+
+    /// Returns the index of `ip` and whether it lies within the allocator,
+    /// resolving and writing it if needed.
+    fn ip(&mut self, out: &mut dyn Write, ip: usize) -> io::Result<(usize, bool)> {
+        if let Some(&resolved) = self.ips.get(&ip) {
+            return Ok(resolved);
+        }
+        let symbols = resolve(ip);
+        let module = env::current_exe()
+            .map(|exe| exe.display().to_string())
+            .unwrap_or_default();
+        let module = self.string(out, &module)?;
+        let mut line = format!("i {:x} {:x}", ip, module);
+        // The innermost inlined function comes first, and the function whose
+        // code contains the instruction last.
+        for (name, location) in &symbols {
+            let function = self.string(out, name.as_deref().unwrap_or("<unknown>"))?;
+            let (file, lineno) = match location {
+                Some((file, lineno)) => (self.string(out, file)?, *lineno),
+                None => (0, 0),
+            };
+            line.push_str(&format!(" {:x} {:x} {:x}", function, file, lineno));
+        }
+        writeln!(out, "{}", line)?;
+        let resolved = (self.ips.len() + 1, in_allocator(&symbols));
+        self.ips.insert(ip, resolved);
+        Ok(resolved)
+    }
+
+    /// Returns the node of the stack with the innermost `frames`, writing
+    /// the nodes not yet written from the outermost frame inwards.
+    fn stack(&mut self, out: &mut dyn Write, frames: &[usize]) -> io::Result<usize> {
+        if let Some(&node) = self.stacks.get(frames) {
+            return Ok(node);
+        }
+        let mut ips = Vec::with_capacity(frames.len());
+        for &frame in frames {
+            ips.push(self.ip(out, frame)?);
+        }
+        let skipped = ips.iter().take_while(|&&(_, allocator)| allocator).count();
+        let kept = if skipped == ips.len() {
+            &ips[..]
+        } else {
+            &ips[skipped..]
+        };
+        let mut node = 0;
+        for &(ip, _) in kept.iter().rev() {
+            let next = self.nodes.len() + 1;
+            node = match self.nodes.get(&(ip, node)) {
+                Some(&existing) => existing,
+                None => {
+                    writeln!(out, "t {:x} {:x}", ip, node)?;
+                    self.nodes.insert((ip, node), next);
+                    next
+                },
+            };
+        }
+        self.stacks.insert(frames.to_vec(), node);
+        Ok(node)
+    }
+
+    fn event(&mut self, out: &mut dyn Write, event: &Event) -> io::Result<()> {
+        let node = self.stack(out, event.frames())?;
+        let next = self.kinds.len();
+        let kind = match self.kinds.get(&(event.size, node)) {
+            Some(&kind) => kind,
+            None => {
+                writeln!(out, "a {:x} {:x}", event.size, node)?;
+                self.kinds.insert((event.size, node), next);
+                next
+            },
+        };
+        let time = event.time.saturating_sub(self.started) / 1_000;
+        if time > self.time {
+            writeln!(out, "c {:x}", time)?;
+            self.time = time;
+        }
+        writeln!(out, "{} {:x}", if event.freed { '-' } else { '+' }, kind)
+    }
+
+    /// Writes the events logged since the last call.
+    fn drain(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let events = self.alloc.sites.take_events();
+        let result = ignoring(|| events.as_slice().iter().try_for_each(|event| self.event(out, event)));
+        events.release();
+        result
+    }
+}
+
+impl<T: GlobalAlloc> StatsExporter for HeaptrackWriter<T> {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let cmd: Vec<_> = env::args().collect();
+        writeln!(out, "v {:x} {:x}", HEAPTRACK_VERSION, FILE_FORMAT_VERSION)?;
+        writeln!(out, "X {}", cmd.join(" "))
+    }
+
+    fn export(&mut self, out: &mut dyn Write, _: &Snapshot) -> io::Result<()> {
+        self.drain(out)
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        self.alloc.sites.set_logging(false);
+        self.drain(out)
+    }
+}
+
+/// A background thread which writes every allocation and release to a file
+/// in the trace format of heaptrack, for analysis with `heaptrack_gui` or
+/// `heaptrack_print` on any platform, without preloading heaptrack's own
+/// library.
+///
+/// The recorder enables [call site tracking] and identifies each stack by
+/// its innermost frames alone, without those of the allocator. A
+/// reallocation is written as the release of the block and the allocation of
+/// one of the new size, attributed to the site which first allocated it.
+/// Events are kept in memory between writes, at about a hundred bytes each,
+/// so the interval should be short for a program which allocates rapidly.
+/// The recorder stops when it is dropped, and allocations made by its own
+/// thread are not recorded.
+///
+/// ```no_run
+/// # use stats_alloc::{HeaptrackRecorder, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::{alloc::System, time::Duration};
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let recorder = HeaptrackRecorder::start(GLOBAL, "heaptrack.app", Duration::from_millis(100)).unwrap();
+///     // ...
+///     recorder.stop();
+/// }
+/// ```
+///
+/// [call site tracking]: StatsAlloc::set_call_site_tracking
+#[derive(Debug)]
+pub struct HeaptrackRecorder {
+    reporter: Reporter,
+}
+
+impl HeaptrackRecorder {
+    /// Starts recording the allocations of `alloc` to a new file at `path`,
+    /// writing those made so far once every `interval`.
+    pub fn start<T, P>(alloc: &'static StatsAlloc<T>, path: P, interval: Duration) -> io::Result<Self>
+    where
+        T: GlobalAlloc + Sync,
+        P: AsRef<Path>,
+    {
+        let file = File::create(path)?;
+        alloc.set_call_site_tracking(true);
+        alloc.sites.set_logging(true);
+        let writer = HeaptrackWriter {
+            alloc,
+            started: now(),
+            time: 0,
+            strings: HashMap::new(),
+            ips: HashMap::new(),
+            nodes: HashMap::new(),
+            stacks: HashMap::new(),
+            kinds: HashMap::new(),
+        };
+        let reporter = Reporter::spawn(alloc, interval, writer, BufWriter::new(file))?;
+        Ok(HeaptrackRecorder { reporter })
+    }
+
+    /// Stops the recorder, waiting for the last events to be written.
+    pub fn stop(self) {
+        self.reporter.stop();
+    }
+}
@@ -0,0 +1,126 @@
+//! Callbacks run by the allocator after each operation it counts.
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    ptr,
+    sync::atomic::Ordering,
+};
+
+use crate::{AllocEvent, EventKind, StatsAlloc, StatsCounters};
+
+/// Callbacks which a [`StatsAlloc`] runs after counting each successful
+/// operation, for building policies such as logging, aborting on a huge
+/// allocation or exporting to a custom sink into the allocator itself.
+///
+/// Each callback receives the layout of the operation and the allocator's
+/// counters, which already include it. Every method does nothing by default.
+///
+/// Callbacks run on the allocating thread, within the allocator, so they
+/// should be quick and must not panic. They may allocate: operations made
+/// by a callback are counted as usual but do not run the hook again.
+///
+/// ```
+/// # use stats_alloc::{AllocHook, StatsAlloc, StatsCounters};
+/// # use std::alloc::{GlobalAlloc, Layout};
+/// struct AbortOnHuge;
+///
+/// impl AllocHook for AbortOnHuge {
+///     fn on_alloc(&self, layout: Layout, _: &StatsCounters) {
+///         if layout.size() > 1 << 30 {
+///             eprintln!("refusing to allocate {} bytes", layout.size());
+///             std::process::abort();
+///         }
+///     }
+/// }
+///
+/// static HOOK: AbortOnHuge = AbortOnHuge;
+///
+/// let alloc = StatsAlloc::system();
+/// alloc.set_hook(&HOOK);
+/// let layout = Layout::new::<u64>();
+/// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+/// ```
+pub trait AllocHook: Sync {
+    /// Runs after an allocation or zeroed allocation of `layout`.
+    fn on_alloc(&self, layout: Layout, counters: &StatsCounters) {
+        let _ = (layout, counters);
+    }
+
+    /// Runs after a deallocation of `layout`.
+    fn on_dealloc(&self, layout: Layout, counters: &StatsCounters) {
+        let _ = (layout, counters);
+    }
+
+    /// Runs after a block of `layout` is reallocated to `new_size` bytes.
+    fn on_realloc(&self, layout: Layout, new_size: usize, counters: &StatsCounters) {
+        let _ = (layout, new_size, counters);
+    }
+}
+
+thread_local! {
+    /// Whether a hook or threshold callback is running on the current thread.
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Runs `hook` after each successful allocation, deallocation and
+    /// reallocation counted from now on, in place of any hook set before.
+    ///
+    /// Hooks do not run while counting is paused or compiled out. A hook
+    /// which is replaced may still be running on other threads, so a
+    /// reference to every hook set is kept for the life of the program.
+    pub fn set_hook(&self, hook: &'static dyn AllocHook) {
+        let hook = Box::into_raw(Box::new(hook));
+        self.hook.store(hook, Ordering::Release);
+    }
+
+    /// Stops running the hook set with [`set_hook`](StatsAlloc::set_hook).
+    pub fn clear_hook(&self) {
+        self.hook.store(ptr::null_mut(), Ordering::Release);
+    }
+
+    /// Runs the hook, the subscribers, the huge allocation warning, the slow
+    /// call callback and the threshold after a successful operation, unless
+    /// counting is paused.
+    #[inline]
+    pub(crate) fn notify(&self, event: AllocEvent) {
+        if self.is_paused() {
+            return;
+        }
+        let hook = self.hook.load(Ordering::Acquire);
+        if !hook.is_null()
+            || !self.subscribers.load(Ordering::Relaxed).is_null()
+            || !self.huge_warning.load(Ordering::Relaxed).is_null()
+            || self.latency.has_slow_callback()
+        {
+            exclusive(|| {
+                // Hooks are never freed once set, so the pointer remains
+                // valid.
+                if let Some(hook) = unsafe { hook.as_ref() } {
+                    match event.kind {
+                        EventKind::Alloc => hook.on_alloc(event.layout, &self.counters),
+                        EventKind::Dealloc => hook.on_dealloc(event.layout, &self.counters),
+                        EventKind::Realloc => hook.on_realloc(event.layout, event.new_size, &self.counters),
+                    }
+                }
+                self.publish(&event);
+                self.check_huge_allocation(&event);
+                self.latency.check_slow(&event);
+            });
+        }
+        self.check_threshold();
+    }
+}
+
+/// Runs `f` unless a callback is already running on the current thread, so
+/// that operations made by callbacks never run callbacks themselves.
+#[inline]
+pub(crate) fn exclusive<F: FnOnce()>(f: F) {
+    let _ = RUNNING.try_with(|running| {
+        if !running.replace(true) {
+            f();
+            running.set(false);
+        }
+    });
+}
@@ -0,0 +1,153 @@
+//! Warnings about single allocations of an unexpected size.
+
+use std::{alloc::GlobalAlloc, ptr, sync::atomic::Ordering};
+
+#[cfg(feature = "backtrace")]
+use crate::CallStack;
+use crate::{AllocEvent, EventKind, StatsAlloc};
+
+/// A callback reporting a huge allocation.
+type Callback = Box<dyn Fn(&AllocEvent) + Send + Sync>;
+
+/// A size at or above which allocations are reported, with the callback
+/// reporting them, or `None` to write a warning.
+pub(crate) struct HugeWarning {
+    bytes: usize,
+    callback: Option<Callback>,
+}
+
+impl HugeWarning {
+    /// Returns `true` if `event` allocates a block of at least the warning
+    /// size, or grows one to it.
+    fn reached_by(&self, event: &AllocEvent) -> bool {
+        match event.kind {
+            EventKind::Alloc => event.new_size >= self.bytes,
+            EventKind::Realloc => event.new_size >= self.bytes && event.layout.size() < self.bytes,
+            EventKind::Dealloc => false,
+        }
+    }
+}
+
+/// Returns the name of the operation making a huge allocation.
+fn operation(event: &AllocEvent) -> &'static str {
+    match event.kind {
+        EventKind::Realloc => "reallocation",
+        _ => "allocation",
+    }
+}
+
+/// Logs a warning about a huge allocation, with the call stack which made it
+/// if the `backtrace` feature is enabled.
+#[cfg(feature = "log")]
+fn warn(event: &AllocEvent) {
+    if !log::log_enabled!(target: "stats_alloc", log::Level::Warn) {
+        return;
+    }
+    #[cfg(feature = "backtrace")]
+    log::warn!(
+        target: "stats_alloc",
+        "huge {} of {} bytes\n{}",
+        operation(event),
+        event.new_size,
+        CallStack::capture()
+    );
+    #[cfg(not(feature = "backtrace"))]
+    log::warn!(target: "stats_alloc", "huge {} of {} bytes", operation(event), event.new_size);
+}
+
+/// Writes a warning about a huge allocation to standard error, with the call
+/// stack which made it if the `backtrace` feature is enabled.
+#[cfg(not(feature = "log"))]
+fn warn(event: &AllocEvent) {
+    eprintln!("stats_alloc: huge {} of {} bytes", operation(event), event.new_size);
+    #[cfg(feature = "backtrace")]
+    eprintln!("{}", CallStack::capture());
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Warns of each allocation of at least `bytes`, and each reallocation
+    /// growing a block to that size, in place of any callback set with
+    /// [`on_huge_allocation`](StatsAlloc::on_huge_allocation).
+    ///
+    /// With the `log` feature, the warning is logged at the warn level with
+    /// the target `stats_alloc`. Otherwise it is written to standard error.
+    ///
+    /// A single allocation of hundreds of megabytes is often a bug, such as a
+    /// length read from untrusted input or a capacity computed from the wrong
+    /// value, and is simplest to catch where it is made. With the `backtrace`
+    /// feature, the warning includes the call stack which made the
+    /// allocation, which is expensive to resolve, so the size should be well
+    /// above that of any allocation the program expects to make.
+    ///
+    /// This is independent of the [huge threshold] above which allocations
+    /// are counted as huge.
+    ///
+    /// [huge threshold]: StatsAlloc::set_huge_threshold
+    pub fn warn_on_huge_allocation(&self, bytes: usize) {
+        self.set_huge_warning(bytes, None);
+    }
+
+    /// Runs `callback` with each allocation of at least `bytes`, and each
+    /// reallocation growing a block to that size, in place of any callback
+    /// set before.
+    ///
+    /// The callback runs like an [`AllocHook`], on the allocating thread and
+    /// within the allocator, so it must not panic, and operations it makes do
+    /// not run it again. It may abort the process to stop at the first such
+    /// allocation.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// static LARGEST: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// alloc.on_huge_allocation(1 << 20, |event| {
+    ///     LARGEST.fetch_max(event.new_size, Ordering::SeqCst);
+    /// });
+    /// let layout = Layout::from_size_align(4 << 20, 8).unwrap();
+    /// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    /// assert_eq!(LARGEST.load(Ordering::SeqCst), 4 << 20);
+    /// ```
+    ///
+    /// [`AllocHook`]: crate::AllocHook
+    pub fn on_huge_allocation<F>(&self, bytes: usize, callback: F)
+    where
+        F: Fn(&AllocEvent) + Send + Sync + 'static,
+    {
+        self.set_huge_warning(bytes, Some(Box::new(callback)));
+    }
+
+    /// Stops reporting huge allocations, as set with
+    /// [`warn_on_huge_allocation`](StatsAlloc::warn_on_huge_allocation) or
+    /// [`on_huge_allocation`](StatsAlloc::on_huge_allocation).
+    pub fn clear_huge_allocation_warning(&self) {
+        self.huge_warning.store(ptr::null_mut(), Ordering::Release);
+    }
+
+    fn set_huge_warning(&self, bytes: usize, callback: Option<Callback>) {
+        // A warning which is replaced may still be checked on other threads,
+        // so it is never freed.
+        let warning = Box::leak(Box::new(HugeWarning { bytes, callback }));
+        self.huge_warning.store(warning, Ordering::Release);
+    }
+
+    /// Runs the huge allocation callback if `event` reaches its size.
+    #[inline]
+    pub(crate) fn check_huge_allocation(&self, event: &AllocEvent) {
+        let warning = self.huge_warning.load(Ordering::Acquire);
+        if let Some(warning) = unsafe { warning.as_ref() } {
+            if warning.reached_by(event) {
+                // The warning is written directly, rather than through a
+                // boxed callback, so that the captured stack starts within
+                // this crate and its frames are omitted from the warning.
+                match warning.callback {
+                    Some(ref callback) => callback(event),
+                    None => warn(event),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Measurement of the time spent in the backing allocator.
+
+use std::{
+    alloc::GlobalAlloc,
+    fmt, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{AllocEvent, StatsAlloc};
+
+/// Number of buckets in a [`LatencyHistogram`], the last of which holds
+/// every call of about a minute or more.
+const BUCKETS: usize = 38;
+
+/// Returns the bucket for a call which took `nanos` nanoseconds.
+fn bucket(nanos: u64) -> usize {
+    ((u64::BITS - nanos.leading_zeros()) as usize).min(BUCKETS - 1)
+}
+
+/// Returns the exclusive upper bound of the times in `bucket`.
+fn upper_bound(bucket: usize) -> Duration {
+    Duration::from_nanos(1 << bucket)
+}
+
+/// A duration above which a call is slow, with the callback reporting it.
+pub(crate) struct SlowCall {
+    limit: Duration,
+    callback: Box<dyn Fn(&AllocEvent) + Send + Sync>,
+}
+
+/// The times of the calls into the backing allocator of a `StatsAlloc`,
+/// updated while latency tracking is enabled.
+pub(crate) struct LatencyTable {
+    enabled: AtomicBool,
+    buckets: [AtomicUsize; BUCKETS],
+    slow: AtomicPtr<SlowCall>,
+}
+
+impl LatencyTable {
+    pub(crate) const fn new() -> Self {
+        // Only used to initialize the array of buckets, each as a distinct
+        // value.
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        LatencyTable {
+            enabled: AtomicBool::new(false),
+            buckets: [ZERO; BUCKETS],
+            slow: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns the time at which a call starts, if calls are being timed.
+    #[inline]
+    pub(crate) fn start(&self) -> Option<Instant> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(Instant::now())
+    }
+
+    /// Records the time taken by a call which started at `started`, and
+    /// returns it.
+    #[inline]
+    pub(crate) fn finish(&self, started: Option<Instant>) -> Option<Duration> {
+        let elapsed = started?.elapsed();
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        self.buckets[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+        Some(elapsed)
+    }
+
+    /// Runs the slow call callback if `event` took longer than its limit.
+    #[inline]
+    pub(crate) fn check_slow(&self, event: &AllocEvent) {
+        let slow = unsafe { self.slow.load(Ordering::Acquire).as_ref() };
+        if let (Some(slow), Some(elapsed)) = (slow, event.elapsed) {
+            if elapsed > slow.limit {
+                (slow.callback)(event);
+            }
+        }
+    }
+
+    /// Returns `true` if a slow call callback is set.
+    #[inline]
+    pub(crate) fn has_slow_callback(&self) -> bool {
+        !self.slow.load(Ordering::Relaxed).is_null()
+    }
+}
+
+impl fmt::Debug for LatencyTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LatencyTable")
+            .field("enabled", &self.enabled)
+            .field("slow", &self.has_slow_callback())
+            .finish()
+    }
+}
+
+/// A histogram of the time spent in the backing allocator by each call,
+/// in buckets bounded by successive powers of two nanoseconds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKETS],
+}
+
+impl LatencyHistogram {
+    /// Returns the number of calls timed.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the upper bound of each bucket holding any calls, shortest
+    /// first, with the number of calls taking less time than it but at least
+    /// the bound of the previous bucket. The last bucket, bounded at a little
+    /// over two minutes, also holds any longer calls.
+    pub fn buckets(&self) -> Vec<(Duration, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(i, &count)| (upper_bound(i), count))
+            .collect()
+    }
+
+    /// Returns a time which the fraction `q` of calls took less than, to
+    /// within a factor of two, or `None` if no call has been timed.
+    ///
+    /// For example, `quantile(0.99)` bounds the time taken by all but the
+    /// slowest one percent of calls.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        self.counts.iter().enumerate().find_map(|(i, &n)| {
+            seen += n;
+            if seen >= rank {
+                Some(upper_bound(i))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Returns `true` if the calls into the backing allocator are being
+    /// timed.
+    pub fn latency_tracking(&self) -> bool {
+        self.latency.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Selects whether each call into the backing allocator is timed, for
+    /// spotting stalls such as page faults or the mapping of fresh memory
+    /// which are attributed to allocation.
+    ///
+    /// While enabled, every operation reads the clock twice, and the times
+    /// are kept in a [`LatencyHistogram`] and given as the
+    /// [`elapsed`](AllocEvent::elapsed) time of each event. Enabling tracking
+    /// clears the histogram. Calls are timed even while counting is paused,
+    /// but not when it is compiled out.
+    pub fn set_latency_tracking(&self, enabled: bool) {
+        if enabled && !self.latency_tracking() {
+            for bucket in &self.latency.buckets {
+                bucket.store(0, Ordering::Relaxed);
+            }
+        }
+        self.latency.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns the histogram of the time taken by each call into the backing
+    /// allocator since [latency tracking] was enabled.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// alloc.set_latency_tracking(true);
+    /// let layout = Layout::new::<u64>();
+    /// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    /// let latency = alloc.latency_histogram();
+    /// assert_eq!(latency.count(), 2);
+    /// println!("median: {:?}", latency.quantile(0.5).unwrap());
+    /// ```
+    ///
+    /// [latency tracking]: StatsAlloc::set_latency_tracking
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        let mut counts = [0; BUCKETS];
+        for (count, bucket) in counts.iter_mut().zip(&self.latency.buckets) {
+            *count = bucket.load(Ordering::Relaxed) as u64;
+        }
+        LatencyHistogram { counts }
+    }
+
+    /// Runs `callback` with each successful operation whose call into the
+    /// backing allocator took longer than `limit`, in place of any callback
+    /// set before.
+    ///
+    /// This only has an effect while [latency tracking] is enabled. The
+    /// callback runs like an [`AllocHook`], on the allocating thread and
+    /// within the allocator, so it must not panic, and operations it makes do
+    /// not run it again.
+    ///
+    /// [`AllocHook`]: crate::AllocHook
+    /// [latency tracking]: StatsAlloc::set_latency_tracking
+    pub fn on_slow_allocation<F>(&self, limit: Duration, callback: F)
+    where
+        F: Fn(&AllocEvent) + Send + Sync + 'static,
+    {
+        // A callback which is replaced may still be running on other
+        // threads, so it is never freed.
+        let slow = Box::leak(Box::new(SlowCall {
+            limit,
+            callback: Box::new(callback),
+        }));
+        self.latency.slow.store(slow, Ordering::Release);
+    }
+
+    /// Stops running the callback set with
+    /// [`on_slow_allocation`](StatsAlloc::on_slow_allocation).
+    pub fn clear_slow_allocation(&self) {
+        self.latency.slow.store(ptr::null_mut(), Ordering::Release);
+    }
+}
@@ -36,33 +36,202 @@
 )]
 #![cfg_attr(doc_cfg, feature(allocator_api))]
 #![cfg_attr(doc_cfg, feature(doc_cfg))]
+#![cfg_attr(feature = "alloc-error-hook", feature(alloc_error_hook))]
+// With the `noop` feature, the bookkeeping only reachable from allocation is
+// compiled out.
+#![cfg_attr(feature = "noop", allow(dead_code))]
+
+#[cfg(feature = "backtrace")]
+extern crate backtrace;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "libtest-mimic")]
+extern crate libtest_mimic;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "opentelemetry")]
+extern crate opentelemetry;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+#[cfg(feature = "macros")]
+extern crate stats_alloc_macros;
 
 use std::{
     alloc::{GlobalAlloc, Layout, System},
-    ops,
-    sync::atomic::{AtomicIsize, AtomicUsize, Ordering},
+    fmt, iter, ops, ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
 };
 
+use counters::{count, signed_count, Shard};
+use huge::HugeWarning;
+use latency::LatencyTable;
+use live::LiveTable;
+#[cfg(feature = "backtrace")]
+use site::SiteTable;
+use subscribe::Subscriber;
+use threshold::Threshold;
+
+mod aggregate;
+#[cfg(any(feature = "proptest", feature = "quickcheck"))]
+mod arbitrary;
+mod bench;
+mod budget;
+mod builder;
+mod chrome;
+mod compare;
+mod config;
+mod counters;
+mod csv;
+mod delta;
+mod deny;
+#[cfg(feature = "backtrace")]
+mod dhat;
+mod export;
+mod fault;
+mod guard;
+#[cfg(feature = "libtest-mimic")]
+mod harness;
+#[cfg(feature = "backtrace")]
+mod heaptrack;
+mod hook;
+mod huge;
+mod latency;
+mod live;
+#[cfg(all(feature = "logcat", target_os = "android"))]
+mod logcat;
+#[cfg(feature = "macros")]
+mod macros;
+mod massif;
+mod nested;
+#[cfg(feature = "alloc-error-hook")]
+mod oom;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+mod overhead;
+mod peak;
+mod report;
+mod sample;
+mod schema;
+mod scoped;
+#[cfg(feature = "backtrace")]
+mod site;
+#[cfg(feature = "backtrace")]
+mod speedscope;
+#[cfg(feature = "backtrace")]
+mod stack;
+mod statsd;
+#[cfg(feature = "async")]
+mod stream;
+mod subscribe;
+mod summary;
+mod table;
+mod threshold;
+mod timed;
+
+pub use aggregate::{CounterSummary, RegionReport, StatsAccumulator};
+pub use bench::{bench_allocs, BenchCounter, BenchReport};
+pub use budget::{BudgetExceeded, BudgetViolation};
+pub use builder::StatsBuilder;
+pub use chrome::ChromeTraceExporter;
+pub use compare::{Comparison, ComparisonReport, Measurement};
+pub use config::{Config, ConfigError, ReportDestination, DEFAULT_REPORT_FORMAT};
+pub use counters::StatsCounters;
+pub use csv::{CsvExporter, CsvRecorder};
+pub use delta::StatsDelta;
+pub use deny::{assert_no_alloc, DenyAction, DenyAllocGuard};
+pub use export::{InfluxExporter, JsonLinesExporter, PrometheusExporter, Snapshot, StatsExporter};
+pub use fault::FaultGuard;
+pub use guard::{LeakAction, RegionGuard};
+#[cfg(feature = "libtest-mimic")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "libtest-mimic")))]
+pub use harness::{run_tests, AllocTest};
+#[cfg(feature = "backtrace")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+pub use heaptrack::HeaptrackRecorder;
+pub use hook::AllocHook;
+pub use latency::LatencyHistogram;
+pub use live::{AllocTag, LiveAllocation};
+#[cfg(all(feature = "logcat", target_os = "android"))]
+#[cfg_attr(doc_cfg, doc(cfg(all(feature = "logcat", target_os = "android"))))]
+pub use logcat::LogcatWriter;
+pub use massif::MassifExporter;
+pub use nested::{NestedRegion, RegionBreakdown};
+#[cfg(feature = "opentelemetry")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "opentelemetry")))]
+pub use otel::register_instruments;
+pub use overhead::Overhead;
+pub use report::{ChurnMeter, ExitReport, Reporter, Template, TemplateError};
+pub use schema::{write_results, MeasurementResult, RESULTS_SCHEMA, RESULTS_SCHEMA_VERSION};
+pub use scoped::{ScopedAlloc, ScopedAllocGuard};
+#[cfg(feature = "backtrace")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+pub use site::CallSite;
+#[cfg(feature = "backtrace")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+pub use stack::CallStack;
+#[cfg(feature = "macros")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "macros")))]
+pub use stats_alloc_macros::test;
+pub use statsd::{StatsdExporter, UdpWriter};
+#[cfg(feature = "async")]
+#[cfg_attr(doc_cfg, doc(cfg(feature = "async")))]
+pub use stream::Snapshots;
+pub use subscribe::{AllocEvent, EventFilter, EventKind, Subscription};
+pub use summary::{print_summary_at_exit, record_test, TestSummary};
+pub use timed::{TimedRegion, TimedReport};
+
+/// Items used by the code generated by `#[stats_alloc::test]`.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use macros::TestScope;
+}
+
 /// An instrumenting middleware which keeps track of allocation, deallocation,
 /// and reallocation requests to the underlying global allocator.
-#[derive(Default, Debug)]
+///
+/// With the `noop` feature, every request goes straight to the backing
+/// allocator, so that a `#[global_allocator]` declaration can stay in place
+/// in builds which do not need instrumentation. Nothing is counted, timed,
+/// limited or reported: every statistic remains zero, and settings such as
+/// byte limits, injected failures, scoped allocators and no-alloc scopes have
+/// no effect.
+#[derive(Debug)]
 pub struct StatsAlloc<T: GlobalAlloc> {
-    allocations: AtomicUsize,
-    deallocations: AtomicUsize,
-    reallocations: AtomicUsize,
-    bytes_allocated: AtomicUsize,
-    bytes_deallocated: AtomicUsize,
-    bytes_reallocated: AtomicIsize,
+    counters: StatsCounters,
+    huge_threshold: AtomicUsize,
+    byte_limit: AtomicUsize,
+    fail_countdown: AtomicUsize,
+    fail_threshold: AtomicU32,
+    fail_state: AtomicU32,
+    pair_reallocs: AtomicBool,
+    consistent: AtomicBool,
+    paused: AtomicBool,
+    sample_every: AtomicUsize,
+    hook: AtomicPtr<&'static dyn AllocHook>,
+    threshold: AtomicPtr<Threshold>,
+    subscribers: AtomicPtr<Subscriber>,
+    huge_warning: AtomicPtr<HugeWarning>,
+    latency: LatencyTable,
+    live: LiveTable,
+    #[cfg(feature = "backtrace")]
+    sites: SiteTable,
     inner: T,
 }
 
 /// Allocator statistics
+///
+/// Fields may be added in minor releases, so outside of this crate values are
+/// constructed with [`Stats::builder`] rather than a struct literal.
 #[derive(Clone, Copy, Default, Debug, Hash, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct Stats {
     /// Count of allocation operations
-    pub allocations: usize,
+    pub allocations: u64,
     /// Count of deallocation operations
-    pub deallocations: usize,
+    pub deallocations: u64,
     /// Count of reallocation operations
     ///
     /// An example where reallocation may occur: resizing of a `Vec<T>` when
@@ -75,41 +244,176 @@ pub struct Stats {
     /// x.push(0);
     /// x.push(1); // Potential reallocation
     /// ```
-    pub reallocations: usize,
+    pub reallocations: u64,
     /// Total bytes requested by allocations
-    pub bytes_allocated: usize,
+    pub bytes_allocated: u64,
     /// Total bytes freed by deallocations
-    pub bytes_deallocated: usize,
+    pub bytes_deallocated: u64,
     /// Total of bytes requested minus bytes freed by reallocations
     ///
     /// This number is positive if the total bytes requested by reallocation
     /// operations is greater than the total bytes freed by reallocations. A
     /// positive value indicates that resizable structures are growing, while
     /// a negative value indicates that such structures are shrinking.
-    pub bytes_reallocated: isize,
+    pub bytes_reallocated: i64,
+    /// Count of allocations requesting an alignment of at least a page
+    ///
+    /// Such requests usually bypass the allocator's fast paths.
+    pub page_aligned_allocations: u64,
+    /// Count of allocations, including reallocations which grew past the
+    /// threshold, whose size was at least the huge threshold
+    ///
+    /// See [`StatsAlloc::set_huge_threshold`].
+    pub huge_allocations: u64,
+    /// Total bytes requested by huge allocations
+    pub huge_bytes_allocated: u64,
+    /// Bytes currently allocated: those requested by allocations and
+    /// reallocations, less those freed
+    ///
+    /// Unlike the other fields this is a gauge rather than a running total,
+    /// so the change over a region is negative if the region freed more than
+    /// it allocated. See [`Stats::bytes_in_use`].
+    pub bytes_current: i64,
+    /// Count of allocations currently outstanding
+    ///
+    /// Like `bytes_current`, this is a gauge. Reallocations do not change it.
+    pub live_allocations: i64,
+    /// Highest count of allocations outstanding at any one time
+    ///
+    /// This is a maximum, which subtraction carries through from the later
+    /// snapshot rather than differencing, so the value for a region is the
+    /// highest count up to the region's end. With the `sharded` feature, it
+    /// is the highest count seen when the counters were read.
+    pub peak_live_allocations: u64,
+    /// Count of allocation and reallocation requests which the backing
+    /// allocator failed, and which are counted in no other field
+    pub allocation_failures: u64,
+    /// Count of reallocations to a larger size
+    pub reallocations_grow: u64,
+    /// Count of reallocations to a smaller size
+    pub reallocations_shrink: u64,
+    /// Total bytes by which reallocations grew their blocks
+    pub bytes_grown: u64,
+    /// Total bytes by which reallocations shrank their blocks
+    pub bytes_shrunk: u64,
+    /// Largest size requested by any single allocation or reallocation
+    ///
+    /// Like `peak_live_allocations`, this is a maximum carried through by
+    /// subtraction, so the value for a region bounds the size of every
+    /// allocation made within it, and any made before.
+    pub max_allocation_size: u64,
+    /// Count of allocations requesting an alignment of at most 8
+    pub allocations_align_8: u64,
+    /// Count of allocations requesting an alignment of 16
+    pub allocations_align_16: u64,
+    /// Count of allocations requesting an alignment of 32
+    pub allocations_align_32: u64,
+    /// Count of allocations requesting an alignment of 64
+    pub allocations_align_64: u64,
+    /// Count of allocations requesting an alignment above 64 but below a page
+    ///
+    /// Allocations requesting at least page alignment are counted in
+    /// `page_aligned_allocations`. Together with it, the `allocations_align_*`
+    /// fields count every allocation by requested alignment.
+    pub allocations_align_over_64: u64,
+    /// Count of allocation requests for zero bytes, whether or not they succeeded
+    pub zero_size_allocations: u64,
+    /// Count of times any running total wrapped around on overflow
+    ///
+    /// Differences between two snapshots are computed with wrapping arithmetic,
+    /// so they remain exact across a single wrap of a counter. If this count
+    /// differs between the two snapshots, a difference may nonetheless be
+    /// unreliable.
+    pub overflows: u64,
+}
+
+pub(crate) type FieldFn = fn(&Stats) -> i128;
+
+/// Every field of `Stats` by name, in declaration order.
+pub(crate) const STATS_FIELDS: &[(&str, FieldFn)] = &[
+    ("allocations", |s| s.allocations as i128),
+    ("deallocations", |s| s.deallocations as i128),
+    ("reallocations", |s| s.reallocations as i128),
+    ("bytes_allocated", |s| s.bytes_allocated as i128),
+    ("bytes_deallocated", |s| s.bytes_deallocated as i128),
+    ("bytes_reallocated", |s| s.bytes_reallocated as i128),
+    ("page_aligned_allocations", |s| s.page_aligned_allocations as i128),
+    ("huge_allocations", |s| s.huge_allocations as i128),
+    ("huge_bytes_allocated", |s| s.huge_bytes_allocated as i128),
+    ("bytes_current", |s| s.bytes_current as i128),
+    ("live_allocations", |s| s.live_allocations as i128),
+    ("peak_live_allocations", |s| s.peak_live_allocations as i128),
+    ("allocation_failures", |s| s.allocation_failures as i128),
+    ("reallocations_grow", |s| s.reallocations_grow as i128),
+    ("reallocations_shrink", |s| s.reallocations_shrink as i128),
+    ("bytes_grown", |s| s.bytes_grown as i128),
+    ("bytes_shrunk", |s| s.bytes_shrunk as i128),
+    ("max_allocation_size", |s| s.max_allocation_size as i128),
+    ("allocations_align_8", |s| s.allocations_align_8 as i128),
+    ("allocations_align_16", |s| s.allocations_align_16 as i128),
+    ("allocations_align_32", |s| s.allocations_align_32 as i128),
+    ("allocations_align_64", |s| s.allocations_align_64 as i128),
+    ("allocations_align_over_64", |s| s.allocations_align_over_64 as i128),
+    ("zero_size_allocations", |s| s.zero_size_allocations as i128),
+    ("overflows", |s| s.overflows as i128),
+];
+
+/// Fields of `Stats` which are maxima, carried through rather than
+/// differenced by subtraction.
+pub(crate) const MAXIMUM_FIELDS: &[&str] = &["peak_live_allocations", "max_allocation_size"];
+
+/// How a reallocation is reflected in the allocation and deallocation
+/// statistics.
+///
+/// In either mode, each reallocation is counted in `reallocations` and its
+/// net change in size in `bytes_reallocated`.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum ReallocAccounting {
+    /// Only the change in size is counted: growth is added to
+    /// `bytes_allocated` and shrinkage to `bytes_deallocated`, while the
+    /// allocation and deallocation counts are unaffected.
+    #[default]
+    Net,
+    /// A reallocation is counted as a deallocation of the old size followed by
+    /// an allocation of the new size.
+    Pair,
 }
 
 /// An instrumented instance of the system allocator.
-pub static INSTRUMENTED_SYSTEM: StatsAlloc<System> = StatsAlloc {
-    allocations: AtomicUsize::new(0),
-    deallocations: AtomicUsize::new(0),
-    reallocations: AtomicUsize::new(0),
-    bytes_allocated: AtomicUsize::new(0),
-    bytes_deallocated: AtomicUsize::new(0),
-    bytes_reallocated: AtomicIsize::new(0),
-    inner: System,
-};
+pub static INSTRUMENTED_SYSTEM: StatsAlloc<System> = StatsAlloc::system();
+
+/// The size of a memory page assumed when classifying page-aligned
+/// allocations.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The default size at or above which an allocation is considered huge.
+///
+/// This matches the point at which common `malloc` implementations stop
+/// serving requests from their size-class caches and map pages directly.
+pub const DEFAULT_HUGE_THRESHOLD: usize = 128 * 1024;
 
 impl StatsAlloc<System> {
     /// Provides access to an instrumented instance of the system allocator.
     pub const fn system() -> Self {
         StatsAlloc {
-            allocations: AtomicUsize::new(0),
-            deallocations: AtomicUsize::new(0),
-            reallocations: AtomicUsize::new(0),
-            bytes_allocated: AtomicUsize::new(0),
-            bytes_deallocated: AtomicUsize::new(0),
-            bytes_reallocated: AtomicIsize::new(0),
+            counters: StatsCounters::new(),
+            huge_threshold: AtomicUsize::new(DEFAULT_HUGE_THRESHOLD),
+            byte_limit: AtomicUsize::new(usize::MAX),
+            fail_countdown: AtomicUsize::new(0),
+            fail_threshold: AtomicU32::new(0),
+            fail_state: AtomicU32::new(0),
+            pair_reallocs: AtomicBool::new(false),
+            consistent: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            sample_every: AtomicUsize::new(0),
+            hook: AtomicPtr::new(ptr::null_mut()),
+            threshold: AtomicPtr::new(ptr::null_mut()),
+            subscribers: AtomicPtr::new(ptr::null_mut()),
+            huge_warning: AtomicPtr::new(ptr::null_mut()),
+            latency: LatencyTable::new(),
+            live: LiveTable::new(),
+            #[cfg(feature = "backtrace")]
+            sites: SiteTable::new(),
             inner: System,
         }
     }
@@ -121,12 +425,24 @@ impl<T: GlobalAlloc> StatsAlloc<T> {
     #[cfg(feature = "nightly")]
     pub const fn new(inner: T) -> Self {
         StatsAlloc {
-            allocations: AtomicUsize::new(0),
-            deallocations: AtomicUsize::new(0),
-            reallocations: AtomicUsize::new(0),
-            bytes_allocated: AtomicUsize::new(0),
-            bytes_deallocated: AtomicUsize::new(0),
-            bytes_reallocated: AtomicIsize::new(0),
+            counters: StatsCounters::new(),
+            huge_threshold: AtomicUsize::new(DEFAULT_HUGE_THRESHOLD),
+            byte_limit: AtomicUsize::new(usize::MAX),
+            fail_countdown: AtomicUsize::new(0),
+            fail_threshold: AtomicU32::new(0),
+            fail_state: AtomicU32::new(0),
+            pair_reallocs: AtomicBool::new(false),
+            consistent: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            sample_every: AtomicUsize::new(0),
+            hook: AtomicPtr::new(ptr::null_mut()),
+            threshold: AtomicPtr::new(ptr::null_mut()),
+            subscribers: AtomicPtr::new(ptr::null_mut()),
+            huge_warning: AtomicPtr::new(ptr::null_mut()),
+            latency: LatencyTable::new(),
+            live: LiveTable::new(),
+            #[cfg(feature = "backtrace")]
+            sites: SiteTable::new(),
             inner,
         }
     }
@@ -136,29 +452,674 @@ impl<T: GlobalAlloc> StatsAlloc<T> {
     #[cfg(not(feature = "nightly"))]
     pub fn new(inner: T) -> Self {
         StatsAlloc {
-            allocations: AtomicUsize::new(0),
-            deallocations: AtomicUsize::new(0),
-            reallocations: AtomicUsize::new(0),
-            bytes_allocated: AtomicUsize::new(0),
-            bytes_deallocated: AtomicUsize::new(0),
-            bytes_reallocated: AtomicIsize::new(0),
+            counters: StatsCounters::new(),
+            huge_threshold: AtomicUsize::new(DEFAULT_HUGE_THRESHOLD),
+            byte_limit: AtomicUsize::new(usize::MAX),
+            fail_countdown: AtomicUsize::new(0),
+            fail_threshold: AtomicU32::new(0),
+            fail_state: AtomicU32::new(0),
+            pair_reallocs: AtomicBool::new(false),
+            consistent: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            sample_every: AtomicUsize::new(0),
+            hook: AtomicPtr::new(ptr::null_mut()),
+            threshold: AtomicPtr::new(ptr::null_mut()),
+            subscribers: AtomicPtr::new(ptr::null_mut()),
+            huge_warning: AtomicPtr::new(ptr::null_mut()),
+            latency: LatencyTable::new(),
+            live: LiveTable::new(),
+            #[cfg(feature = "backtrace")]
+            sites: SiteTable::new(),
             inner,
         }
     }
 
     /// Takes a snapshot of the current view of the allocator statistics.
+    ///
+    /// If consistent snapshots are enabled, the snapshot reflects a single
+    /// point in time. Otherwise each counter is loaded independently, so
+    /// operations made concurrently by other threads may be partially
+    /// reflected, e.g. counted in `allocations` but not yet in
+    /// `bytes_allocated`.
     pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        if self.consistent.load(Ordering::Relaxed) {
+            self.counters.load_consistent_into(&mut stats);
+        } else {
+            self.stats_into(&mut stats);
+        }
+        stats
+    }
+
+    /// Returns `true` if [`stats`](StatsAlloc::stats) takes consistent
+    /// snapshots.
+    pub fn consistent_snapshots(&self) -> bool {
+        self.consistent.load(Ordering::Relaxed)
+    }
+
+    /// Selects whether [`stats`](StatsAlloc::stats) takes consistent
+    /// snapshots.
+    ///
+    /// While enabled, each operation marks itself as in progress while it
+    /// updates the counters, and taking a snapshot retries until no operation
+    /// overlapped it. This adds shared atomic operations to every allocation,
+    /// and taking a snapshot may spin while other threads allocate heavily.
+    /// Operations which were already in progress when this is enabled may
+    /// still be partially reflected in the next snapshot.
+    ///
+    /// [`stats_into`](StatsAlloc::stats_into) is unaffected and remains
+    /// async-signal-safe.
+    pub fn set_consistent_snapshots(&self, enabled: bool) {
+        self.consistent.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Writes a snapshot of the current view of the allocator statistics into
+    /// a caller-provided `Stats`.
+    ///
+    /// This only performs plain atomic loads: it neither allocates nor takes
+    /// any locks, and so is async-signal-safe. Crash handlers and signal
+    /// handlers (e.g., a `SIGQUIT` dump) may use it to capture the allocator
+    /// state into a preallocated, typically static, buffer.
+    #[inline]
+    pub fn stats_into(&self, out: &mut Stats) {
+        self.counters.load_into(out);
+    }
+
+    /// Returns the number of bytes currently allocated through this
+    /// allocator.
+    ///
+    /// This is maintained as a single gauge, so unlike subtracting
+    /// `bytes_deallocated` from `bytes_allocated` it cannot tear between
+    /// loads.
+    #[inline]
+    pub fn bytes_in_use(&self) -> i64 {
+        self.counters.bytes_current()
+    }
+
+    /// Returns the number of allocations currently outstanding.
+    #[inline]
+    pub fn live_allocations(&self) -> i64 {
+        self.counters.live_allocations()
+    }
+
+    /// Returns the highest number of allocations outstanding at any one time.
+    ///
+    /// With the `sharded` feature, this is approximate. See
+    /// [`StatsCounters`].
+    #[inline]
+    pub fn peak_live_allocations(&self) -> u64 {
+        self.counters.peak_live_allocations()
+    }
+
+    /// Returns the largest size requested by any single allocation or
+    /// reallocation.
+    #[inline]
+    pub fn max_allocation_size(&self) -> u64 {
+        self.counters.max_allocation_size()
+    }
+
+    /// Provides read-only access to the individual counters.
+    #[inline]
+    pub fn counters(&self) -> &StatsCounters {
+        &self.counters
+    }
+
+    /// Returns the size at or above which allocations are counted as huge.
+    pub fn huge_threshold(&self) -> usize {
+        self.huge_threshold.load(Ordering::Relaxed)
+    }
+
+    /// Sets the size at or above which allocations are counted as huge.
+    ///
+    /// Defaults to [`DEFAULT_HUGE_THRESHOLD`].
+    pub fn set_huge_threshold(&self, bytes: usize) {
+        self.huge_threshold.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns the ceiling on bytes in use set with
+    /// [`set_byte_limit`](StatsAlloc::set_byte_limit), if any.
+    pub fn byte_limit(&self) -> Option<usize> {
+        match self.byte_limit.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Sets a ceiling on the bytes in use, above which allocations and
+    /// growing reallocations fail by returning null without reaching the
+    /// backing allocator, or removes it with `None`.
+    ///
+    /// This simulates a constrained environment, for testing how a program
+    /// handles running out of memory. Failures are counted in
+    /// `allocation_failures`. The ceiling is checked against `bytes_current`,
+    /// so it is only approximate while other threads allocate concurrently,
+    /// and has no effect while counting is paused or compiled out.
+    pub fn set_byte_limit(&self, limit: Option<usize>) {
+        self.byte_limit.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    /// Makes the `n`th subsequent allocation, zeroed allocation or
+    /// reallocation return null without reaching the backing allocator, with
+    /// `1` failing the very next one. Passing `0` cancels a pending failure.
+    ///
+    /// Running a test once for each `n` until it no longer fails exercises
+    /// every out-of-memory path in fallible-allocation code. Requests from
+    /// all threads count towards `n`, and the failure is counted in
+    /// `allocation_failures`.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// let layout = Layout::new::<u64>();
+    /// alloc.fail_allocation(2);
+    /// unsafe {
+    ///     let first = alloc.alloc(layout);
+    ///     assert!(!first.is_null());
+    ///     assert!(alloc.alloc(layout).is_null());
+    ///     alloc.dealloc(first, layout);
+    /// }
+    /// ```
+    pub fn fail_allocation(&self, n: usize) {
+        self.fail_countdown.store(n, Ordering::SeqCst);
+    }
+
+    /// Returns the number of allocation requests remaining until the failure
+    /// set with [`fail_allocation`](StatsAlloc::fail_allocation), if one is
+    /// pending.
+    pub fn pending_failure(&self) -> Option<usize> {
+        match self.fail_countdown.load(Ordering::SeqCst) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    /// Makes each subsequent allocation, zeroed allocation or reallocation
+    /// fail with the given `probability`, by returning null without reaching
+    /// the backing allocator. A probability of zero turns this off.
+    ///
+    /// Failures are drawn from a pseudo-random sequence starting from `seed`,
+    /// so a single-threaded run fails the same requests each time it is
+    /// repeated with the same seed. The sequence is shared by all threads.
+    /// Failures are counted in `allocation_failures`.
+    pub fn set_failure_probability(&self, probability: f64, seed: u64) {
+        self.fail_state.store(fault::fold_seed(seed), Ordering::SeqCst);
+        self.fail_threshold
+            .store(fault::threshold(probability), Ordering::SeqCst);
+    }
+
+    /// Returns the probability with which allocation requests fail, as set
+    /// with [`set_failure_probability`](StatsAlloc::set_failure_probability).
+    pub fn failure_probability(&self) -> f64 {
+        match self.fail_threshold.load(Ordering::Relaxed) {
+            u32::MAX => 1.0,
+            threshold => threshold as f64 / 4_294_967_296.0,
+        }
+    }
+
+    /// Returns `true` if this request is the one set to fail with
+    /// `fail_allocation`, is chosen to fail by `set_failure_probability`, or
+    /// is failed by a `FaultGuard` on the current thread.
+    #[inline]
+    fn inject_failure(&self) -> bool {
+        let countdown = self.fail_countdown.load(Ordering::Relaxed) != 0
+            && self
+                .fail_countdown
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                == Ok(1);
+        countdown || self.random_failure() || fault::inject()
+    }
+
+    #[inline]
+    fn random_failure(&self) -> bool {
+        let threshold = self.fail_threshold.load(Ordering::Relaxed);
+        if threshold == 0 {
+            return false;
+        }
+        fault::fails(threshold, self.fail_state.fetch_add(fault::GAMMA, Ordering::Relaxed))
+    }
+
+    /// Returns `true` if `additional` bytes may be allocated without
+    /// exceeding the byte limit, which is not applied while paused as
+    /// `bytes_current` is not kept up to date.
+    #[inline]
+    fn within_limit(&self, additional: usize) -> bool {
+        let limit = self.byte_limit.load(Ordering::Relaxed);
+        limit == usize::MAX
+            || self.paused.load(Ordering::Relaxed)
+            || self.bytes_in_use() as i128 + additional as i128 <= limit as i128
+    }
+
+    /// Returns how reallocations are currently being counted.
+    pub fn realloc_accounting(&self) -> ReallocAccounting {
+        if self.pair_reallocs.load(Ordering::Relaxed) {
+            ReallocAccounting::Pair
+        } else {
+            ReallocAccounting::Net
+        }
+    }
+
+    /// Selects how subsequent reallocations are counted.
+    ///
+    /// Defaults to [`ReallocAccounting::Net`].
+    pub fn set_realloc_accounting(&self, accounting: ReallocAccounting) {
+        self.pair_reallocs
+            .store(accounting == ReallocAccounting::Pair, Ordering::Relaxed);
+    }
+
+    /// Stops counting, so that requests pass straight through to the backing
+    /// allocator until [`resume`](StatsAlloc::resume) is called.
+    ///
+    /// Memory allocated while paused and freed after resuming is counted as
+    /// a deallocation without a matching allocation, so the `bytes_current`
+    /// and `live_allocations` gauges may drift across a pause.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes counting after a [`pause`](StatsAlloc::pause).
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if counting is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Returns the interval at which operations are counted, as set with
+    /// [`set_sampling`](StatsAlloc::set_sampling), or `None` if every
+    /// operation is.
+    pub fn sampling(&self) -> Option<usize> {
+        match self.sample_every.load(Ordering::Relaxed) {
+            0 | 1 => None,
+            every => Some(every),
+        }
+    }
+
+    /// Counts one in every `every` operations, chosen at random, each as
+    /// `every` operations of its kind and size, or counts every operation
+    /// again with `0` or `1`.
+    ///
+    /// Most counters are then estimates, which skip the shared atomic updates
+    /// for the operations not chosen. The `bytes_current`, `live_allocations`
+    /// and `peak_live_allocations` gauges are still updated by every
+    /// operation, and remain exact, as does the byte limit checked against
+    /// them. Every operation still pays for those updates, so sampling saves
+    /// only part of the cost of counting, as the `sampled` time of
+    /// [`Overhead::calibrate`] shows. `max_allocation_size` is the largest size among the
+    /// operations chosen. Totals are only estimated well over many
+    /// operations, so the difference between two snapshots close together
+    /// may be far from the true one.
+    ///
+    /// The interval may be changed at any time, and only affects operations
+    /// counted afterwards.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// let alloc = StatsAlloc::system();
+    /// alloc.set_sampling(100);
+    /// let layout = Layout::new::<u64>();
+    /// for _ in 0..10_000 {
+    ///     unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    /// }
+    /// let stats = alloc.stats();
+    /// assert_eq!(stats.allocations % 100, 0);
+    /// assert_eq!(stats.bytes_current, 0);
+    /// ```
+    pub fn set_sampling(&self, every: usize) {
+        self.sample_every.store(every, Ordering::Relaxed);
+    }
+
+    /// Returns the number of operations the current one is counted as: zero
+    /// if it is not chosen while sampling, or otherwise the sampling
+    /// interval.
+    #[inline]
+    fn sample_weight(&self) -> usize {
+        match self.sample_every.load(Ordering::Relaxed) {
+            0 | 1 => 1,
+            every => match sample::sampled(every) {
+                Some(true) => every,
+                Some(false) => 0,
+                // A thread being torn down counts each operation exactly,
+                // rather than scaling it by the interval.
+                None => 1,
+            },
+        }
+    }
+
+    /// Runs `record` unless counting is paused, marking it as
+    /// a single update if consistent snapshots are enabled.
+    #[inline]
+    fn record<F: FnOnce()>(&self, record: F) {
+        if self.paused.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.consistent.load(Ordering::Relaxed) {
+            self.counters.begin_update();
+            record();
+            self.counters.end_update();
+        } else {
+            record();
+        }
+    }
+
+    /// Records the outcome of an allocation which returned `ptr`.
+    #[inline]
+    fn record_alloc(&self, ptr: *mut u8, layout: Layout) {
+        let shard = self.counters.shard();
+        if !ptr.is_null() {
+            shard
+                .bytes_current
+                .fetch_add(signed_count(layout.size() as isize), Ordering::SeqCst);
+            peak::record(layout.size() as i64);
+            self.record_live_allocation(shard);
+        }
+        let weight = self.sample_weight();
+        if weight == 0 {
+            return;
+        }
+        if layout.size() == 0 {
+            shard.add(&shard.zero_size_allocations, weight);
+        }
+        if ptr.is_null() {
+            shard.add(&shard.allocation_failures, weight);
+            return;
+        }
+        shard.add(&shard.allocations, weight);
+        shard.add(&shard.bytes_allocated, layout.size().wrapping_mul(weight));
+        shard
+            .max_allocation_size
+            .fetch_max(count(layout.size()), Ordering::SeqCst);
+        self.record_layout(shard, layout, weight);
+    }
+
+    /// Records a deallocation.
+    #[inline]
+    fn record_dealloc(&self, layout: Layout) {
+        let shard = self.counters.shard();
+        shard
+            .bytes_current
+            .fetch_sub(signed_count(layout.size() as isize), Ordering::SeqCst);
+        shard.live_allocations.fetch_sub(1, Ordering::SeqCst);
+        peak::record(-(layout.size() as i64));
+        let weight = self.sample_weight();
+        if weight == 0 {
+            return;
+        }
+        shard.add(&shard.deallocations, weight);
+        shard.add(&shard.bytes_deallocated, layout.size().wrapping_mul(weight));
+    }
+
+    /// Records the outcome of a reallocation which returned `ptr`.
+    ///
+    /// A failed reallocation leaves the original block in place, so only the
+    /// failure is counted.
+    #[inline]
+    fn record_realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) {
+        let shard = self.counters.shard();
+        let change = new_size.wrapping_sub(layout.size()) as isize;
+        if !ptr.is_null() {
+            shard.bytes_current.fetch_add(signed_count(change), Ordering::SeqCst);
+            peak::record(change as i64);
+        }
+        let weight = self.sample_weight();
+        if weight == 0 {
+            return;
+        }
+        if ptr.is_null() {
+            shard.add(&shard.allocation_failures, weight);
+            return;
+        }
+        shard.add(&shard.reallocations, weight);
+        shard.max_allocation_size.fetch_max(count(new_size), Ordering::SeqCst);
+        if new_size > layout.size() {
+            shard.add(&shard.reallocations_grow, weight);
+            shard.add(&shard.bytes_grown, (new_size - layout.size()).wrapping_mul(weight));
+        } else if new_size < layout.size() {
+            shard.add(&shard.reallocations_shrink, weight);
+            shard.add(&shard.bytes_shrunk, (layout.size() - new_size).wrapping_mul(weight));
+        }
+        if self.pair_reallocs.load(Ordering::Relaxed) {
+            shard.add(&shard.deallocations, weight);
+            shard.add(&shard.bytes_deallocated, layout.size().wrapping_mul(weight));
+            shard.add(&shard.allocations, weight);
+            shard.add(&shard.bytes_allocated, new_size.wrapping_mul(weight));
+            if let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) {
+                self.record_layout(shard, new_layout, weight);
+            }
+        } else if new_size > layout.size() {
+            let difference = new_size - layout.size();
+            shard.add(&shard.bytes_allocated, difference.wrapping_mul(weight));
+            let threshold = self.huge_threshold();
+            if new_size >= threshold && layout.size() < threshold {
+                shard.add(&shard.huge_allocations, weight);
+                shard.add(&shard.huge_bytes_allocated, new_size.wrapping_mul(weight));
+            }
+        } else if new_size < layout.size() {
+            let difference = layout.size() - new_size;
+            shard.add(&shard.bytes_deallocated, difference.wrapping_mul(weight));
+        }
+        shard
+            .bytes_reallocated
+            .fetch_add(signed_count(change.wrapping_mul(weight as isize)), Ordering::SeqCst);
+    }
+
+    /// Counts a new live allocation in the stripe of the current thread.
+    ///
+    /// Without the `sharded` feature there is a single stripe, and the peak
+    /// is kept exactly here. With it, the peak is only raised when loaded,
+    /// so that this touches no counter shared with other threads.
+    #[inline]
+    fn record_live_allocation(&self, shard: &Shard) {
+        let live = shard.live_allocations.fetch_add(1, Ordering::SeqCst) + 1;
+        if cfg!(not(feature = "sharded")) && live > 0 {
+            self.counters
+                .peak_live_allocations
+                .fetch_max(count(live as usize), Ordering::SeqCst);
+        }
+    }
+
+    #[inline]
+    fn record_layout(&self, shard: &Shard, layout: Layout, weight: usize) {
+        let by_alignment = match layout.align() {
+            0..=8 => &shard.allocations_align_8,
+            16 => &shard.allocations_align_16,
+            32 => &shard.allocations_align_32,
+            64 => &shard.allocations_align_64,
+            align if align < PAGE_SIZE => &shard.allocations_align_over_64,
+            _ => &shard.page_aligned_allocations,
+        };
+        shard.add(by_alignment, weight);
+        if layout.size() >= self.huge_threshold() {
+            shard.add(&shard.huge_allocations, weight);
+            shard.add(&shard.huge_bytes_allocated, layout.size().wrapping_mul(weight));
+        }
+    }
+}
+
+impl<T: GlobalAlloc + Default> Default for StatsAlloc<T> {
+    fn default() -> Self {
+        StatsAlloc::new(T::default())
+    }
+}
+
+impl Stats {
+    /// Returns the allocation churn: the total bytes allocated plus the total
+    /// bytes deallocated.
+    ///
+    /// The churn rate over an interval can be reported with a `{churn/s}`
+    /// placeholder in a [`Template`], or tracked with a [`ChurnMeter`].
+    pub fn churn(&self) -> u128 {
+        self.bytes_allocated as u128 + self.bytes_deallocated as u128
+    }
+
+    /// Returns the allocations not yet matched by a deallocation.
+    ///
+    /// For a snapshot of a whole allocator this equals `live_allocations`;
+    /// for a hand-built `Stats` it is derived from the operation counts.
+    pub fn outstanding_allocations(&self) -> i64 {
+        self.allocations.wrapping_sub(self.deallocations) as i64
+    }
+
+    /// Returns `true` if every allocation was matched by a deallocation and
+    /// every byte allocated, including by reallocations, was freed.
+    ///
+    /// ```
+    /// # use stats_alloc::{Region, StatsAlloc, INSTRUMENTED_SYSTEM};
+    /// # use std::alloc::System;
+    /// #[global_allocator]
+    /// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+    ///
+    /// fn main() {
+    ///     let region = Region::new(GLOBAL);
+    ///     let mut buffer = vec![0u8; 16];
+    ///     buffer.extend_from_slice(&[1; 64]);
+    ///     drop(buffer);
+    ///     assert!(region.change().is_balanced());
+    /// }
+    /// ```
+    pub fn is_balanced(&self) -> bool {
+        self.allocations == self.deallocations && self.bytes_allocated == self.bytes_deallocated
+    }
+
+    /// Returns the bytes allocated but not freed, or a negative value if more
+    /// was freed than allocated.
+    ///
+    /// Growth and shrinkage by reallocation are already reflected in
+    /// `bytes_allocated` and `bytes_deallocated`, so no separate realloc term
+    /// is needed.
+    pub fn leaked_bytes(&self) -> i64 {
+        self.bytes_allocated.wrapping_sub(self.bytes_deallocated) as i64
+    }
+
+    /// Returns the mean size of an allocation, or `None` if there were no
+    /// allocations.
+    pub fn average_allocation_size(&self) -> Option<f64> {
+        if self.allocations == 0 {
+            None
+        } else {
+            Some(self.bytes_allocated as f64 / self.allocations as f64)
+        }
+    }
+
+    /// Returns the count of allocation, deallocation and reallocation
+    /// operations together.
+    pub fn total_operations(&self) -> u64 {
+        self.allocations
+            .saturating_add(self.deallocations)
+            .saturating_add(self.reallocations)
+    }
+
+    /// Subtracts `rhs` field by field, returning `None` if any unsigned
+    /// counter of `rhs` is greater than that of `self`, or a signed field
+    /// overflows. The maxima of `self` are kept, as with `Stats - Stats`.
+    pub fn checked_sub(self, rhs: Stats) -> Option<Stats> {
+        Some(Stats {
+            allocations: self.allocations.checked_sub(rhs.allocations)?,
+            deallocations: self.deallocations.checked_sub(rhs.deallocations)?,
+            reallocations: self.reallocations.checked_sub(rhs.reallocations)?,
+            bytes_allocated: self.bytes_allocated.checked_sub(rhs.bytes_allocated)?,
+            bytes_deallocated: self.bytes_deallocated.checked_sub(rhs.bytes_deallocated)?,
+            bytes_reallocated: self.bytes_reallocated.checked_sub(rhs.bytes_reallocated)?,
+            page_aligned_allocations: self
+                .page_aligned_allocations
+                .checked_sub(rhs.page_aligned_allocations)?,
+            huge_allocations: self.huge_allocations.checked_sub(rhs.huge_allocations)?,
+            huge_bytes_allocated: self.huge_bytes_allocated.checked_sub(rhs.huge_bytes_allocated)?,
+            bytes_current: self.bytes_current.checked_sub(rhs.bytes_current)?,
+            live_allocations: self.live_allocations.checked_sub(rhs.live_allocations)?,
+            peak_live_allocations: self.peak_live_allocations,
+            allocation_failures: self.allocation_failures.checked_sub(rhs.allocation_failures)?,
+            reallocations_grow: self.reallocations_grow.checked_sub(rhs.reallocations_grow)?,
+            reallocations_shrink: self.reallocations_shrink.checked_sub(rhs.reallocations_shrink)?,
+            bytes_grown: self.bytes_grown.checked_sub(rhs.bytes_grown)?,
+            bytes_shrunk: self.bytes_shrunk.checked_sub(rhs.bytes_shrunk)?,
+            max_allocation_size: self.max_allocation_size,
+            allocations_align_8: self.allocations_align_8.checked_sub(rhs.allocations_align_8)?,
+            allocations_align_16: self.allocations_align_16.checked_sub(rhs.allocations_align_16)?,
+            allocations_align_32: self.allocations_align_32.checked_sub(rhs.allocations_align_32)?,
+            allocations_align_64: self.allocations_align_64.checked_sub(rhs.allocations_align_64)?,
+            allocations_align_over_64: self
+                .allocations_align_over_64
+                .checked_sub(rhs.allocations_align_over_64)?,
+            zero_size_allocations: self.zero_size_allocations.checked_sub(rhs.zero_size_allocations)?,
+            overflows: self.overflows.checked_sub(rhs.overflows)?,
+        })
+    }
+
+    /// Subtracts `rhs` field by field, stopping at zero for unsigned counters
+    /// and at the numeric bounds for signed fields. The maxima of `self` are
+    /// kept, as with `Stats - Stats`.
+    pub fn saturating_sub(self, rhs: Stats) -> Stats {
         Stats {
-            allocations: self.allocations.load(Ordering::SeqCst),
-            deallocations: self.deallocations.load(Ordering::SeqCst),
-            reallocations: self.reallocations.load(Ordering::SeqCst),
-            bytes_allocated: self.bytes_allocated.load(Ordering::SeqCst),
-            bytes_deallocated: self.bytes_deallocated.load(Ordering::SeqCst),
-            bytes_reallocated: self.bytes_reallocated.load(Ordering::SeqCst),
+            allocations: self.allocations.saturating_sub(rhs.allocations),
+            deallocations: self.deallocations.saturating_sub(rhs.deallocations),
+            reallocations: self.reallocations.saturating_sub(rhs.reallocations),
+            bytes_allocated: self.bytes_allocated.saturating_sub(rhs.bytes_allocated),
+            bytes_deallocated: self.bytes_deallocated.saturating_sub(rhs.bytes_deallocated),
+            bytes_reallocated: self.bytes_reallocated.saturating_sub(rhs.bytes_reallocated),
+            page_aligned_allocations: self
+                .page_aligned_allocations
+                .saturating_sub(rhs.page_aligned_allocations),
+            huge_allocations: self.huge_allocations.saturating_sub(rhs.huge_allocations),
+            huge_bytes_allocated: self.huge_bytes_allocated.saturating_sub(rhs.huge_bytes_allocated),
+            bytes_current: self.bytes_current.saturating_sub(rhs.bytes_current),
+            live_allocations: self.live_allocations.saturating_sub(rhs.live_allocations),
+            peak_live_allocations: self.peak_live_allocations,
+            allocation_failures: self.allocation_failures.saturating_sub(rhs.allocation_failures),
+            reallocations_grow: self.reallocations_grow.saturating_sub(rhs.reallocations_grow),
+            reallocations_shrink: self.reallocations_shrink.saturating_sub(rhs.reallocations_shrink),
+            bytes_grown: self.bytes_grown.saturating_sub(rhs.bytes_grown),
+            bytes_shrunk: self.bytes_shrunk.saturating_sub(rhs.bytes_shrunk),
+            max_allocation_size: self.max_allocation_size,
+            allocations_align_8: self.allocations_align_8.saturating_sub(rhs.allocations_align_8),
+            allocations_align_16: self.allocations_align_16.saturating_sub(rhs.allocations_align_16),
+            allocations_align_32: self.allocations_align_32.saturating_sub(rhs.allocations_align_32),
+            allocations_align_64: self.allocations_align_64.saturating_sub(rhs.allocations_align_64),
+            allocations_align_over_64: self
+                .allocations_align_over_64
+                .saturating_sub(rhs.allocations_align_over_64),
+            zero_size_allocations: self.zero_size_allocations.saturating_sub(rhs.zero_size_allocations),
+            overflows: self.overflows.saturating_sub(rhs.overflows),
         }
     }
+
+    /// Returns the bytes currently allocated, or for the change over a
+    /// region, the net bytes the region left allocated.
+    pub fn bytes_in_use(&self) -> i64 {
+        self.bytes_current
+    }
 }
 
+/// Summarizes the operations and bytes, with bytes scaled to binary units,
+/// e.g. `alloc: 1 024 ops / 3.2 MiB, dealloc: 1 020 ops / 3.1 MiB, realloc: 12
+/// ops, net: +96 KiB`.
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("alloc: ")?;
+        report::write_count(f, self.allocations)?;
+        f.write_str(" ops / ")?;
+        report::write_bytes(f, self.bytes_allocated as i128, false)?;
+        f.write_str(", dealloc: ")?;
+        report::write_count(f, self.deallocations)?;
+        f.write_str(" ops / ")?;
+        report::write_bytes(f, self.bytes_deallocated as i128, false)?;
+        f.write_str(", realloc: ")?;
+        report::write_count(f, self.reallocations)?;
+        f.write_str(" ops, net: ")?;
+        let net = self.bytes_allocated as i128 - self.bytes_deallocated as i128;
+        report::write_bytes(f, net, true)
+    }
+}
+
+/// Subtraction wraps, so that the difference between two snapshots remains
+/// exact across a single wrap-around of a counter. See [`Stats::overflows`].
+///
+/// The maxima `peak_live_allocations` and `max_allocation_size` are not
+/// differenced: the value of the later snapshot, on the left, is kept.
 impl ops::Sub for Stats {
     type Output = Stats;
 
@@ -170,12 +1131,90 @@ impl ops::Sub for Stats {
 
 impl ops::SubAssign for Stats {
     fn sub_assign(&mut self, rhs: Self) {
-        self.allocations -= rhs.allocations;
-        self.deallocations -= rhs.deallocations;
-        self.reallocations -= rhs.reallocations;
-        self.bytes_allocated -= rhs.bytes_allocated;
-        self.bytes_deallocated -= rhs.bytes_deallocated;
-        self.bytes_reallocated -= rhs.bytes_reallocated;
+        self.allocations = self.allocations.wrapping_sub(rhs.allocations);
+        self.deallocations = self.deallocations.wrapping_sub(rhs.deallocations);
+        self.reallocations = self.reallocations.wrapping_sub(rhs.reallocations);
+        self.bytes_allocated = self.bytes_allocated.wrapping_sub(rhs.bytes_allocated);
+        self.bytes_deallocated = self.bytes_deallocated.wrapping_sub(rhs.bytes_deallocated);
+        self.bytes_reallocated = self.bytes_reallocated.wrapping_sub(rhs.bytes_reallocated);
+        self.page_aligned_allocations = self.page_aligned_allocations.wrapping_sub(rhs.page_aligned_allocations);
+        self.huge_allocations = self.huge_allocations.wrapping_sub(rhs.huge_allocations);
+        self.huge_bytes_allocated = self.huge_bytes_allocated.wrapping_sub(rhs.huge_bytes_allocated);
+        self.bytes_current = self.bytes_current.wrapping_sub(rhs.bytes_current);
+        self.live_allocations = self.live_allocations.wrapping_sub(rhs.live_allocations);
+        self.allocation_failures = self.allocation_failures.wrapping_sub(rhs.allocation_failures);
+        self.reallocations_grow = self.reallocations_grow.wrapping_sub(rhs.reallocations_grow);
+        self.reallocations_shrink = self.reallocations_shrink.wrapping_sub(rhs.reallocations_shrink);
+        self.bytes_grown = self.bytes_grown.wrapping_sub(rhs.bytes_grown);
+        self.bytes_shrunk = self.bytes_shrunk.wrapping_sub(rhs.bytes_shrunk);
+        self.allocations_align_8 = self.allocations_align_8.wrapping_sub(rhs.allocations_align_8);
+        self.allocations_align_16 = self.allocations_align_16.wrapping_sub(rhs.allocations_align_16);
+        self.allocations_align_32 = self.allocations_align_32.wrapping_sub(rhs.allocations_align_32);
+        self.allocations_align_64 = self.allocations_align_64.wrapping_sub(rhs.allocations_align_64);
+        self.allocations_align_over_64 = self
+            .allocations_align_over_64
+            .wrapping_sub(rhs.allocations_align_over_64);
+        self.zero_size_allocations = self.zero_size_allocations.wrapping_sub(rhs.zero_size_allocations);
+        self.overflows = self.overflows.wrapping_sub(rhs.overflows);
+    }
+}
+
+/// Adds field by field with wrapping arithmetic, for combining the changes
+/// over separate regions.
+///
+/// Every counter is summed, including gauges such as `bytes_current`, whose
+/// sums should be interpreted with care. The maxima `peak_live_allocations`
+/// and `max_allocation_size` take the larger of the two values.
+impl ops::Add for Stats {
+    type Output = Stats;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl ops::AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.allocations = self.allocations.wrapping_add(rhs.allocations);
+        self.deallocations = self.deallocations.wrapping_add(rhs.deallocations);
+        self.reallocations = self.reallocations.wrapping_add(rhs.reallocations);
+        self.bytes_allocated = self.bytes_allocated.wrapping_add(rhs.bytes_allocated);
+        self.bytes_deallocated = self.bytes_deallocated.wrapping_add(rhs.bytes_deallocated);
+        self.bytes_reallocated = self.bytes_reallocated.wrapping_add(rhs.bytes_reallocated);
+        self.page_aligned_allocations = self.page_aligned_allocations.wrapping_add(rhs.page_aligned_allocations);
+        self.huge_allocations = self.huge_allocations.wrapping_add(rhs.huge_allocations);
+        self.huge_bytes_allocated = self.huge_bytes_allocated.wrapping_add(rhs.huge_bytes_allocated);
+        self.bytes_current = self.bytes_current.wrapping_add(rhs.bytes_current);
+        self.live_allocations = self.live_allocations.wrapping_add(rhs.live_allocations);
+        self.peak_live_allocations = self.peak_live_allocations.max(rhs.peak_live_allocations);
+        self.allocation_failures = self.allocation_failures.wrapping_add(rhs.allocation_failures);
+        self.reallocations_grow = self.reallocations_grow.wrapping_add(rhs.reallocations_grow);
+        self.reallocations_shrink = self.reallocations_shrink.wrapping_add(rhs.reallocations_shrink);
+        self.bytes_grown = self.bytes_grown.wrapping_add(rhs.bytes_grown);
+        self.bytes_shrunk = self.bytes_shrunk.wrapping_add(rhs.bytes_shrunk);
+        self.max_allocation_size = self.max_allocation_size.max(rhs.max_allocation_size);
+        self.allocations_align_8 = self.allocations_align_8.wrapping_add(rhs.allocations_align_8);
+        self.allocations_align_16 = self.allocations_align_16.wrapping_add(rhs.allocations_align_16);
+        self.allocations_align_32 = self.allocations_align_32.wrapping_add(rhs.allocations_align_32);
+        self.allocations_align_64 = self.allocations_align_64.wrapping_add(rhs.allocations_align_64);
+        self.allocations_align_over_64 = self
+            .allocations_align_over_64
+            .wrapping_add(rhs.allocations_align_over_64);
+        self.zero_size_allocations = self.zero_size_allocations.wrapping_add(rhs.zero_size_allocations);
+        self.overflows = self.overflows.wrapping_add(rhs.overflows);
+    }
+}
+
+impl iter::Sum for Stats {
+    fn sum<I: Iterator<Item = Stats>>(iter: I) -> Self {
+        iter.fold(Stats::default(), ops::Add::add)
+    }
+}
+
+impl<'a> iter::Sum<&'a Stats> for Stats {
+    fn sum<I: Iterator<Item = &'a Stats>>(iter: I) -> Self {
+        iter.copied().sum()
     }
 }
 
@@ -185,6 +1224,9 @@ impl ops::SubAssign for Stats {
 pub struct Region<'a, T: GlobalAlloc + 'a> {
     alloc: &'a StatsAlloc<T>,
     initial_stats: Stats,
+    name: Option<&'static str>,
+    paused_at: Option<Stats>,
+    checkpoint: peak::Checkpoint,
 }
 
 impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
@@ -192,23 +1234,58 @@ impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
     /// allocator.
     #[inline]
     pub fn new(alloc: &'a StatsAlloc<T>) -> Self {
+        // The checkpoint is taken first, as it may allocate.
+        let checkpoint = peak::enter();
         Region {
             alloc,
             initial_stats: alloc.stats(),
+            name: None,
+            paused_at: None,
+            checkpoint,
         }
     }
 
-    /// Returns the statistics as of instantiation or the last reset.
+    /// Creates a new region with a label identifying it in logs.
+    ///
+    /// With the `log` feature, the region's change is logged at the info
+    /// level with its name when it is dropped. The name is a `&'static str`
+    /// so that labelling a region does not itself allocate.
+    #[inline]
+    pub fn named(alloc: &'a StatsAlloc<T>, name: &'static str) -> Self {
+        let checkpoint = peak::enter();
+        Region {
+            alloc,
+            initial_stats: alloc.stats(),
+            name: Some(name),
+            paused_at: None,
+            checkpoint,
+        }
+    }
+
+    /// Returns the region's name, if it was created with [`Region::named`].
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Returns the statistics as of instantiation or the last reset, moved
+    /// forward past any spans excluded with `pause()`.
     #[inline]
     pub fn initial(&self) -> Stats {
         self.initial_stats
     }
 
     /// Returns the difference between the currently reported statistics and
-    /// those provided by `initial()`.
+    /// those provided by `initial()`, less any spans excluded with `pause()`.
+    ///
+    /// This never panics: fields are subtracted with wrapping arithmetic, as
+    /// with `Stats - Stats`. Use [`Stats::checked_sub`] on `initial()` to
+    /// detect a field which went backwards instead. The maxima are those of
+    /// the allocator at the end of the region, so `max_allocation_size` is at
+    /// least the size of the largest allocation made within it.
     #[inline]
     pub fn change(&self) -> Stats {
-        self.alloc.stats() - self.initial_stats
+        self.current() - self.initial_stats
     }
 
     /// Returns the difference between the currently reported statistics and
@@ -216,7 +1293,7 @@ impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
     /// reported statistics.
     #[inline]
     pub fn change_and_reset(&mut self) -> Stats {
-        let latest = self.alloc.stats();
+        let latest = self.current();
         let diff = latest - self.initial_stats;
         self.initial_stats = latest;
         diff
@@ -226,7 +1303,73 @@ impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
     /// referenced allocator.
     #[inline]
     pub fn reset(&mut self) {
-        self.initial_stats = self.alloc.stats();
+        self.initial_stats = self.current();
+    }
+
+    /// Stops counting changes towards this region until `resume()`, to
+    /// exclude a section such as logging or test setup.
+    ///
+    /// Only this region is affected: the allocator keeps counting, unlike
+    /// [`StatsAlloc::pause`]. Pausing an already paused region does nothing.
+    #[inline]
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(self.alloc.stats());
+        }
+    }
+
+    /// Resumes counting changes towards this region after `pause()`.
+    #[inline]
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            // Move the starting point forward past the excluded span.
+            self.initial_stats = self.alloc.stats() - (paused_at - self.initial_stats);
+        }
+    }
+
+    /// Returns the highest net bytes allocated by the current thread at any
+    /// point since the region was created, relative to the bytes allocated
+    /// by the thread at that time.
+    ///
+    /// Unlike `change().bytes_current`, which is the net bytes at the end,
+    /// this is the high-water mark reached along the way. It covers only the
+    /// thread which created the region, counting allocations through any
+    /// `StatsAlloc`, and is not affected by `reset()` or `pause()`. Regions on
+    /// a thread must be dropped in the reverse order of their creation for
+    /// the marks of enclosing regions to remain accurate.
+    ///
+    /// Once the region is moved to another thread, the mark is out of reach,
+    /// and this returns zero there. Dropping it there leaves the marks of
+    /// both threads as they are, so, as when dropped out of order, regions
+    /// enclosing it on the creating thread no longer see peaks from before
+    /// it was created.
+    #[inline]
+    pub fn peak(&self) -> i64 {
+        peak::since(&self.checkpoint)
+    }
+
+    /// Returns `true` if the region is paused.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Returns the statistics at which the region stands: the latest, or those
+    /// when it was paused.
+    fn current(&self) -> Stats {
+        self.paused_at.unwrap_or_else(|| self.alloc.stats())
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> Drop for Region<'a, T> {
+    fn drop(&mut self) {
+        peak::exit(&self.checkpoint);
+        #[cfg(feature = "log")]
+        {
+            if let Some(name) = self.name {
+                log::info!(target: "stats_alloc", "region {}: {}", name, self.change());
+            }
+        }
     }
 }
 
@@ -248,36 +1391,116 @@ unsafe impl<'a, T: GlobalAlloc + 'a> GlobalAlloc for &'a StatsAlloc<T> {
     }
 }
 
+#[cfg(not(feature = "noop"))]
 unsafe impl<T: GlobalAlloc> GlobalAlloc for StatsAlloc<T> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.allocations.fetch_add(1, Ordering::SeqCst);
-        self.bytes_allocated.fetch_add(layout.size(), Ordering::SeqCst);
+        deny::check(layout.size());
+        let started = self.latency.start();
+        let ptr = match scoped::current() {
+            _ if self.inject_failure() || !self.within_limit(layout.size()) => ptr::null_mut(),
+            Some(backing) => scoped::suspended(|| backing.alloc(layout)),
+            None => self.inner.alloc(layout),
+        };
+        let elapsed = self.latency.finish(started);
+        self.record(|| {
+            self.record_alloc(ptr, layout);
+            self.live.insert(ptr, layout);
+            #[cfg(feature = "backtrace")]
+            self.sites.record_alloc(ptr, layout.size());
+        });
+        if !ptr.is_null() {
+            self.notify(AllocEvent::alloc(layout, elapsed));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.record(|| {
+            self.record_dealloc(layout);
+            self.live.remove(ptr);
+            #[cfg(feature = "backtrace")]
+            self.sites.record_dealloc(ptr);
+        });
+        let started = self.latency.start();
+        match scoped::owner_of(ptr) {
+            Some(backing) => scoped::suspended(|| backing.dealloc(ptr, layout)),
+            None => self.inner.dealloc(ptr, layout),
+        }
+        let elapsed = self.latency.finish(started);
+        self.notify(AllocEvent::dealloc(layout, elapsed));
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        deny::check(layout.size());
+        let started = self.latency.start();
+        let ptr = match scoped::current() {
+            _ if self.inject_failure() || !self.within_limit(layout.size()) => ptr::null_mut(),
+            Some(backing) => scoped::suspended(|| backing.alloc_zeroed(layout)),
+            None => self.inner.alloc_zeroed(layout),
+        };
+        let elapsed = self.latency.finish(started);
+        self.record(|| {
+            self.record_alloc(ptr, layout);
+            self.live.insert(ptr, layout);
+            #[cfg(feature = "backtrace")]
+            self.sites.record_alloc(ptr, layout.size());
+        });
+        if !ptr.is_null() {
+            self.notify(AllocEvent::alloc(layout, elapsed));
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        deny::check(new_size);
+        let growth = new_size.saturating_sub(layout.size());
+        let started = self.latency.start();
+        let new_ptr = match scoped::owner_of(ptr) {
+            _ if self.inject_failure() || (growth > 0 && !self.within_limit(growth)) => ptr::null_mut(),
+            Some(backing) => scoped::suspended(|| backing.realloc(ptr, layout, new_size)),
+            None => self.inner.realloc(ptr, layout, new_size),
+        };
+        let elapsed = self.latency.finish(started);
+        self.record(|| {
+            self.record_realloc(new_ptr, layout, new_size);
+            self.live.reallocate(ptr, new_ptr, layout, new_size);
+            #[cfg(feature = "backtrace")]
+            self.sites.record_realloc(ptr, new_ptr, layout.size(), new_size);
+        });
+        if !new_ptr.is_null() {
+            self.notify(AllocEvent::realloc(layout, new_size, elapsed));
+        }
+        new_ptr
+    }
+}
+
+/// With the `noop` feature, every request goes straight to the backing
+/// allocator.
+#[cfg(feature = "noop")]
+unsafe impl<T: GlobalAlloc> GlobalAlloc for StatsAlloc<T> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         self.inner.alloc(layout)
     }
 
+    #[inline]
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.deallocations.fetch_add(1, Ordering::SeqCst);
-        self.bytes_deallocated.fetch_add(layout.size(), Ordering::SeqCst);
         self.inner.dealloc(ptr, layout)
     }
 
+    #[inline]
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        self.allocations.fetch_add(1, Ordering::SeqCst);
-        self.bytes_allocated.fetch_add(layout.size(), Ordering::SeqCst);
         self.inner.alloc_zeroed(layout)
     }
 
+    #[inline]
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        self.reallocations.fetch_add(1, Ordering::SeqCst);
-        if new_size > layout.size() {
-            let difference = new_size - layout.size();
-            self.bytes_allocated.fetch_add(difference, Ordering::SeqCst);
-        } else if new_size < layout.size() {
-            let difference = layout.size() - new_size;
-            self.bytes_deallocated.fetch_add(difference, Ordering::SeqCst);
-        }
-        self.bytes_reallocated
-            .fetch_add(new_size.wrapping_sub(layout.size()) as isize, Ordering::SeqCst);
         self.inner.realloc(ptr, layout, new_size)
     }
 }
+
+unsafe impl<T: ScopedAlloc> ScopedAlloc for StatsAlloc<T> {
+    fn owns(&self, ptr: *mut u8) -> bool {
+        self.inner.owns(ptr)
+    }
+}
@@ -0,0 +1,452 @@
+//! A table of the allocations currently outstanding, for enumerating leaks.
+
+#[cfg(feature = "backtrace")]
+use std::ptr;
+#[cfg(feature = "backtrace")]
+use std::sync::{atomic::AtomicUsize, Arc};
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    cmp::Reverse,
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "backtrace")]
+use crate::CallStack;
+use crate::{
+    table::{hash_address, Keyed, Locked, Table},
+    StatsAlloc,
+};
+
+/// An allocation which had not been freed when the table was read.
+///
+/// Fields depend on the enabled features, so values are only ever obtained
+/// from [`StatsAlloc::outstanding`].
+// Not `Copy`, so that enabling the `backtrace` feature is not a breaking
+// change.
+#[allow(missing_copy_implementations)]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct LiveAllocation {
+    /// Address of the allocated block
+    pub address: usize,
+    /// Size of the block in bytes
+    pub size: usize,
+    /// Alignment of the block in bytes
+    pub align: usize,
+    /// Time at which the block was first allocated, before any reallocation
+    pub allocated_at: Instant,
+    /// Tag in effect on the allocating thread, as set with an [`AllocTag`]
+    pub tag: Option<&'static str>,
+    /// Call stack of the allocating call, if it was sampled
+    ///
+    /// See [`StatsAlloc::set_backtrace_sampling`].
+    #[cfg(feature = "backtrace")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub backtrace: Option<Arc<CallStack>>,
+}
+
+impl LiveAllocation {
+    /// Returns the time elapsed since the block was first allocated.
+    pub fn age(&self) -> Duration {
+        self.allocated_at.elapsed()
+    }
+}
+
+thread_local! {
+    static TAG: Cell<Option<&'static str>> = const { Cell::new(None) };
+    /// Set while the current thread captures a call stack, whose own
+    /// allocations are not tracked.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A guard which tags the allocations made on the current thread until it is
+/// dropped, so that outstanding allocations can be traced back to the part
+/// of the program which made them.
+///
+/// Tags only have an effect while live allocation tracking is enabled. Guards
+/// may be nested, and each restores the enclosing tag when dropped.
+#[derive(Debug)]
+pub struct AllocTag {
+    previous: Option<&'static str>,
+    /// The guard must be dropped on the thread whose tag it changed.
+    _thread: PhantomData<*const ()>,
+}
+
+impl AllocTag {
+    /// Tags allocations made on this thread with `tag` while the returned
+    /// guard is alive.
+    pub fn new(tag: &'static str) -> Self {
+        AllocTag {
+            previous: TAG.with(|current| current.replace(Some(tag))),
+            _thread: PhantomData,
+        }
+    }
+}
+
+impl Drop for AllocTag {
+    fn drop(&mut self) {
+        let _ = TAG.try_with(|current| current.set(self.previous));
+    }
+}
+
+/// Number of independently locked parts of the table.
+const SHARDS: usize = 16;
+
+/// A slot of the table, empty when `address` is zero.
+#[derive(Clone, Copy)]
+struct Slot {
+    address: usize,
+    size: usize,
+    align: usize,
+    allocated_at: Instant,
+    tag: Option<&'static str>,
+    /// A reference counted by `Arc`, or null if none was captured.
+    #[cfg(feature = "backtrace")]
+    backtrace: *const CallStack,
+}
+
+// The only pointer is to a call stack shared through an `Arc`.
+unsafe impl Send for Slot {}
+
+impl Slot {
+    /// Releases the call stack of a slot which has left the table.
+    ///
+    /// This may free memory through the instrumented allocator, so it must
+    /// not be called while holding a lock on the table.
+    fn dispose(self) {
+        #[cfg(feature = "backtrace")]
+        {
+            if !self.backtrace.is_null() {
+                drop(unsafe { Arc::from_raw(self.backtrace) });
+            }
+        }
+    }
+
+    fn to_live_allocation(self) -> LiveAllocation {
+        LiveAllocation {
+            address: self.address,
+            size: self.size,
+            align: self.align,
+            allocated_at: self.allocated_at,
+            tag: self.tag,
+            #[cfg(feature = "backtrace")]
+            backtrace: if self.backtrace.is_null() {
+                None
+            } else {
+                unsafe {
+                    Arc::increment_strong_count(self.backtrace);
+                    Some(Arc::from_raw(self.backtrace))
+                }
+            },
+        }
+    }
+}
+
+impl Keyed for Slot {
+    type Key = usize;
+
+    fn vacant() -> Self {
+        Slot {
+            address: 0,
+            size: 0,
+            align: 0,
+            allocated_at: Instant::now(),
+            tag: None,
+            #[cfg(feature = "backtrace")]
+            backtrace: ptr::null(),
+        }
+    }
+
+    fn vacate(&mut self) {
+        self.address = 0;
+    }
+
+    fn is_vacant(&self) -> bool {
+        self.address == 0
+    }
+
+    fn key(&self) -> usize {
+        self.address
+    }
+
+    fn hash(&address: &usize) -> u64 {
+        hash_address(address)
+    }
+}
+
+/// Releases every slot of a table which has been taken out of its shard, and
+/// returns its storage to the system allocator.
+fn clear(table: Table<Slot>) {
+    for &slot in table.iter() {
+        slot.dispose();
+    }
+    table.release();
+}
+
+/// One independently locked part of the table.
+type Shard = Locked<Table<Slot>>;
+
+// Only used to initialize the array of shards, each as a distinct value.
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SHARD: Shard = Locked::new(Table::EMPTY);
+
+/// The outstanding allocations of a `StatsAlloc`, updated while tracking is
+/// enabled.
+pub(crate) struct LiveTable {
+    enabled: AtomicBool,
+    #[cfg(feature = "backtrace")]
+    backtrace_every: AtomicUsize,
+    #[cfg(feature = "backtrace")]
+    tracked: AtomicUsize,
+    shards: [Shard; SHARDS],
+}
+
+impl LiveTable {
+    pub(crate) const fn new() -> Self {
+        LiveTable {
+            enabled: AtomicBool::new(false),
+            #[cfg(feature = "backtrace")]
+            backtrace_every: AtomicUsize::new(0),
+            #[cfg(feature = "backtrace")]
+            tracked: AtomicUsize::new(0),
+            shards: [EMPTY_SHARD; SHARDS],
+        }
+    }
+
+    fn shard(&self, address: usize) -> &Shard {
+        &self.shards[(Slot::hash(&address) >> 60) as usize % SHARDS]
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            for shard in &self.shards {
+                clear(shard.with(|table| std::mem::replace(table, Table::EMPTY)));
+            }
+        }
+    }
+
+    /// Records an allocation of `layout` at `ptr`.
+    #[inline]
+    pub(crate) fn insert(&self, ptr: *mut u8, layout: Layout) {
+        if !self.is_enabled() || ptr.is_null() || CAPTURING.try_with(Cell::get).unwrap_or(true) {
+            return;
+        }
+        let slot = Slot {
+            address: ptr as usize,
+            size: layout.size(),
+            align: layout.align(),
+            allocated_at: Instant::now(),
+            tag: TAG.try_with(Cell::get).ok().flatten(),
+            #[cfg(feature = "backtrace")]
+            backtrace: self.sample_backtrace(),
+        };
+        self.put(slot);
+    }
+
+    /// Inserts `slot` into its shard, releasing whichever slot is not kept.
+    fn put(&self, slot: Slot) {
+        if let Some(discarded) = self.shard(slot.address).with(|table| table.insert(slot)) {
+            discarded.dispose();
+        }
+    }
+
+    /// Captures the call stack if this tracked allocation is due to be
+    /// sampled.
+    #[cfg(feature = "backtrace")]
+    fn sample_backtrace(&self) -> *const CallStack {
+        let every = self.backtrace_every.load(Ordering::Relaxed);
+        if every == 0 || !self.tracked.fetch_add(1, Ordering::Relaxed).is_multiple_of(every) {
+            return ptr::null();
+        }
+        CAPTURING.with(|capturing| capturing.set(true));
+        let backtrace = Arc::into_raw(Arc::new(CallStack::capture()));
+        CAPTURING.with(|capturing| capturing.set(false));
+        backtrace
+    }
+
+    /// Records the release of the allocation at `ptr`.
+    #[inline]
+    pub(crate) fn remove(&self, ptr: *mut u8) {
+        if self.is_enabled() {
+            let address = ptr as usize;
+            if let Some(slot) = self.shard(address).with(|table| table.remove(&address)) {
+                slot.dispose();
+            }
+        }
+    }
+
+    /// Records the move of the allocation at `ptr` to `new_ptr` with a new
+    /// size, keeping the time, tag and backtrace of its original allocation.
+    #[inline]
+    pub(crate) fn reallocate(&self, ptr: *mut u8, new_ptr: *mut u8, layout: Layout, new_size: usize) {
+        if !self.is_enabled() || new_ptr.is_null() {
+            return;
+        }
+        let address = ptr as usize;
+        match self.shard(address).with(|table| table.remove(&address)) {
+            Some(slot) => {
+                self.put(Slot {
+                    address: new_ptr as usize,
+                    size: new_size,
+                    ..slot
+                });
+            },
+            None => {
+                if let Ok(new_layout) = Layout::from_size_align(new_size, layout.align()) {
+                    self.insert(new_ptr, new_layout);
+                }
+            },
+        }
+    }
+
+    fn snapshot(&self) -> Vec<LiveAllocation> {
+        let mut live = Vec::new();
+        for shard in &self.shards {
+            // The vector is grown outside the lock, since growing it may
+            // itself be tracked in this very shard.
+            loop {
+                let needed = shard.with(|table| table.len());
+                live.reserve(needed);
+                let copied = shard.with(|table| {
+                    if live.capacity() - live.len() < table.len() {
+                        return false;
+                    }
+                    live.extend(table.iter().map(|slot| slot.to_live_allocation()));
+                    true
+                });
+                if copied {
+                    break;
+                }
+            }
+        }
+        live
+    }
+}
+
+impl fmt::Debug for LiveTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LiveTable")
+            .field("enabled", &self.is_enabled())
+            .field(
+                "len",
+                &self
+                    .shards
+                    .iter()
+                    .map(|shard| shard.with(|table| table.len()))
+                    .sum::<usize>(),
+            )
+            .finish()
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Returns `true` if outstanding allocations are being tracked.
+    pub fn live_tracking(&self) -> bool {
+        self.live.is_enabled()
+    }
+
+    /// Selects whether each outstanding allocation is recorded, so that leaks
+    /// can be enumerated with [`outstanding`](StatsAlloc::outstanding) rather
+    /// than only counted.
+    ///
+    /// While enabled, every allocation, deallocation and reallocation takes a
+    /// lock on part of a table keyed by address, whose storage comes from the
+    /// system allocator rather than through this allocator. Only allocations
+    /// made after tracking is enabled and while counting is not paused are
+    /// recorded, and disabling tracking discards the table.
+    pub fn set_live_tracking(&self, enabled: bool) {
+        self.live.set_enabled(enabled);
+    }
+
+    /// Returns the interval at which call stacks are captured, as set with
+    /// [`set_backtrace_sampling`](StatsAlloc::set_backtrace_sampling), or
+    /// `None` if they are not.
+    #[cfg(feature = "backtrace")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn backtrace_sampling(&self) -> Option<usize> {
+        match self.live.backtrace_every.load(Ordering::Relaxed) {
+            0 => None,
+            every => Some(every),
+        }
+    }
+
+    /// Captures the call stack of one in every `every` tracked allocations,
+    /// and keeps it with the allocation's record, or stops capturing with
+    /// `0`.
+    ///
+    /// This only has an effect while [live tracking] is enabled. Unwinding
+    /// the stack is expensive, so sampling trades detail for overhead: even
+    /// an interval of a few hundred usually finds where a leak comes from.
+    /// Symbols are only resolved once a [`CallStack`] is displayed. The
+    /// memory for a stack is itself allocated but not tracked, and is freed
+    /// once its allocation is freed and no [`LiveAllocation`] refers to it.
+    ///
+    /// [live tracking]: StatsAlloc::set_live_tracking
+    #[cfg(feature = "backtrace")]
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn set_backtrace_sampling(&self, every: usize) {
+        self.live.backtrace_every.store(every, Ordering::Relaxed);
+    }
+
+    /// Returns the allocations tracked as outstanding, in no particular
+    /// order.
+    ///
+    /// The table is read one part at a time, so allocations made or freed
+    /// concurrently by other threads may or may not be included.
+    ///
+    /// ```
+    /// # use stats_alloc::{AllocTag, StatsAlloc};
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// alloc.set_live_tracking(true);
+    /// let layout = Layout::new::<[u8; 48]>();
+    /// let ptr = {
+    ///     let _tag = AllocTag::new("cache");
+    ///     unsafe { alloc.alloc(layout) }
+    /// };
+    /// let leaks: Vec<_> = alloc.outstanding().collect();
+    /// assert_eq!((leaks[0].size, leaks[0].tag), (48, Some("cache")));
+    /// # unsafe { alloc.dealloc(ptr, layout) };
+    /// ```
+    pub fn outstanding(&self) -> impl Iterator<Item = LiveAllocation> {
+        self.live.snapshot().into_iter()
+    }
+
+    /// Returns the `n` largest allocations tracked as outstanding, largest
+    /// first and the oldest first among those of the same size.
+    ///
+    /// Like [`outstanding`](StatsAlloc::outstanding), this requires
+    /// [live tracking](StatsAlloc::set_live_tracking) to be enabled.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// alloc.set_live_tracking(true);
+    /// let layouts = [Layout::new::<[u8; 16]>(), Layout::new::<[u8; 4096]>()];
+    /// let ptrs: Vec<_> = layouts.iter().map(|&layout| unsafe { alloc.alloc(layout) }).collect();
+    /// let largest = alloc.largest_outstanding(1);
+    /// assert_eq!((largest.len(), largest[0].size), (1, 4096));
+    /// # for (&ptr, &layout) in ptrs.iter().zip(&layouts) {
+    /// #     unsafe { alloc.dealloc(ptr, layout) };
+    /// # }
+    /// ```
+    pub fn largest_outstanding(&self, n: usize) -> Vec<LiveAllocation> {
+        let mut live = self.live.snapshot();
+        live.sort_unstable_by_key(|allocation| (Reverse(allocation.size), allocation.allocated_at));
+        live.truncate(n);
+        live
+    }
+}
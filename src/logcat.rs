@@ -0,0 +1,86 @@
+//! Reporting to the Android log, for libraries embedded in mobile apps.
+
+use std::{
+    ffi::CString,
+    io::{self, Write},
+    os::raw::{c_char, c_int},
+};
+
+const ANDROID_LOG_INFO: c_int = 4;
+
+#[link(name = "log")]
+extern "C" {
+    fn __android_log_write(prio: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+}
+
+/// A writer which sends each line written to it to the Android log (logcat)
+/// at info priority.
+///
+/// This is intended as the output of a [`Reporter`](crate::Reporter) or
+/// [`ExitReport`](crate::ExitReport) where standard error is not visible.
+///
+/// ```no_run
+/// # use stats_alloc::{LogcatWriter, Reporter, Template, INSTRUMENTED_SYSTEM};
+/// # use std::time::Duration;
+/// let template = Template::parse("{allocations} allocs, {bytes_allocated} B").unwrap();
+/// let reporter = Reporter::spawn(
+///     &INSTRUMENTED_SYSTEM,
+///     Duration::from_secs(60),
+///     template,
+///     LogcatWriter::new("stats_alloc"),
+/// );
+/// ```
+#[derive(Debug)]
+pub struct LogcatWriter {
+    tag: CString,
+    line: Vec<u8>,
+}
+
+impl LogcatWriter {
+    /// Creates a writer which logs under `tag`, from which any NUL
+    /// characters are removed.
+    pub fn new(tag: &str) -> Self {
+        LogcatWriter {
+            tag: without_nul(tag.as_bytes()),
+            line: Vec::new(),
+        }
+    }
+
+    fn write_line(&mut self) {
+        let text = without_nul(&self.line);
+        self.line.clear();
+        unsafe {
+            __android_log_write(ANDROID_LOG_INFO, self.tag.as_ptr(), text.as_ptr());
+        }
+    }
+}
+
+fn without_nul(bytes: &[u8]) -> CString {
+    CString::new(bytes.iter().copied().filter(|&b| b != 0).collect::<Vec<_>>()).expect("NUL characters were removed")
+}
+
+impl Write for LogcatWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                self.write_line();
+            } else {
+                self.line.push(byte);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.line.is_empty() {
+            self.write_line();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LogcatWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
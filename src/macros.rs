@@ -0,0 +1,60 @@
+//! Support for the `#[stats_alloc::test]` attribute.
+
+use std::{
+    alloc::GlobalAlloc,
+    sync::{Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+use crate::{print_summary_at_exit, record_test, Region, Stats, StatsAlloc};
+
+/// Held by each running test, as the counters it measures are shared by the
+/// whole process.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// The region over a single test, checked against its budget when the test
+/// returns.
+///
+/// Scopes are run one at a time, so that tests which the harness runs in
+/// parallel do not count each other's allocations.
+#[derive(Debug)]
+pub struct TestScope<'a, T: GlobalAlloc + 'a> {
+    region: Region<'a, T>,
+    name: &'static str,
+    budget: Stats,
+    // Declared last, so it is released after the test has been recorded.
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl<'a, T: GlobalAlloc + 'a> TestScope<'a, T> {
+    /// Starts measuring the test `name`, whose statistics are recorded for
+    /// the summary printed at exit, once no other scope is running.
+    pub fn new(alloc: &'a StatsAlloc<T>, name: &'static str, budget: Stats) -> Self {
+        // A test which failed while holding the lock has poisoned it.
+        let serial = SERIAL.lock().unwrap_or_else(PoisonError::into_inner);
+        print_summary_at_exit();
+        TestScope {
+            region: Region::new(alloc),
+            name,
+            budget,
+            _serial: serial,
+        }
+    }
+
+    /// Panics if the test exceeded its budget.
+    pub fn finish(self) {
+        if let Err(exceeded) = self.region.change().check_budget(&self.budget) {
+            panic!("{}: {}", self.name, exceeded);
+        }
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> Drop for TestScope<'a, T> {
+    fn drop(&mut self) {
+        let change = self.region.change();
+        if thread::panicking() {
+            eprintln!("stats_alloc: {}: {}", self.name, change);
+        }
+        record_test(self.name, change);
+    }
+}
@@ -0,0 +1,65 @@
+//! Output of heap usage over time in the format of Valgrind's massif tool.
+
+use std::{
+    env,
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use crate::{Snapshot, StatsExporter};
+
+/// Exports snapshots in the output format of Valgrind's massif heap profiler,
+/// for viewing with `ms_print` or massif-visualizer.
+///
+/// Each snapshot records `bytes_current` as the heap size, with time in
+/// milliseconds since the first snapshot. No call sites are tracked, so
+/// every snapshot has an empty heap tree, and no extra or stack bytes are
+/// reported.
+///
+/// ```no_run
+/// # use stats_alloc::{MassifExporter, Reporter, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::{alloc::System, fs::File, time::Duration};
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let out = File::create("massif.out.stats_alloc").unwrap();
+///     let _reporter = Reporter::spawn(GLOBAL, Duration::from_millis(100), MassifExporter::new(), out).unwrap();
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MassifExporter {
+    start: Option<SystemTime>,
+    snapshots: u64,
+}
+
+impl MassifExporter {
+    /// Creates an exporter which has not yet written any snapshots.
+    pub fn new() -> Self {
+        MassifExporter::default()
+    }
+}
+
+impl StatsExporter for MassifExporter {
+    fn begin(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let cmd: Vec<_> = env::args().collect();
+        writeln!(out, "desc: (none)")?;
+        writeln!(out, "cmd: {}", cmd.join(" "))?;
+        writeln!(out, "time_unit: ms")
+    }
+
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        let start = *self.start.get_or_insert(snapshot.time);
+        let time = snapshot.time.duration_since(start).unwrap_or_default().as_millis();
+        writeln!(out, "#-----------")?;
+        writeln!(out, "snapshot={}", self.snapshots)?;
+        writeln!(out, "#-----------")?;
+        writeln!(out, "time={}", time)?;
+        writeln!(out, "mem_heap_B={}", snapshot.stats.bytes_current.max(0))?;
+        writeln!(out, "mem_heap_extra_B=0")?;
+        writeln!(out, "mem_stacks_B=0")?;
+        writeln!(out, "heap_tree=empty")?;
+        self.snapshots += 1;
+        Ok(())
+    }
+}
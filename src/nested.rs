@@ -0,0 +1,125 @@
+//! Regions which nest within one another on the current thread.
+
+use std::{alloc::GlobalAlloc, cell::RefCell, fmt};
+
+use crate::{report::write_bytes, Region, Stats, StatsAlloc};
+
+thread_local! {
+    /// Breakdowns of the finished children of each open nested region, from
+    /// outermost to innermost.
+    static STACK: RefCell<Vec<Vec<RegionBreakdown>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The change over a nested region, together with that of each child region
+/// which finished within it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionBreakdown {
+    /// Name of the region
+    pub name: &'static str,
+    /// Change in statistics over the region, including its children
+    pub change: Stats,
+    /// Breakdowns of the child regions, in the order they finished
+    pub children: Vec<RegionBreakdown>,
+}
+
+/// Summarizes the bytes allocated by the region and each of its children,
+/// e.g. `deserialize: 40 KiB, of which header: 2.0 KiB, body: 38 KiB`.
+impl fmt::Display for RegionBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        write_bytes(f, self.change.bytes_allocated as i128, false)?;
+        for (i, child) in self.children.iter().enumerate() {
+            f.write_str(if i == 0 { ", of which " } else { ", " })?;
+            if child.children.is_empty() {
+                write!(f, "{}", child)?;
+            } else {
+                write!(f, "({})", child)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A named region which reports its change to the nested region enclosing it
+/// on the same thread when it is dropped.
+///
+/// Bookkeeping for finished children allocates on the current thread, and is
+/// counted in the enclosing region but not in the children themselves.
+///
+/// ```
+/// # use stats_alloc::{Region, StatsAlloc, INSTRUMENTED_SYSTEM};
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let outer = Region::nested(GLOBAL, "deserialize");
+///     {
+///         let _header = Region::nested(GLOBAL, "header");
+///         let _ = vec![0u8; 16];
+///     }
+///     let breakdown = outer.breakdown();
+///     assert_eq!(breakdown.children[0].name, "header");
+///     println!("{}", breakdown);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct NestedRegion<'a, T: GlobalAlloc + 'a> {
+    region: Region<'a, T>,
+    depth: usize,
+}
+
+impl<'a, T: GlobalAlloc + 'a> Region<'a, T> {
+    /// Creates a named region nested within the innermost nested region
+    /// currently open on this thread, if any.
+    pub fn nested(alloc: &'a StatsAlloc<T>, name: &'static str) -> NestedRegion<'a, T> {
+        let depth = STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            stack.push(Vec::new());
+            stack.len() - 1
+        });
+        NestedRegion {
+            region: Region::named(alloc, name),
+            depth,
+        }
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> NestedRegion<'a, T> {
+    /// Returns the region being measured.
+    pub fn region(&self) -> &Region<'a, T> {
+        &self.region
+    }
+
+    /// Returns the change so far, with the breakdowns of the children which
+    /// have finished.
+    pub fn breakdown(&self) -> RegionBreakdown {
+        let change = self.region.change();
+        let children = STACK.with(|stack| stack.borrow().get(self.depth).cloned().unwrap_or_default());
+        RegionBreakdown {
+            name: self.region.name().unwrap_or_default(),
+            change,
+            children,
+        }
+    }
+}
+
+impl<'a, T: GlobalAlloc + 'a> Drop for NestedRegion<'a, T> {
+    fn drop(&mut self) {
+        let change = self.region.change();
+        let name = self.region.name().unwrap_or_default();
+        let _ = STACK.try_with(|stack| {
+            let mut stack = stack.borrow_mut();
+            // Children still open when their parent finishes are discarded,
+            // and report nothing if they are dropped later.
+            if stack.len() <= self.depth {
+                return;
+            }
+            stack.truncate(self.depth + 1);
+            let children = stack.pop().unwrap_or_default();
+            if let Some(parent) = stack.last_mut() {
+                parent.push(RegionBreakdown { name, change, children });
+            }
+        });
+    }
+}
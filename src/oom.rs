@@ -0,0 +1,69 @@
+//! Reporting of the allocator's state when the process runs out of memory.
+
+use std::{
+    alloc::{self, GlobalAlloc, Layout},
+    sync::{Mutex, PoisonError},
+};
+
+use crate::{Stats, StatsAlloc};
+
+/// An allocator whose statistics are written by the allocation error hook.
+trait Snapshot: Sync {
+    fn snapshot(&self) -> Stats;
+}
+
+impl<T: GlobalAlloc + Sync> Snapshot for StatsAlloc<T> {
+    fn snapshot(&self) -> Stats {
+        self.stats()
+    }
+}
+
+static REPORTED: Mutex<Option<&'static dyn Snapshot>> = Mutex::new(None);
+
+/// Writes the failed layout and the final statistics to standard error.
+///
+/// This runs when an allocation has already failed, so it only formats into
+/// the unbuffered standard error, which does not allocate.
+fn report(layout: Layout) {
+    eprintln!(
+        "memory allocation of {} bytes (align {}) failed",
+        layout.size(),
+        layout.align()
+    );
+    let alloc = *REPORTED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(alloc) = alloc {
+        eprintln!("stats_alloc: statistics at allocation failure: {:#?}", alloc.snapshot());
+    }
+}
+
+impl<T: GlobalAlloc + Sync> StatsAlloc<T> {
+    /// Installs an [allocation error hook] which writes the failed layout and
+    /// a final snapshot of these statistics to standard error, in place of
+    /// any hook or allocator installed before.
+    ///
+    /// The hook runs when an allocation fails and the program cannot carry
+    /// on, such as when a `Vec` cannot grow, just before the process aborts,
+    /// so that post-mortem logs show the state of the allocator at the time.
+    /// A failed request which was made through this allocator has already
+    /// been counted in `allocation_failures`, and is included in the
+    /// snapshot.
+    ///
+    /// ```no_run
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::System;
+    /// #[global_allocator]
+    /// static GLOBAL: StatsAlloc<System> = StatsAlloc::system();
+    ///
+    /// GLOBAL.install_alloc_error_hook();
+    /// ```
+    ///
+    /// The `alloc-error-hook` feature requires a nightly compiler, as the
+    /// hook is not yet stable.
+    ///
+    /// [allocation error hook]: std::alloc::set_alloc_error_hook
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "alloc-error-hook")))]
+    pub fn install_alloc_error_hook(&'static self) {
+        *REPORTED.lock().unwrap_or_else(PoisonError::into_inner) = Some(self);
+        alloc::set_alloc_error_hook(report);
+    }
+}
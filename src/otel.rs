@@ -0,0 +1,57 @@
+//! Publishing of allocator statistics as OpenTelemetry instruments.
+
+use std::alloc::GlobalAlloc;
+
+use opentelemetry::metrics::Meter;
+
+use crate::{export::is_gauge, StatsAlloc, STATS_FIELDS};
+
+/// Registers an observable instrument on `meter` for every field of
+/// [`Stats`], each reporting the current value from `alloc` whenever the
+/// meter's reader collects.
+///
+/// Instruments are named `stats_alloc.` followed by the field name. Fields
+/// which only increase are published as `u64` observable counters, and those
+/// which may decrease, such as `bytes_current`, as `i64` observable gauges.
+/// Byte counts have the unit `By`.
+///
+/// ```
+/// # extern crate opentelemetry;
+/// # extern crate stats_alloc;
+/// # use stats_alloc::{register_instruments, INSTRUMENTED_SYSTEM};
+/// # fn main() {
+/// let meter = opentelemetry::global::meter("my_app");
+/// register_instruments(&INSTRUMENTED_SYSTEM, &meter);
+/// # }
+/// ```
+///
+/// [`Stats`]: crate::Stats
+pub fn register_instruments<T: GlobalAlloc + Sync>(alloc: &'static StatsAlloc<T>, meter: &Meter) {
+    for &(field, value) in STATS_FIELDS {
+        let name = format!("stats_alloc.{}", field);
+        let unit = if field.contains("bytes") || field == "max_allocation_size" {
+            Some("By")
+        } else {
+            None
+        };
+        if is_gauge(field) {
+            let gauge = meter.i64_observable_gauge(name);
+            let gauge = match unit {
+                Some(unit) => gauge.with_unit(unit),
+                None => gauge,
+            };
+            gauge
+                .with_callback(move |observer| observer.observe(value(&alloc.stats()) as i64, &[]))
+                .build();
+        } else {
+            let counter = meter.u64_observable_counter(name);
+            let counter = match unit {
+                Some(unit) => counter.with_unit(unit),
+                None => counter,
+            };
+            counter
+                .with_callback(move |observer| observer.observe(value(&alloc.stats()) as u64, &[]))
+                .build();
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Measurement of the cost of instrumentation on the current machine.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use crate::{ReallocAccounting, StatsAlloc};
+
+/// Operations performed by each iteration of the calibration loop.
+const OPERATIONS_PER_ITERATION: u32 = 3;
+
+/// Sampling interval of the allocator timed for [`Overhead::sampled`].
+const SAMPLING: usize = 100;
+
+/// The time taken per allocator operation with and without instrumentation,
+/// as measured by [`Overhead::calibrate`].
+///
+/// ```
+/// # use stats_alloc::Overhead;
+/// let overhead = Overhead::calibrate(10_000);
+/// println!("{}", overhead);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overhead {
+    /// Number of iterations of the calibration loop
+    pub iterations: u32,
+    /// Time per operation of the backing allocator alone
+    pub uninstrumented: Duration,
+    /// Time per operation with net reallocation accounting
+    pub net: Duration,
+    /// Time per operation with paired reallocation accounting
+    pub pair: Duration,
+    /// Time per operation with net reallocation accounting, counting one in
+    /// every hundred operations
+    pub sampled: Duration,
+}
+
+impl Overhead {
+    /// Runs a loop of allocations, reallocations and deallocations through
+    /// the system allocator, both directly and through a `StatsAlloc` in each
+    /// accounting mode and with sampling, and returns the average time per
+    /// operation.
+    ///
+    /// The loop runs on the current thread with a private `StatsAlloc`, so
+    /// the counters of other instrumented allocators are not disturbed.
+    /// Results are only indicative: they vary between runs and depend on
+    /// contention from other threads.
+    pub fn calibrate(iterations: u32) -> Self {
+        let iterations = iterations.max(1);
+        let net = StatsAlloc::new(System);
+        let pair = StatsAlloc::new(System);
+        pair.set_realloc_accounting(ReallocAccounting::Pair);
+        let sampled = StatsAlloc::new(System);
+        sampled.set_sampling(SAMPLING);
+
+        // Warm up the backing allocator before timing anything.
+        time(&System, iterations / 10 + 1);
+        Overhead {
+            iterations,
+            uninstrumented: time(&System, iterations),
+            net: time(&net, iterations),
+            pair: time(&pair, iterations),
+            sampled: time(&sampled, iterations),
+        }
+    }
+
+    /// Returns the time added to each operation by net accounting, the
+    /// default mode.
+    pub fn per_operation(&self) -> Duration {
+        self.net.saturating_sub(self.uninstrumented)
+    }
+}
+
+fn time<A: GlobalAlloc>(alloc: &A, iterations: u32) -> Duration {
+    let layout = Layout::from_size_align(64, 8).expect("calibration layout is valid");
+    let start = Instant::now();
+    for _ in 0..iterations {
+        unsafe {
+            let ptr = black_box(alloc.alloc(layout));
+            if ptr.is_null() {
+                continue;
+            }
+            let grown = black_box(alloc.realloc(ptr, layout, 128));
+            if grown.is_null() {
+                alloc.dealloc(ptr, layout);
+            } else {
+                alloc.dealloc(grown, Layout::from_size_align_unchecked(128, 8));
+            }
+        }
+    }
+    start.elapsed() / iterations.saturating_mul(OPERATIONS_PER_ITERATION)
+}
+
+impl fmt::Display for Overhead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let overhead = |d: Duration| d.as_nanos() as i128 - self.uninstrumented.as_nanos() as i128;
+        writeln!(f, "{:<16} {:>10} {:>10}", "mode", "ns/op", "overhead")?;
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>10}",
+            "uninstrumented",
+            self.uninstrumented.as_nanos(),
+            "-"
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>+10}",
+            "net",
+            self.net.as_nanos(),
+            overhead(self.net)
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>+10}",
+            "pair",
+            self.pair.as_nanos(),
+            overhead(self.pair)
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>10} {:>+10}",
+            "sampled 1/100",
+            self.sampled.as_nanos(),
+            overhead(self.sampled)
+        )
+    }
+}
@@ -0,0 +1,74 @@
+//! Per-thread high-water marks of bytes in use, for [`Region::peak`].
+//!
+//! [`Region::peak`]: crate::Region::peak
+
+use std::{
+    cell::Cell,
+    thread::{self, ThreadId},
+};
+
+thread_local! {
+    /// Net bytes allocated by this thread through any `StatsAlloc`.
+    static NET: Cell<i64> = const { Cell::new(0) };
+    /// Highest value of `NET` since the innermost open region began.
+    static PEAK: Cell<i64> = const { Cell::new(0) };
+}
+
+/// Records a change in the net bytes allocated by the current thread.
+#[inline]
+pub(crate) fn record(change: i64) {
+    let _ = NET.try_with(|net| {
+        let current = net.get().wrapping_add(change);
+        net.set(current);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(current)));
+    });
+}
+
+/// The state of the thread which began a region.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Checkpoint {
+    thread: ThreadId,
+    start: i64,
+    outer_peak: i64,
+}
+
+impl Checkpoint {
+    /// Returns `true` if the region was begun on the current thread, whose
+    /// marks are the only ones which can be read.
+    fn is_current(&self) -> bool {
+        self.thread == thread::current().id()
+    }
+}
+
+/// Starts a new high-water mark for a region beginning now.
+pub(crate) fn enter() -> Checkpoint {
+    // Looked up first, as the first lookup on a thread may allocate.
+    let thread = thread::current().id();
+    let start = NET.try_with(Cell::get).unwrap_or_default();
+    let outer_peak = PEAK.try_with(|peak| peak.replace(start)).unwrap_or(start);
+    Checkpoint {
+        thread,
+        start,
+        outer_peak,
+    }
+}
+
+/// Returns the highest net bytes allocated by the current thread above those
+/// at `checkpoint`, or zero on any thread but the one which began it.
+pub(crate) fn since(checkpoint: &Checkpoint) -> i64 {
+    if !checkpoint.is_current() {
+        return 0;
+    }
+    PEAK.try_with(Cell::get)
+        .map(|peak| peak.wrapping_sub(checkpoint.start))
+        .unwrap_or_default()
+}
+
+/// Ends the region begun at `checkpoint`, folding its high-water mark into
+/// that of the enclosing region. Nothing is done on any thread but the one
+/// which began it, whose marks are out of reach.
+pub(crate) fn exit(checkpoint: &Checkpoint) {
+    if checkpoint.is_current() {
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(checkpoint.outer_peak)));
+    }
+}
@@ -0,0 +1,409 @@
+//! Periodic reporting of allocator statistics.
+
+use std::{
+    alloc::GlobalAlloc,
+    error::Error,
+    fmt,
+    fmt::Write as _,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{FieldFn, Snapshot, Stats, StatsAlloc, StatsExporter, STATS_FIELDS};
+
+/// Statistics derived from the `Stats` fields which may also be used in
+/// templates.
+const DERIVED_FIELDS: &[(&str, FieldFn)] = &[
+    ("bytes_in_use", |s| s.bytes_in_use() as i128),
+    ("churn", |s| s.churn() as i128),
+    ("outstanding_allocations", |s| s.outstanding_allocations() as i128),
+    ("total_operations", |s| s.total_operations() as i128),
+];
+
+fn field(index: usize) -> FieldFn {
+    STATS_FIELDS
+        .iter()
+        .chain(DERIVED_FIELDS)
+        .nth(index)
+        .expect("valid field index")
+        .1
+}
+
+/// Writes `n` with its digits grouped in threes, separated by spaces.
+pub(crate) fn write_count(f: &mut fmt::Formatter, n: u64) -> fmt::Result {
+    let digits = n.to_string();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            f.write_char(' ')?;
+        }
+        f.write_char(digit)?;
+    }
+    Ok(())
+}
+
+/// Writes a byte quantity scaled to the largest binary unit in which it is at
+/// least one, e.g. `512 B`, `3.2 MiB` or `96 KiB`, with a sign if `signed`.
+pub(crate) fn write_bytes(f: &mut fmt::Formatter, bytes: i128, signed: bool) -> fmt::Result {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    let sign = match bytes {
+        b if b < 0 => "-",
+        _ if signed => "+",
+        _ => "",
+    };
+    let mut value = bytes.unsigned_abs() as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit > 0 && value < 10.0 {
+        write!(f, "{}{:.1} {}", sign, value, UNITS[unit])
+    } else {
+        write!(f, "{}{:.0} {}", sign, value, UNITS[unit])
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Total(usize),
+    Rate(usize),
+}
+
+/// A format for a single report line, with placeholders for statistics.
+///
+/// A placeholder `{allocations}` is replaced with the current value of the
+/// named `Stats` field, while `{allocations/s}` is replaced with its average
+/// rate of change per second over the last reporting interval. Literal braces
+/// are written as `{{` and `}}`.
+///
+/// Besides the fields, placeholders may name `bytes_in_use`, as returned by
+/// [`Stats::bytes_in_use`], `churn`, `outstanding_allocations` and
+/// `total_operations`.
+///
+/// ```
+/// # use stats_alloc::Template;
+/// let template: Template = "{bytes_in_use} live, {allocations/s}/s".parse().unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// An error encountered while parsing a [`Template`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A placeholder names a field which does not exist
+    UnknownField(String),
+    /// A placeholder was opened but never closed
+    Unclosed,
+    /// A closing brace appeared outside of a placeholder
+    UnmatchedClose,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TemplateError::UnknownField(name) => write!(f, "unknown statistic `{}` in template", name),
+            TemplateError::Unclosed => f.write_str("unclosed placeholder in template"),
+            TemplateError::UnmatchedClose => f.write_str("unmatched `}` in template"),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+impl Template {
+    /// Parses a template from its textual form.
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                },
+                '}' => return Err(TemplateError::UnmatchedClose),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(TemplateError::Unclosed),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    let (field, rate) = match name.strip_suffix("/s") {
+                        Some(field) => (field, true),
+                        None => (&name[..], false),
+                    };
+                    let index = STATS_FIELDS
+                        .iter()
+                        .chain(DERIVED_FIELDS)
+                        .position(|&(candidate, _)| candidate == field.trim())
+                        .ok_or_else(|| TemplateError::UnknownField(name.clone()))?;
+                    segments.push(if rate {
+                        Segment::Rate(index)
+                    } else {
+                        Segment::Total(index)
+                    });
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    /// Renders the template for the `current` statistics, computing rates from
+    /// the `previous` statistics taken `elapsed` earlier.
+    pub fn render(&self, current: &Stats, previous: &Stats, elapsed: Duration) -> String {
+        let mut line = String::new();
+        let seconds = elapsed.as_secs_f64();
+        for segment in &self.segments {
+            match *segment {
+                Segment::Literal(ref text) => line.push_str(text),
+                Segment::Total(index) => {
+                    let _ = write!(line, "{}", field(index)(current));
+                },
+                Segment::Rate(index) => {
+                    let change = field(index)(current) - field(index)(previous);
+                    let rate = if seconds > 0.0 { change as f64 / seconds } else { 0.0 };
+                    let _ = write!(line, "{:.0}", rate);
+                },
+            }
+        }
+        line
+    }
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::parse(s)
+    }
+}
+
+/// Tracks the rate of allocation churn, the bytes allocated plus the bytes
+/// deallocated, across successive samples.
+///
+/// High churn with flat residency indicates memory which is repeatedly freed
+/// and reacquired, something that net byte counts alone do not reveal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChurnMeter {
+    last: Option<(u128, Instant)>,
+    current_rate: f64,
+    max_rate: f64,
+}
+
+impl ChurnMeter {
+    /// Creates a meter which has not yet been sampled.
+    pub fn new() -> Self {
+        ChurnMeter::default()
+    }
+
+    /// Records a sample of the statistics, returning the churn rate in bytes
+    /// per second since the previous sample.
+    ///
+    /// The first sample only establishes a starting point, and returns zero.
+    pub fn sample(&mut self, stats: &Stats) -> f64 {
+        let now = Instant::now();
+        let churn = stats.churn();
+        if let Some((previous, at)) = self.last {
+            let seconds = (now - at).as_secs_f64();
+            if seconds > 0.0 {
+                self.current_rate = churn.saturating_sub(previous) as f64 / seconds;
+                self.max_rate = self.max_rate.max(self.current_rate);
+            }
+        }
+        self.last = Some((churn, now));
+        self.current_rate
+    }
+
+    /// Returns the churn rate, in bytes per second, over the latest interval.
+    pub fn current_rate(&self) -> f64 {
+        self.current_rate
+    }
+
+    /// Returns the highest churn rate, in bytes per second, seen over any
+    /// interval.
+    pub fn max_rate(&self) -> f64 {
+        self.max_rate
+    }
+}
+
+/// A background thread which periodically exports a snapshot of allocator
+/// statistics through a [`StatsExporter`], such as a [`Template`].
+///
+/// The reporter stops when it is dropped.
+#[derive(Debug)]
+pub struct Reporter {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    /// Starts exporting the statistics of `alloc` to `out` once every
+    /// `interval`.
+    ///
+    /// Reporting ends early if writing to `out` fails.
+    pub fn spawn<T, E, W>(
+        alloc: &'static StatsAlloc<T>,
+        interval: Duration,
+        mut exporter: E,
+        mut out: W,
+    ) -> io::Result<Self>
+    where
+        T: GlobalAlloc + Sync,
+        E: StatsExporter + Send + 'static,
+        W: Write + Send + 'static,
+    {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = Arc::clone(&stop);
+        let thread = thread::Builder::new()
+            .name("stats_alloc-reporter".to_owned())
+            .spawn(move || {
+                let (ref stopped, ref condvar) = *signal;
+                let mut previous = alloc.stats();
+                let mut last = Instant::now();
+                if exporter.begin(&mut out).is_err() {
+                    return;
+                }
+                let mut guard = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                loop {
+                    guard = condvar
+                        .wait_timeout_while(guard, interval, |stopped| !*stopped)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                    if *guard {
+                        let _ = exporter.finish(&mut out).and_then(|_| out.flush());
+                        return;
+                    }
+                    let now = Instant::now();
+                    let snapshot = Snapshot {
+                        stats: alloc.stats(),
+                        previous,
+                        elapsed: now - last,
+                        time: SystemTime::now(),
+                    };
+                    if exporter.export(&mut out, &snapshot).and_then(|_| out.flush()).is_err() {
+                        return;
+                    }
+                    previous = snapshot.stats;
+                    last = now;
+                }
+            })?;
+        Ok(Reporter {
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stops the reporter, waiting for its thread to finish.
+    pub fn stop(self) {}
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        let (ref stopped, ref condvar) = *self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        condvar.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The template used by [`ExitReport::stderr`].
+const EXIT_FORMAT: &str = "stats_alloc: {allocations} allocs, {deallocations} deallocs, {reallocations} reallocs, \
+                           {bytes_allocated} B allocated, {bytes_deallocated} B deallocated";
+
+/// A guard which exports a final snapshot of allocator statistics when it is
+/// dropped, typically held for the whole of `main`.
+///
+/// The snapshot's previous statistics are those at the time the guard was
+/// created, so rates in the exported record cover the guard's lifetime.
+/// Nothing is exported if the guard is dropped while panicking, and errors
+/// while exporting are ignored.
+///
+/// ```
+/// # use stats_alloc::{ExitReport, INSTRUMENTED_SYSTEM};
+/// let _report = ExitReport::stderr(&INSTRUMENTED_SYSTEM);
+/// // ... the rest of `main`
+/// ```
+pub struct ExitReport<T: GlobalAlloc + 'static> {
+    alloc: &'static StatsAlloc<T>,
+    initial: Stats,
+    started: Instant,
+    exporter: Box<dyn StatsExporter>,
+    out: Box<dyn Write>,
+}
+
+impl<T: GlobalAlloc + 'static> ExitReport<T> {
+    /// Creates a guard which exports the statistics of `alloc` to `out` when
+    /// dropped.
+    pub fn new<E, W>(alloc: &'static StatsAlloc<T>, exporter: E, out: W) -> Self
+    where
+        E: StatsExporter + 'static,
+        W: Write + 'static,
+    {
+        ExitReport {
+            alloc,
+            initial: alloc.stats(),
+            started: Instant::now(),
+            exporter: Box::new(exporter),
+            out: Box::new(out),
+        }
+    }
+
+    /// Creates a guard which writes a one-line summary of the statistics of
+    /// `alloc` to standard error when dropped.
+    pub fn stderr(alloc: &'static StatsAlloc<T>) -> Self {
+        let template = Template::parse(EXIT_FORMAT).expect("exit template is valid");
+        ExitReport::new(alloc, template, io::stderr())
+    }
+}
+
+impl<T: GlobalAlloc + 'static> Drop for ExitReport<T> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+        let snapshot = Snapshot {
+            stats: self.alloc.stats(),
+            previous: self.initial,
+            elapsed: self.started.elapsed(),
+            time: SystemTime::now(),
+        };
+        let (exporter, out) = (&mut self.exporter, &mut self.out);
+        let _ = exporter
+            .begin(out)
+            .and_then(|_| exporter.export(out, &snapshot))
+            .and_then(|_| exporter.finish(out))
+            .and_then(|_| out.flush());
+    }
+}
+
+impl<T: GlobalAlloc + 'static> fmt::Debug for ExitReport<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExitReport")
+            .field("initial", &self.initial)
+            .field("started", &self.started)
+            .finish()
+    }
+}
@@ -0,0 +1,31 @@
+//! Random selection of the operations counted while sampling.
+
+use std::cell::Cell;
+
+thread_local! {
+    /// State of the current thread's xorshift generator, or zero until it is
+    /// first seeded.
+    static STATE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Returns `true` with a probability of one in `every`, or `None` if the
+/// thread is being torn down and can no longer be sampled.
+#[inline]
+pub(crate) fn sampled(every: usize) -> Option<bool> {
+    STATE
+        .try_with(|state| {
+            let mut x = state.get();
+            if x == 0 {
+                // Seed each thread differently, from the address of its own
+                // state, so that threads do not select in lockstep.
+                let address: *const Cell<u64> = state;
+                x = (address as u64 | 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            }
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            state.set(x);
+            x % every as u64 == 0
+        })
+        .ok()
+}
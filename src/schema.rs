@@ -0,0 +1,128 @@
+//! A stable, versioned JSON format for allocation measurement results.
+//!
+//! Results are written as a single JSON document of the following shape,
+//! with one entry in `counters` for every field of [`Stats`]:
+//!
+//! ```json
+//! {
+//!   "schema": "stats_alloc.results",
+//!   "version": 1,
+//!   "results": [
+//!     {
+//!       "id": "parse_small_document",
+//!       "iterations": 100,
+//!       "counters": { "allocations": 1200, "deallocations": 1200, ... }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Counters are totals across all iterations. Within a version, fields are
+//! only ever added, so consumers should ignore keys they do not recognize.
+
+use std::io::{self, Write};
+
+use crate::{Measurement, Stats, STATS_FIELDS};
+
+/// The name identifying documents written in this format.
+pub const RESULTS_SCHEMA: &str = "stats_alloc.results";
+
+/// The version of the results format written by [`write_results`].
+pub const RESULTS_SCHEMA_VERSION: u32 = 1;
+
+/// The allocation measurement of a single test or benchmark.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MeasurementResult {
+    /// Identifier of the test or benchmark which was measured
+    pub id: String,
+    /// Number of iterations included in the counters
+    pub iterations: u64,
+    /// Change in allocation statistics over all iterations
+    pub stats: Stats,
+}
+
+impl MeasurementResult {
+    /// Creates a result for a single iteration of the identified measurement.
+    pub fn new(id: &str, stats: Stats) -> Self {
+        MeasurementResult {
+            id: id.to_owned(),
+            iterations: 1,
+            stats,
+        }
+    }
+}
+
+impl<'a> From<&'a Measurement> for MeasurementResult {
+    fn from(measurement: &'a Measurement) -> Self {
+        MeasurementResult::new(&measurement.name, measurement.stats)
+    }
+}
+
+/// Writes the given results to `out` as a versioned JSON document.
+pub fn write_results<W: Write>(mut out: W, results: &[MeasurementResult]) -> io::Result<()> {
+    write!(out, "{{\"schema\":")?;
+    write_json_str(&mut out, RESULTS_SCHEMA)?;
+    write!(out, ",\"version\":{},\"results\":[", RESULTS_SCHEMA_VERSION)?;
+    for (i, result) in results.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(b"{\"id\":")?;
+        write_json_str(&mut out, &result.id)?;
+        write!(out, ",\"iterations\":{},\"counters\":", result.iterations)?;
+        write_counters(&mut out, &result.stats)?;
+        out.write_all(b"}")?;
+    }
+    out.write_all(b"]}")
+}
+
+impl Stats {
+    /// Returns every field as a JSON object of integers keyed by field name,
+    /// in declaration order, as found in the `counters` of the results format.
+    ///
+    /// For periodic snapshots, use a [`Reporter`] with a
+    /// [`JsonLinesExporter`], which writes such an object on each line.
+    ///
+    /// ```
+    /// # use stats_alloc::Stats;
+    /// let json = Stats::builder().allocations(3).build().to_json();
+    /// assert!(json.starts_with("{\"allocations\":3,\"deallocations\":0,"));
+    /// ```
+    ///
+    /// [`Reporter`]: crate::Reporter
+    /// [`JsonLinesExporter`]: crate::JsonLinesExporter
+    pub fn to_json(&self) -> String {
+        let mut out = Vec::new();
+        write_counters(&mut out, self).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("counters are ASCII")
+    }
+}
+
+/// Writes every field of `stats` as a JSON object.
+pub(crate) fn write_counters<W: Write + ?Sized>(out: &mut W, stats: &Stats) -> io::Result<()> {
+    out.write_all(b"{")?;
+    for (i, &(name, value)) in STATS_FIELDS.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        write!(out, "\"{}\":{}", name, value(stats))?;
+    }
+    out.write_all(b"}")
+}
+
+/// Writes `s` as a quoted and escaped JSON string.
+pub(crate) fn write_json_str<W: Write + ?Sized>(out: &mut W, s: &str) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => out.write_all(b"\\\"")?,
+            '\\' => out.write_all(b"\\\\")?,
+            '\n' => out.write_all(b"\\n")?,
+            '\r' => out.write_all(b"\\r")?,
+            '\t' => out.write_all(b"\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    out.write_all(b"\"")
+}
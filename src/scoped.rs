@@ -0,0 +1,88 @@
+//! Thread-scoped redirection of allocations to an alternative backing
+//! allocator.
+
+use std::{alloc::GlobalAlloc, cell::Cell, fmt, marker::PhantomData};
+
+/// A backing allocator which can temporarily take over the allocations made
+/// through a [`StatsAlloc`](crate::StatsAlloc) on the current thread.
+///
+/// # Safety
+///
+/// `owns` must return `true` for every live pointer handed out by this
+/// allocator, and `false` for any pointer that it did not allocate.
+pub unsafe trait ScopedAlloc: GlobalAlloc + Sync {
+    /// Returns `true` if `ptr` was allocated by this allocator.
+    fn owns(&self, ptr: *mut u8) -> bool;
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<&'static dyn ScopedAlloc>> = const { Cell::new(None) };
+}
+
+/// Returns the backing allocator overriding the current thread, if any.
+#[inline]
+pub(crate) fn current() -> Option<&'static dyn ScopedAlloc> {
+    CURRENT.try_with(Cell::get).ok().flatten()
+}
+
+/// Returns the overriding allocator if it owns `ptr`.
+#[inline]
+pub(crate) fn owner_of(ptr: *mut u8) -> Option<&'static dyn ScopedAlloc> {
+    current().filter(|alloc| alloc.owns(ptr))
+}
+
+/// Runs `f` with the current thread's override suspended, so that a backing
+/// allocator may itself allocate through the global allocator without
+/// recursing into itself.
+#[inline]
+pub(crate) fn suspended<R, F: FnOnce() -> R>(f: F) -> R {
+    let previous = CURRENT.try_with(Cell::take).ok().flatten();
+    let result = f();
+    let _ = CURRENT.try_with(|current| current.set(previous));
+    result
+}
+
+/// A guard which routes new allocations made on the current thread to an
+/// alternative backing allocator until it is dropped.
+///
+/// Statistics continue to be recorded by the instrumenting allocator, so a
+/// phase of a program can be moved onto an arena without rewriting each of its
+/// call sites. Allocations which existed before the guard was created continue
+/// to be released to the original allocator.
+pub struct ScopedAllocGuard {
+    previous: Option<&'static dyn ScopedAlloc>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ScopedAllocGuard {
+    /// Routes allocations made on the current thread to `alloc` while the
+    /// returned guard is alive.
+    ///
+    /// # Safety
+    ///
+    /// Memory obtained from `alloc` while the guard is active must either be
+    /// freed on this thread before the guard is dropped, or never be freed
+    /// through the global allocator at all (as is common with arenas which are
+    /// released wholesale). Freeing it later would hand it to the original
+    /// backing allocator.
+    pub unsafe fn new(alloc: &'static dyn ScopedAlloc) -> Self {
+        ScopedAllocGuard {
+            previous: CURRENT.with(|current| current.replace(Some(alloc))),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl fmt::Debug for ScopedAllocGuard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ScopedAllocGuard")
+            .field("nested", &self.previous.is_some())
+            .finish()
+    }
+}
+
+impl Drop for ScopedAllocGuard {
+    fn drop(&mut self) {
+        let _ = CURRENT.try_with(|current| current.set(self.previous));
+    }
+}
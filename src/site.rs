@@ -0,0 +1,744 @@
+//! Aggregation of allocations by the call site which made them.
+
+use std::{
+    alloc::GlobalAlloc,
+    cell::Cell,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    table::{hash_address, Keyed, Locked, Log, Table},
+    CallStack, StatsAlloc,
+};
+
+/// Number of innermost frames identifying a call site, enough to reach past
+/// the allocator and the collection which called it.
+///
+/// The nearest return addresses alone are within the allocator shims and
+/// `alloc`, so they would attribute nearly every allocation to the same few
+/// sites. Without frame pointers, which Rust omits by default, reaching the
+/// caller needs the unwinder. See the `frame_pointers` module for the walk
+/// used when they are kept.
+const SITE_FRAMES: usize = 12;
+
+/// Number of independently locked parts of each table.
+const SHARDS: usize = 16;
+
+/// The statistics of the allocations made from one call site.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct CallSite {
+    /// Innermost frames of the call stack identifying the site
+    pub stack: CallStack,
+    /// Number of allocations made from the site
+    pub allocations: u64,
+    /// Number of reallocations made from the site
+    pub reallocations: u64,
+    /// Number of bytes allocated from the site, including the growth of
+    /// reallocated blocks
+    pub bytes_allocated: u64,
+    /// Number of blocks allocated from the site which are still outstanding
+    pub live_allocations: u64,
+    /// Number of bytes in blocks allocated from the site which are still
+    /// outstanding, following any reallocation of them
+    pub bytes_current: u64,
+    /// Highest value of `bytes_current` at any one time
+    pub peak_bytes: u64,
+    /// Total time for which blocks allocated from the site have been
+    /// outstanding, counting those not yet freed up to now
+    pub total_lifetime: Duration,
+}
+
+/// Resolves the site to symbols, in the style of a standard library
+/// backtrace, below a line of its totals.
+impl fmt::Display for CallSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} bytes in {} allocations and {} reallocations at:",
+            self.bytes_allocated, self.allocations, self.reallocations
+        )?;
+        write!(f, "{}", self.stack)
+    }
+}
+
+thread_local! {
+    /// Set while the current thread writes out what has been recorded, whose
+    /// own allocations are not.
+    static IGNORED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` without attributing the current thread's allocations to call
+/// sites.
+pub(crate) fn ignoring<R, F: FnOnce() -> R>(f: F) -> R {
+    let previous = IGNORED.with(|ignored| ignored.replace(true));
+    let result = f();
+    IGNORED.with(|ignored| ignored.set(previous));
+    result
+}
+
+/// Returns the number of microseconds since the first call.
+pub(crate) fn now() -> u64 {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64
+}
+
+/// The totals of one call site, in a slot of the table which is empty when
+/// its innermost frame is zero.
+#[derive(Clone, Copy)]
+pub(crate) struct Site {
+    frames: [usize; SITE_FRAMES],
+    pub(crate) allocations: u64,
+    pub(crate) reallocations: u64,
+    pub(crate) bytes_allocated: u64,
+    pub(crate) live_allocations: u64,
+    pub(crate) bytes_current: u64,
+    pub(crate) peak_bytes: u64,
+    /// Value of `live_allocations` when `peak_bytes` was reached
+    pub(crate) peak_live_allocations: u64,
+    /// Value of `bytes_current` when the bytes outstanding from all sites
+    /// last peaked
+    pub(crate) global_peak_bytes: u64,
+    /// Value of `live_allocations` when the bytes outstanding from all sites
+    /// last peaked
+    pub(crate) global_peak_live_allocations: u64,
+    /// Total lifetime of the blocks freed, in microseconds
+    lifetimes: u64,
+    /// Total of the times at which the outstanding blocks were allocated
+    births: u64,
+}
+
+/// Returns the frames captured, without the unused entries past the
+/// outermost frame of a short stack.
+fn trim(frames: &[usize; SITE_FRAMES]) -> &[usize] {
+    let len = frames.iter().position(|&ip| ip == 0).unwrap_or(SITE_FRAMES);
+    &frames[..len]
+}
+
+impl Site {
+    /// Returns the innermost frames of the site.
+    pub(crate) fn frames(&self) -> &[usize] {
+        trim(&self.frames)
+    }
+
+    /// Returns the total lifetime of the site's blocks up to `now`, in
+    /// microseconds.
+    pub(crate) fn total_lifetime(&self, now: u64) -> u64 {
+        (self.lifetimes + self.live_allocations * now).saturating_sub(self.births)
+    }
+
+    fn add_block(&mut self, size: usize, born: u64) {
+        self.live_allocations += 1;
+        self.births += born;
+        self.resize_block(0, size);
+    }
+
+    fn remove_block(&mut self, size: usize, born: u64, now: u64) {
+        self.live_allocations = self.live_allocations.saturating_sub(1);
+        self.births = self.births.saturating_sub(born);
+        self.lifetimes += now.saturating_sub(born);
+        self.resize_block(size, 0);
+    }
+
+    fn resize_block(&mut self, old_size: usize, new_size: usize) {
+        self.bytes_current = (self.bytes_current + new_size as u64).saturating_sub(old_size as u64);
+        if self.bytes_current >= self.peak_bytes {
+            self.peak_bytes = self.bytes_current;
+            self.peak_live_allocations = self.live_allocations;
+        }
+    }
+
+    fn to_call_site(self, now: u64) -> CallSite {
+        CallSite {
+            stack: CallStack::from_frames(self.frames()),
+            allocations: self.allocations,
+            reallocations: self.reallocations,
+            bytes_allocated: self.bytes_allocated,
+            live_allocations: self.live_allocations,
+            bytes_current: self.bytes_current,
+            peak_bytes: self.peak_bytes,
+            total_lifetime: Duration::from_micros(self.total_lifetime(now)),
+        }
+    }
+}
+
+impl Keyed for Site {
+    type Key = [usize; SITE_FRAMES];
+
+    fn vacant() -> Self {
+        Site {
+            frames: [0; SITE_FRAMES],
+            allocations: 0,
+            reallocations: 0,
+            bytes_allocated: 0,
+            live_allocations: 0,
+            bytes_current: 0,
+            peak_bytes: 0,
+            peak_live_allocations: 0,
+            global_peak_bytes: 0,
+            global_peak_live_allocations: 0,
+            lifetimes: 0,
+            births: 0,
+        }
+    }
+
+    fn vacate(&mut self) {
+        self.frames[0] = 0;
+    }
+
+    fn is_vacant(&self) -> bool {
+        self.frames[0] == 0
+    }
+
+    fn key(&self) -> Self::Key {
+        self.frames
+    }
+
+    fn hash(frames: &Self::Key) -> u64 {
+        frames.iter().fold(0, |hash: u64, &ip| {
+            (hash ^ ip as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        })
+    }
+}
+
+/// An outstanding block and the site which allocated it, in a slot of the
+/// table which is empty when `address` is zero.
+#[derive(Clone, Copy)]
+struct Block {
+    address: usize,
+    size: usize,
+    born: u64,
+    frames: [usize; SITE_FRAMES],
+}
+
+impl Keyed for Block {
+    type Key = usize;
+
+    fn vacant() -> Self {
+        Block {
+            address: 0,
+            size: 0,
+            born: 0,
+            frames: [0; SITE_FRAMES],
+        }
+    }
+
+    fn vacate(&mut self) {
+        self.address = 0;
+    }
+
+    fn is_vacant(&self) -> bool {
+        self.address == 0
+    }
+
+    fn key(&self) -> usize {
+        self.address
+    }
+
+    fn hash(&address: &usize) -> u64 {
+        hash_address(address)
+    }
+}
+
+/// The allocation or release of a block, logged while events are recorded.
+#[derive(Clone, Copy)]
+pub(crate) struct Event {
+    /// Time of the event, in microseconds
+    pub(crate) time: u64,
+    pub(crate) size: usize,
+    pub(crate) freed: bool,
+    /// Frames of the site which allocated the block
+    frames: [usize; SITE_FRAMES],
+}
+
+impl Event {
+    /// Returns the innermost frames of the site which allocated the block.
+    pub(crate) fn frames(&self) -> &[usize] {
+        trim(&self.frames)
+    }
+}
+
+/// Captures the innermost frames of the calling thread's stack.
+#[cfg(all(feature = "frame-pointers", any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+fn capture() -> [usize; SITE_FRAMES] {
+    frame_pointers::capture()
+}
+
+/// Captures the innermost frames of the calling thread's stack.
+#[cfg(not(all(feature = "frame-pointers", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+fn capture() -> [usize; SITE_FRAMES] {
+    let mut frames = [0; SITE_FRAMES];
+    let mut len = 0;
+    // As for `CallStack::capture`, no lock is taken to unwind.
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            frames[len] = frame.ip() as usize;
+            len += 1;
+            len < SITE_FRAMES
+        });
+    }
+    frames
+}
+
+/// Capture of call sites by following the chain of frame records, which is
+/// only kept when the program is built with `-C force-frame-pointers=yes`.
+///
+/// Each record holds the caller's record and the return address, so walking
+/// twelve frames is twelve pairs of loads rather than a search of the unwind
+/// tables for each. A function built without frame pointers breaks the
+/// chain, so every record is checked to lie further up the stack than the
+/// last and below the outermost frame of the thread, which the unwinder
+/// finds once for each thread. A broken chain then only misattributes the
+/// allocation, and cannot read outside the stack.
+#[cfg(all(feature = "frame-pointers", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod frame_pointers {
+    use std::{arch::asm, cell::Cell};
+
+    use super::SITE_FRAMES;
+
+    thread_local! {
+        /// The highest address of the current thread's stack in use, or
+        /// zero if not yet found.
+        static STACK_END: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Finds the end of the current thread's stack as the highest stack
+    /// pointer of any frame.
+    fn stack_end() -> usize {
+        let mut end = 0;
+        // As for `CallStack::capture`, no lock is taken to unwind.
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                end = end.max(frame.sp() as usize);
+                true
+            });
+        }
+        end
+    }
+
+    #[inline(always)]
+    fn frame_pointer() -> usize {
+        let fp: usize;
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack, preserves_flags));
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags));
+        }
+        fp
+    }
+
+    /// Captures the return addresses of the innermost frames of the calling
+    /// thread's stack.
+    #[inline(never)]
+    pub(super) fn capture() -> [usize; SITE_FRAMES] {
+        let end = STACK_END.with(|end| {
+            if end.get() == 0 {
+                end.set(stack_end());
+            }
+            end.get()
+        });
+        let mut frames = [0; SITE_FRAMES];
+        let mut fp = frame_pointer();
+        for frame in frames.iter_mut() {
+            if !fp.is_multiple_of(align_of::<usize>()) || fp.saturating_add(2 * size_of::<usize>()) > end {
+                break;
+            }
+            let record = fp as *const usize;
+            // The record lies between this frame and the end of the stack.
+            let (next, ip) = unsafe { (*record, *record.add(1)) };
+            if ip == 0 {
+                break;
+            }
+            *frame = ip;
+            if next <= fp {
+                break;
+            }
+            fp = next;
+        }
+        frames
+    }
+}
+
+// Only used to initialize the arrays of shards, each as a distinct value.
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_SITES: Locked<Table<Site>> = Locked::new(Table::EMPTY);
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY_BLOCKS: Locked<Table<Block>> = Locked::new(Table::EMPTY);
+
+/// The totals of every site, as read from a [`SiteTable`].
+pub(crate) struct Profile {
+    pub(crate) sites: Vec<Site>,
+    /// Time at which attribution was enabled, in microseconds
+    pub(crate) started: u64,
+    /// Time at which the profile was read, in microseconds
+    pub(crate) ended: u64,
+    /// Time at which the bytes outstanding last peaked, in microseconds
+    pub(crate) peaked: u64,
+}
+
+/// The allocations of a `StatsAlloc` aggregated by call site, updated while
+/// attribution is enabled.
+pub(crate) struct SiteTable {
+    enabled: AtomicBool,
+    started: AtomicU64,
+    bytes_current: AtomicU64,
+    peak_bytes: AtomicU64,
+    peaked: AtomicU64,
+    /// Set from when the bytes outstanding reach a new peak until they next
+    /// decrease, which is when each site's share of the peak is recorded.
+    at_peak: AtomicBool,
+    sites: [Locked<Table<Site>>; SHARDS],
+    blocks: [Locked<Table<Block>>; SHARDS],
+    /// Time from which the events of blocks allocated since are logged, or
+    /// `u64::MAX` if they are not.
+    logged_since: AtomicU64,
+    events: Locked<Log<Event>>,
+}
+
+impl SiteTable {
+    pub(crate) const fn new() -> Self {
+        SiteTable {
+            enabled: AtomicBool::new(false),
+            started: AtomicU64::new(0),
+            bytes_current: AtomicU64::new(0),
+            peak_bytes: AtomicU64::new(0),
+            peaked: AtomicU64::new(0),
+            at_peak: AtomicBool::new(false),
+            sites: [EMPTY_SITES; SHARDS],
+            blocks: [EMPTY_BLOCKS; SHARDS],
+            logged_since: AtomicU64::new(u64::MAX),
+            events: Locked::new(Log::EMPTY),
+        }
+    }
+
+    #[inline]
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            if !self.is_enabled() {
+                let started = now();
+                self.started.store(started, Ordering::Relaxed);
+                self.bytes_current.store(0, Ordering::Relaxed);
+                self.peak_bytes.store(0, Ordering::Relaxed);
+                self.peaked.store(started, Ordering::Relaxed);
+                self.at_peak.store(false, Ordering::Relaxed);
+                self.enabled.store(true, Ordering::Relaxed);
+            }
+        } else {
+            self.enabled.store(false, Ordering::Relaxed);
+            self.set_logging(false);
+            self.take_events().release();
+            for shard in &self.blocks {
+                shard.with(|table| std::mem::replace(table, Table::EMPTY)).release();
+            }
+            for shard in &self.sites {
+                shard.with(|table| std::mem::replace(table, Table::EMPTY)).release();
+            }
+        }
+    }
+
+    /// Selects whether the allocation and release of each block allocated
+    /// from now on is logged.
+    pub(crate) fn set_logging(&self, enabled: bool) {
+        if enabled {
+            let _ = self
+                .logged_since
+                .compare_exchange(u64::MAX, now(), Ordering::Relaxed, Ordering::Relaxed);
+        } else {
+            self.logged_since.store(u64::MAX, Ordering::Relaxed);
+        }
+    }
+
+    /// Takes the events logged so far, whose storage must be released.
+    pub(crate) fn take_events(&self) -> Log<Event> {
+        self.events.with(|log| std::mem::replace(log, Log::EMPTY))
+    }
+
+    /// Logs an event for `block` if it was allocated while logging.
+    fn log(&self, block: &Block, size: usize, freed: bool, time: u64) {
+        if block.born >= self.logged_since.load(Ordering::Relaxed) {
+            let event = Event {
+                time,
+                size,
+                freed,
+                frames: block.frames,
+            };
+            // Events are dropped if the log cannot grow to hold them.
+            let _ = self.events.with(|log| log.push(event));
+        }
+    }
+
+    /// Updates the totals of the site with `frames`, unless the table cannot
+    /// grow to hold it.
+    fn update_site<F: FnOnce(&mut Site)>(&self, frames: [usize; SITE_FRAMES], f: F) {
+        let shard = &self.sites[(Site::hash(&frames) >> 60) as usize % SHARDS];
+        shard.with(|table| {
+            if let Some(site) = table.get_or_insert_with(frames, || Site {
+                frames,
+                ..Site::vacant()
+            }) {
+                f(site);
+            }
+        });
+    }
+
+    fn blocks(&self, address: usize) -> &Locked<Table<Block>> {
+        &self.blocks[(hash_address(address) >> 60) as usize % SHARDS]
+    }
+
+    /// Inserts `block` into its shard, freeing whichever block is not kept.
+    fn put_block(&self, block: Block) {
+        if let Some(discarded) = self.blocks(block.address).with(|table| table.insert(block)) {
+            self.free_block(discarded, now());
+        }
+    }
+
+    fn take_block(&self, address: usize) -> Option<Block> {
+        self.blocks(address).with(|table| table.remove(&address))
+    }
+
+    fn free_block(&self, block: Block, now: u64) {
+        self.log(&block, block.size, true, now);
+        self.shrink_heap(block.size as u64);
+        self.update_site(block.frames, |site| site.remove_block(block.size, block.born, now));
+    }
+
+    fn grow_heap(&self, bytes: u64, now: u64) {
+        let current = self.bytes_current.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if current > self.peak_bytes.fetch_max(current, Ordering::Relaxed) {
+            self.peaked.store(now, Ordering::Relaxed);
+            self.at_peak.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Subtracts `bytes` from the bytes outstanding, first recording each
+    /// site's share of the peak if they are about to leave it.
+    fn shrink_heap(&self, bytes: u64) {
+        if self.at_peak.swap(false, Ordering::Relaxed) {
+            for shard in &self.sites {
+                shard.with(|table| {
+                    for site in table.iter_mut() {
+                        site.global_peak_bytes = site.bytes_current;
+                        site.global_peak_live_allocations = site.live_allocations;
+                    }
+                });
+            }
+        }
+        self.bytes_current.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Records an allocation of `size` bytes at `ptr` by the calling site.
+    #[inline]
+    pub(crate) fn record_alloc(&self, ptr: *mut u8, size: usize) {
+        if !self.is_enabled() || ptr.is_null() || IGNORED.try_with(Cell::get).unwrap_or(true) {
+            return;
+        }
+        let frames = capture();
+        // Nothing is recorded in the unlikely event that unwinding fails.
+        if frames[0] == 0 {
+            return;
+        }
+        let born = now();
+        self.grow_heap(size as u64, born);
+        self.update_site(frames, |site| {
+            site.allocations += 1;
+            site.bytes_allocated += size as u64;
+            site.add_block(size, born);
+        });
+        let block = Block {
+            address: ptr as usize,
+            size,
+            born,
+            frames,
+        };
+        self.log(&block, size, false, born);
+        self.put_block(block);
+    }
+
+    /// Records the release of the block at `ptr`.
+    #[inline]
+    pub(crate) fn record_dealloc(&self, ptr: *mut u8) {
+        if self.is_enabled() {
+            if let Some(block) = self.take_block(ptr as usize) {
+                self.free_block(block, now());
+            }
+        }
+    }
+
+    /// Records a reallocation by the calling site of the block at `ptr` from
+    /// `old_size` to `new_size` bytes at `new_ptr`.
+    ///
+    /// The reallocation is counted for the calling site, while the block
+    /// stays with the site which first allocated it.
+    #[inline]
+    pub(crate) fn record_realloc(&self, ptr: *mut u8, new_ptr: *mut u8, old_size: usize, new_size: usize) {
+        if !self.is_enabled() || new_ptr.is_null() || IGNORED.try_with(Cell::get).unwrap_or(true) {
+            return;
+        }
+        let frames = capture();
+        if frames[0] == 0 {
+            return;
+        }
+        let now = now();
+        self.update_site(frames, |site| {
+            site.reallocations += 1;
+            site.bytes_allocated += new_size.saturating_sub(old_size) as u64;
+        });
+        let block = match self.take_block(ptr as usize) {
+            Some(block) => {
+                if new_size < block.size {
+                    self.shrink_heap((block.size - new_size) as u64);
+                } else {
+                    self.grow_heap((new_size - block.size) as u64, now);
+                }
+                self.update_site(block.frames, |site| site.resize_block(block.size, new_size));
+                self.log(&block, block.size, true, now);
+                self.log(&block, new_size, false, now);
+                Block {
+                    address: new_ptr as usize,
+                    size: new_size,
+                    ..block
+                }
+            },
+            // The block was allocated before attribution was enabled, so it
+            // is taken to be allocated here.
+            None => {
+                self.grow_heap(new_size as u64, now);
+                self.update_site(frames, |site| site.add_block(new_size, now));
+                let block = Block {
+                    address: new_ptr as usize,
+                    size: new_size,
+                    born: now,
+                    frames,
+                };
+                self.log(&block, new_size, false, now);
+                block
+            },
+        };
+        self.put_block(block);
+    }
+
+    /// Copies the totals of every site.
+    pub(crate) fn profile(&self) -> Profile {
+        let mut sites = Vec::new();
+        for shard in &self.sites {
+            // The vector is grown outside the lock, since growing it may
+            // itself be recorded in this very shard.
+            loop {
+                let needed = shard.with(|table| table.len());
+                sites.reserve(needed);
+                let copied = shard.with(|table| {
+                    if sites.capacity() - sites.len() < table.len() {
+                        return false;
+                    }
+                    sites.extend(table.iter().copied());
+                    true
+                });
+                if copied {
+                    break;
+                }
+            }
+        }
+        // The shares of a peak which has not yet been left are the current
+        // totals.
+        if self.at_peak.load(Ordering::Relaxed) {
+            for site in &mut sites {
+                site.global_peak_bytes = site.bytes_current;
+                site.global_peak_live_allocations = site.live_allocations;
+            }
+        }
+        Profile {
+            sites,
+            started: self.started.load(Ordering::Relaxed),
+            ended: now(),
+            peaked: self.peaked.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Debug for SiteTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SiteTable")
+            .field("enabled", &self.is_enabled())
+            .field(
+                "len",
+                &self
+                    .sites
+                    .iter()
+                    .map(|shard| shard.with(|table| table.len()))
+                    .sum::<usize>(),
+            )
+            .finish()
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Returns `true` if allocations are being attributed to call sites.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn call_site_tracking(&self) -> bool {
+        self.sites.is_enabled()
+    }
+
+    /// Selects whether allocations and reallocations are attributed to the
+    /// site which made them, so that [`call_sites`](StatsAlloc::call_sites)
+    /// can rank where memory is allocated.
+    ///
+    /// A site is identified by the innermost twelve frames of its call stack,
+    /// which are only resolved to symbols once a [`CallSite`] is displayed.
+    /// The frames are still unwound on every allocation and reallocation,
+    /// using the unwind tables as Rust omits frame pointers by default. On
+    /// Linux this costs a few microseconds per allocation, making allocation
+    /// some twenty times slower, so attribution suits profiling runs rather
+    /// than production. It is nonetheless cheaper than capturing whole call
+    /// stacks, which also resolves every frame.
+    ///
+    /// With the `frame-pointers` feature, on x86-64 and AArch64, the frames
+    /// are instead found by following frame pointers, and attribution costs a
+    /// few hundred nanoseconds per allocation rather than microseconds. The
+    /// program, including the standard library where it
+    /// allocates, must then be built with `-C force-frame-pointers=yes`, or
+    /// allocations are attributed to the wrong sites. The unwinder is then
+    /// only used once for each thread.
+    ///
+    /// The totals of each site, and the site of each outstanding block, are
+    /// kept in tables whose storage comes from the system allocator, and
+    /// every deallocation takes a lock on part of the table of blocks.
+    /// Disabling attribution discards both tables.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn set_call_site_tracking(&self, enabled: bool) {
+        self.sites.set_enabled(enabled);
+    }
+
+    /// Returns the sites which have allocated since attribution was enabled,
+    /// those which allocated the most bytes first.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// let alloc = StatsAlloc::system();
+    /// alloc.set_call_site_tracking(true);
+    /// let layout = Layout::new::<[u8; 48]>();
+    /// let ptr = unsafe { alloc.alloc(layout) };
+    /// assert_eq!(alloc.call_sites()[0].bytes_allocated, 48);
+    /// # unsafe { alloc.dealloc(ptr, layout) };
+    /// ```
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn call_sites(&self) -> Vec<CallSite> {
+        let Profile { sites, ended, .. } = self.sites.profile();
+        let mut sites: Vec<_> = sites.into_iter().map(|site| site.to_call_site(ended)).collect();
+        sites.sort_by_key(|site| std::cmp::Reverse(site.bytes_allocated));
+        sites
+    }
+}
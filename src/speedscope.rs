@@ -0,0 +1,142 @@
+//! Output of call site profiles in the file format of the speedscope viewer.
+
+use std::{
+    alloc::GlobalAlloc,
+    collections::HashMap,
+    env,
+    io::{self, Write},
+};
+
+use crate::{schema::write_json_str, site::Site, stack::Resolver, StatsAlloc};
+
+/// The URL identifying the file format.
+const SCHEMA: &str = "https://www.speedscope.app/file-format-schema.json";
+
+/// The weight of a site in a profile.
+type Weight = fn(&Site) -> u64;
+
+/// The profiles written, by name, unit and the weight of a site in each.
+const PROFILES: &[(&str, &str, Weight)] = &[
+    ("bytes allocated", "bytes", |site| site.bytes_allocated),
+    ("bytes outstanding", "bytes", |site| site.bytes_current),
+    ("allocations", "none", |site| site.allocations),
+];
+
+/// The distinct frames of the profile, shared by every sample.
+#[derive(Default)]
+struct FrameTable {
+    /// Name and file of each frame
+    frames: Vec<(String, Option<String>)>,
+    indices: HashMap<(String, Option<String>), usize>,
+    resolver: Resolver,
+}
+
+impl FrameTable {
+    /// Returns the frames of a call stack, outermost first as speedscope
+    /// expects, without the frames of the allocator itself.
+    fn stack(&mut self, frames: &[usize]) -> Vec<usize> {
+        let FrameTable {
+            frames: table,
+            indices,
+            resolver,
+        } = self;
+        let mut stack: Vec<_> = resolver
+            .symbols(frames)
+            .into_iter()
+            .map(|(ip, (name, location))| {
+                let frame = (
+                    name.clone().unwrap_or_else(|| format!("{:#x}", ip)),
+                    location.as_ref().map(|(file, _)| file.clone()),
+                );
+                *indices.entry(frame).or_insert_with_key(|frame| {
+                    table.push(frame.clone());
+                    table.len() - 1
+                })
+            })
+            .collect();
+        stack.reverse();
+        stack
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Writes the allocations attributed to call sites as sampled profiles
+    /// in the file format of speedscope, for viewing in a browser at
+    /// <https://www.speedscope.app> without installing anything.
+    ///
+    /// Each call site is a sample, weighted in one profile by the bytes it
+    /// allocated, in another by the bytes it has outstanding and in a third
+    /// by its number of allocations. The left heavy and sandwich views then
+    /// show where memory is allocated. Symbols are resolved while writing,
+    /// which may take some time for a large profile.
+    ///
+    /// ```no_run
+    /// # use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
+    /// # use std::{alloc::System, fs::File};
+    /// #[global_allocator]
+    /// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+    ///
+    /// fn main() {
+    ///     GLOBAL.set_call_site_tracking(true);
+    ///     // ...
+    ///     let mut out = File::create("heap.speedscope.json").unwrap();
+    ///     GLOBAL.write_speedscope(&mut out).unwrap();
+    /// }
+    /// ```
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "backtrace")))]
+    pub fn write_speedscope(&self, out: &mut dyn Write) -> io::Result<()> {
+        let profile = self.sites.profile();
+        let mut frames = FrameTable::default();
+        let stacks: Vec<_> = profile.sites.iter().map(|site| frames.stack(site.frames())).collect();
+        let cmd: Vec<_> = env::args().collect();
+        write!(out, "{{\"$schema\":")?;
+        write_json_str(out, SCHEMA)?;
+        write!(out, ",\"name\":")?;
+        write_json_str(out, &cmd.join(" "))?;
+        writeln!(out, ",\"exporter\":\"stats_alloc {}\"", env!("CARGO_PKG_VERSION"))?;
+        write!(out, ",\"shared\":{{\"frames\":[")?;
+        for (i, (name, file)) in frames.frames.iter().enumerate() {
+            write!(out, "{}{{\"name\":", if i == 0 { "" } else { "," })?;
+            write_json_str(out, name)?;
+            if let Some(file) = file {
+                write!(out, ",\"file\":")?;
+                write_json_str(out, file)?;
+            }
+            write!(out, "}}")?;
+        }
+        writeln!(out, "]}}")?;
+        write!(out, ",\"profiles\":[")?;
+        for (i, &(name, unit, weight)) in PROFILES.iter().enumerate() {
+            let samples: Vec<_> = profile
+                .sites
+                .iter()
+                .zip(&stacks)
+                .map(|(site, stack)| (weight(site), stack))
+                .filter(|&(weight, _)| weight > 0)
+                .collect();
+            let total: u64 = samples.iter().map(|&(weight, _)| weight).sum();
+            writeln!(
+                out,
+                "{}{{\"type\":\"sampled\",\"name\":\"{}\",\"unit\":\"{}\",\"startValue\":0,\"endValue\":{}",
+                if i == 0 { "" } else { "," },
+                name,
+                unit,
+                total
+            )?;
+            write!(out, ",\"samples\":[")?;
+            for (j, (_, stack)) in samples.iter().enumerate() {
+                write!(out, "{}[", if j == 0 { "" } else { "," })?;
+                for (k, frame) in stack.iter().enumerate() {
+                    write!(out, "{}{}", if k == 0 { "" } else { "," }, frame)?;
+                }
+                write!(out, "]")?;
+            }
+            write!(out, "],\"weights\":[")?;
+            for (j, (weight, _)) in samples.iter().enumerate() {
+                write!(out, "{}{}", if j == 0 { "" } else { "," }, weight)?;
+            }
+            writeln!(out, "]}}")?;
+        }
+        writeln!(out, "],\"activeProfileIndex\":0}}")
+    }
+}
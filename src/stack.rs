@@ -0,0 +1,162 @@
+//! Call stacks captured at the site of an allocation.
+
+use std::{collections::HashMap, ffi::c_void, fmt};
+
+/// Greatest number of frames captured in a stack.
+const MAX_FRAMES: usize = 64;
+
+/// The call stack of an allocation, captured as the instruction pointer of
+/// each frame and only resolved to symbols when displayed.
+///
+/// The stack is captured without taking any lock, unlike
+/// `std::backtrace::Backtrace`, which holds a process-wide lock while it
+/// allocates and so cannot safely be captured within an allocator.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CallStack {
+    frames: Box<[usize]>,
+}
+
+impl CallStack {
+    /// Captures the stack of the calling thread, innermost frame first.
+    ///
+    /// The frames are collected before any memory is allocated to hold them.
+    pub(crate) fn capture() -> Self {
+        let mut frames = [0; MAX_FRAMES];
+        let mut len = 0;
+        // No lock is needed to unwind on the supported platforms, and taking
+        // the `backtrace` crate's own lock could deadlock if this thread
+        // already holds it while allocating.
+        unsafe {
+            backtrace::trace_unsynchronized(|frame| {
+                frames[len] = frame.ip() as usize;
+                len += 1;
+                len < MAX_FRAMES
+            });
+        }
+        CallStack {
+            frames: frames[..len].into(),
+        }
+    }
+
+    pub(crate) fn from_frames(frames: &[usize]) -> Self {
+        CallStack { frames: frames.into() }
+    }
+
+    /// Returns the instruction pointer of each frame, innermost first, from
+    /// the frames of the allocator itself out to the thread's entry point.
+    pub fn frames(&self) -> &[usize] {
+        &self.frames
+    }
+}
+
+impl fmt::Debug for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.frames.iter().map(|&ip| ip as *const c_void))
+            .finish()
+    }
+}
+
+/// A symbol of a resolved frame: its name and source location, where known.
+pub(crate) type Symbol = (Option<String>, Option<(String, u32)>);
+
+/// Resolves the frame at `ip` to its symbols, innermost inlined call first.
+pub(crate) fn resolve(ip: usize) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    backtrace::resolve(ip as *mut c_void, |symbol| {
+        let name = symbol.name().map(|name| format!("{:#}", name));
+        let location = symbol
+            .filename()
+            .map(|file| (file.display().to_string(), symbol.lineno().unwrap_or(0)));
+        symbols.push((name, location));
+    });
+    symbols
+}
+
+/// Returns `true` if a symbol belongs to the allocator or the code capturing
+/// the stack, rather than to its caller.
+fn in_allocator_symbol((name, _): &Symbol) -> bool {
+    name.as_ref().is_some_and(|name| {
+        name.starts_with("backtrace::")
+            || name.contains("stats_alloc::")
+            || name.starts_with("__rust")
+            || name.starts_with("__rdl")
+            || name.starts_with("alloc::alloc::")
+            || name.starts_with("<alloc::alloc::Global")
+            // The thread-local guards the allocator runs callbacks within
+            || name.starts_with("std::thread::local::")
+    })
+}
+
+/// Returns `true` if every symbol of a frame belongs to the allocator.
+pub(crate) fn in_allocator(symbols: &[Symbol]) -> bool {
+    symbols.iter().all(in_allocator_symbol)
+}
+
+/// Resolves the frames of many call stacks, resolving each instruction
+/// pointer only once.
+#[derive(Default)]
+pub(crate) struct Resolver {
+    resolved: HashMap<usize, Vec<Symbol>>,
+}
+
+impl Resolver {
+    /// Returns the symbols of `frames` with the instruction pointer of each,
+    /// innermost first, without the innermost symbols of the allocator unless
+    /// every symbol lies within it.
+    ///
+    /// A frame which cannot be resolved has one symbol with neither a name
+    /// nor a location.
+    pub(crate) fn symbols(&mut self, frames: &[usize]) -> Vec<(usize, &Symbol)> {
+        for &ip in frames {
+            self.resolved.entry(ip).or_insert_with(|| {
+                let symbols = resolve(ip);
+                if symbols.is_empty() {
+                    vec![(None, None)]
+                } else {
+                    symbols
+                }
+            });
+        }
+        let resolved = &self.resolved;
+        let mut symbols: Vec<_> = frames
+            .iter()
+            .flat_map(|ip| resolved[ip].iter().map(move |symbol| (*ip, symbol)))
+            .collect();
+        let skipped = symbols
+            .iter()
+            .take_while(|(_, symbol)| in_allocator_symbol(symbol))
+            .count();
+        if skipped < symbols.len() {
+            symbols.drain(..skipped);
+        }
+        symbols
+    }
+}
+
+/// Resolves each frame to its symbols and source locations, in the style of
+/// a standard library backtrace. Frames within the allocator are omitted.
+impl fmt::Display for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut in_alloc = true;
+        let mut index = 0;
+        for &ip in self.frames.iter() {
+            let symbols = resolve(ip);
+            in_alloc = in_alloc && in_allocator(&symbols);
+            if in_alloc {
+                continue;
+            }
+            if symbols.is_empty() {
+                writeln!(f, "{:4}: {:?}", index, ip as *const c_void)?;
+            }
+            for (name, location) in symbols {
+                writeln!(f, "{:4}: {}", index, name.as_deref().unwrap_or("<unknown>"))?;
+                if let Some((file, line)) = location {
+                    writeln!(f, "             at {}:{}", file, line)?;
+                }
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+}
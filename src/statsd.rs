@@ -0,0 +1,136 @@
+//! Reporting of allocator statistics to a StatsD server.
+
+use std::{
+    alloc::GlobalAlloc,
+    io::{self, Write},
+    net::{ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use crate::{export::is_gauge, Reporter, Snapshot, StatsAlloc, StatsExporter, STATS_FIELDS};
+
+/// The largest datagram sent, chosen to fit within a typical Ethernet MTU.
+const MAX_DATAGRAM: usize = 1_432;
+
+/// Exports snapshots as StatsD metrics, one line per field of [`Stats`].
+///
+/// Fields which only increase are sent as counters of their change since the
+/// previous snapshot, and those which may decrease as gauges of their current
+/// value.
+///
+/// [`Stats`]: crate::Stats
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatsdExporter {
+    prefix: String,
+}
+
+impl StatsdExporter {
+    /// Creates an exporter whose metric names begin with `prefix` followed by
+    /// a period.
+    pub fn new(prefix: &str) -> Self {
+        StatsdExporter {
+            prefix: prefix.to_owned(),
+        }
+    }
+}
+
+impl Default for StatsdExporter {
+    fn default() -> Self {
+        StatsdExporter::new("stats_alloc")
+    }
+}
+
+impl StatsExporter for StatsdExporter {
+    fn export(&mut self, out: &mut dyn Write, snapshot: &Snapshot) -> io::Result<()> {
+        let change = snapshot.change();
+        for &(name, value) in STATS_FIELDS {
+            if is_gauge(name) {
+                let value = value(&snapshot.stats);
+                // A signed gauge value adjusts the previous one, so a negative
+                // value is sent relative to zero.
+                if value < 0 {
+                    writeln!(out, "{}.{}:0|g", self.prefix, name)?;
+                }
+                writeln!(out, "{}.{}:{}|g", self.prefix, name, value)?;
+            } else {
+                writeln!(out, "{}.{}:{}|c", self.prefix, name, value(&change))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A writer which sends what is written to it as UDP datagrams when flushed,
+/// splitting at line boundaries to keep each datagram small.
+#[derive(Debug)]
+pub struct UdpWriter {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl UdpWriter {
+    /// Binds an ephemeral local socket which sends to `addr`.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to send to"))?;
+        let local = if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        Ok(UdpWriter {
+            socket,
+            buffer: Vec::new(),
+        })
+    }
+}
+
+impl Write for UdpWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut rest = &self.buffer[..];
+        while !rest.is_empty() {
+            let end = if rest.len() <= MAX_DATAGRAM {
+                rest.len()
+            } else {
+                rest[..MAX_DATAGRAM]
+                    .iter()
+                    .rposition(|&b| b == b'\n')
+                    .map_or(MAX_DATAGRAM, |newline| newline + 1)
+            };
+            self.socket.send(&rest[..end])?;
+            rest = &rest[end..];
+        }
+        self.buffer.clear();
+        Ok(())
+    }
+}
+
+impl<T: GlobalAlloc + Sync> StatsAlloc<T> {
+    /// Starts a [`Reporter`] sending the statistics of this allocator to the
+    /// StatsD server at `addr` once every `interval`, with metric names
+    /// prefixed by `stats_alloc`.
+    ///
+    /// For another prefix, spawn a `Reporter` with a [`StatsdExporter`] and a
+    /// [`UdpWriter`].
+    ///
+    /// ```no_run
+    /// # use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
+    /// # use std::{alloc::System, time::Duration};
+    /// #[global_allocator]
+    /// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+    ///
+    /// fn main() {
+    ///     let _reporter = GLOBAL
+    ///         .start_statsd_reporter("127.0.0.1:8125", Duration::from_secs(10))
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn start_statsd_reporter<A: ToSocketAddrs>(&'static self, addr: A, interval: Duration) -> io::Result<Reporter> {
+        Reporter::spawn(self, interval, StatsdExporter::default(), UdpWriter::connect(addr)?)
+    }
+}
@@ -0,0 +1,137 @@
+//! A `futures` stream of periodic statistics snapshots.
+
+use std::{
+    alloc::GlobalAlloc,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+
+use crate::{Stats, StatsAlloc};
+
+#[derive(Default)]
+struct TimerState {
+    deadline: Option<Instant>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Timer {
+    state: Mutex<TimerState>,
+    condvar: Condvar,
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if state.closed {
+                return;
+            }
+            match state.deadline {
+                Some(deadline) if deadline <= Instant::now() => {
+                    state.deadline = None;
+                    if let Some(waker) = state.waker.take() {
+                        drop(state);
+                        waker.wake();
+                        state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                    }
+                },
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    state = self
+                        .condvar
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                },
+                None => {
+                    state = self.condvar.wait(state).unwrap_or_else(|e| e.into_inner());
+                },
+            }
+        }
+    }
+}
+
+/// A stream of allocator statistics, yielding a snapshot once every interval.
+///
+/// Created by [`StatsAlloc::snapshots`]. The first snapshot is yielded
+/// immediately. Waiting between snapshots is handled by a background thread,
+/// so the stream can be used with any executor.
+pub struct Snapshots<T: GlobalAlloc + 'static> {
+    alloc: &'static StatsAlloc<T>,
+    interval: Duration,
+    next: Instant,
+    timer: Option<Arc<Timer>>,
+}
+
+impl<T: GlobalAlloc + Sync + 'static> StatsAlloc<T> {
+    /// Returns a stream which yields a snapshot of the allocator statistics
+    /// once every `interval`.
+    #[cfg_attr(doc_cfg, doc(cfg(feature = "async")))]
+    pub fn snapshots(&'static self, interval: Duration) -> Snapshots<T> {
+        Snapshots {
+            alloc: self,
+            interval,
+            next: Instant::now(),
+            timer: None,
+        }
+    }
+}
+
+impl<T: GlobalAlloc + Sync + 'static> Stream for Snapshots<T> {
+    type Item = Stats;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Stats>> {
+        let now = Instant::now();
+        if now >= self.next {
+            self.next = now + self.interval;
+            return Poll::Ready(Some(self.alloc.stats()));
+        }
+
+        if self.timer.is_none() {
+            let timer = Arc::new(Timer::default());
+            let background = Arc::clone(&timer);
+            let spawned = thread::Builder::new()
+                .name("stats_alloc-snapshots".to_owned())
+                .spawn(move || background.run());
+            if spawned.is_err() {
+                // Without a timer thread, fall back to polling again at once.
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.timer = Some(timer);
+        }
+
+        let timer = self.timer.as_ref().expect("timer was just started");
+        let mut state = timer.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.deadline = Some(self.next);
+        state.waker = Some(cx.waker().clone());
+        timer.condvar.notify_one();
+        Poll::Pending
+    }
+}
+
+impl<T: GlobalAlloc + 'static> Drop for Snapshots<T> {
+    fn drop(&mut self) {
+        if let Some(ref timer) = self.timer {
+            timer.state.lock().unwrap_or_else(|e| e.into_inner()).closed = true;
+            timer.condvar.notify_one();
+        }
+    }
+}
+
+impl<T: GlobalAlloc + 'static> fmt::Debug for Snapshots<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Snapshots")
+            .field("interval", &self.interval)
+            .field("next", &self.next)
+            .finish()
+    }
+}
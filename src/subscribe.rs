@@ -0,0 +1,335 @@
+//! Subscriptions to the operations of an allocator, filtered by kind and
+//! size.
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    cell::UnsafeCell,
+    fmt,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::StatsAlloc;
+
+/// The kind of operation an [`AllocEvent`] describes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// An allocation or zeroed allocation
+    Alloc,
+    /// A deallocation
+    Dealloc,
+    /// A reallocation
+    Realloc,
+}
+
+impl EventKind {
+    fn bit(self) -> u8 {
+        match self {
+            EventKind::Alloc => 1,
+            EventKind::Dealloc => 2,
+            EventKind::Realloc => 4,
+        }
+    }
+}
+
+/// A successful operation of an allocator, as received by a subscriber.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AllocEvent {
+    /// The kind of operation
+    pub kind: EventKind,
+    /// The layout requested by an allocation, or of the block released or
+    /// resized
+    pub layout: Layout,
+    /// Size of the block after the operation, which is zero for a
+    /// deallocation
+    pub new_size: usize,
+    /// Time spent in the backing allocator, if [latency tracking] is enabled
+    ///
+    /// [latency tracking]: StatsAlloc::set_latency_tracking
+    pub elapsed: Option<Duration>,
+}
+
+impl AllocEvent {
+    pub(crate) fn alloc(layout: Layout, elapsed: Option<Duration>) -> Self {
+        AllocEvent {
+            kind: EventKind::Alloc,
+            layout,
+            new_size: layout.size(),
+            elapsed,
+        }
+    }
+
+    pub(crate) fn dealloc(layout: Layout, elapsed: Option<Duration>) -> Self {
+        AllocEvent {
+            kind: EventKind::Dealloc,
+            layout,
+            new_size: 0,
+            elapsed,
+        }
+    }
+
+    pub(crate) fn realloc(layout: Layout, new_size: usize, elapsed: Option<Duration>) -> Self {
+        AllocEvent {
+            kind: EventKind::Realloc,
+            layout,
+            new_size,
+            elapsed,
+        }
+    }
+
+    /// Returns the larger of the sizes of the block before and after the
+    /// operation.
+    pub fn size(&self) -> usize {
+        self.layout.size().max(self.new_size)
+    }
+}
+
+/// Selects the events a subscriber receives, by their kind and size.
+///
+/// The default filter passes every event.
+///
+/// ```
+/// # use stats_alloc::{EventFilter, EventKind};
+/// // Only reallocations of blocks of 64 KiB or more
+/// let filter = EventFilter::default()
+///     .min_size(64 * 1024)
+///     .only(&[EventKind::Realloc]);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EventFilter {
+    kinds: u8,
+    min_size: usize,
+}
+
+impl Default for EventFilter {
+    fn default() -> Self {
+        EventFilter {
+            kinds: EventKind::Alloc.bit() | EventKind::Dealloc.bit() | EventKind::Realloc.bit(),
+            min_size: 0,
+        }
+    }
+}
+
+impl EventFilter {
+    /// Passes only events whose [`size`](AllocEvent::size) is at least
+    /// `bytes`.
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Passes only events of the given kinds.
+    pub fn only(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = kinds.iter().fold(0, |bits, kind| bits | kind.bit());
+        self
+    }
+
+    /// Returns `true` if `event` passes the filter.
+    pub fn matches(&self, event: &AllocEvent) -> bool {
+        self.kinds & event.kind.bit() != 0 && event.size() >= self.min_size
+    }
+}
+
+/// A callback receiving events.
+type Callback = Box<dyn Fn(&AllocEvent) + Send + Sync>;
+
+/// States of a [`Subscriber`].
+const IDLE: u8 = 0;
+const CLAIMED: u8 = 1;
+const ACTIVE: u8 = 2;
+
+/// A slot for a subscriber, in a list which is only ever prepended to.
+///
+/// A slot is reused once its subscription is cancelled, so the list is only
+/// as long as the most subscriptions active at once. Its filter and callback
+/// are only written while it is claimed, which requires that it is idle and
+/// that no thread is delivering to it.
+pub(crate) struct Subscriber {
+    state: AtomicU8,
+    /// Number of threads which may be delivering an event to the subscriber
+    delivering: AtomicUsize,
+    filter: UnsafeCell<EventFilter>,
+    callback: UnsafeCell<Option<Callback>>,
+    next: Option<&'static Subscriber>,
+}
+
+// The filter and callback are only written by the thread which claimed the
+// subscriber, while no thread is delivering to it.
+unsafe impl Sync for Subscriber {}
+
+impl Subscriber {
+    /// Takes exclusive use of the subscriber if it is idle and no thread is
+    /// delivering to it, returning `true` if it was taken.
+    fn claim(&self) -> bool {
+        if self
+            .state
+            .compare_exchange(IDLE, CLAIMED, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        // A thread which starts delivering after this sees the claim and
+        // skips the subscriber.
+        if self.delivering.load(Ordering::SeqCst) != 0 {
+            self.state.store(IDLE, Ordering::Release);
+            return false;
+        }
+        true
+    }
+
+    /// Replaces the filter and callback of a claimed subscriber, and makes it
+    /// active.
+    fn activate(&self, filter: EventFilter, callback: Callback) {
+        unsafe {
+            *self.filter.get() = filter;
+            *self.callback.get() = Some(callback);
+        }
+        self.state.store(ACTIVE, Ordering::Release);
+    }
+
+    /// Runs the callback with `event` if the subscriber is active and the
+    /// event passes its filter.
+    #[inline]
+    fn deliver(&self, event: &AllocEvent) {
+        if self.state.load(Ordering::Relaxed) != ACTIVE {
+            return;
+        }
+        self.delivering.fetch_add(1, Ordering::SeqCst);
+        if self.state.load(Ordering::SeqCst) == ACTIVE {
+            // The subscriber cannot be claimed until delivery is finished.
+            let (filter, callback) = unsafe { (&*self.filter.get(), &*self.callback.get()) };
+            if let Some(ref callback) = *callback {
+                if filter.matches(event) {
+                    callback(event);
+                }
+            }
+        }
+        self.delivering.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// A registration of a callback for the events passing a filter, which is
+/// cancelled when dropped.
+#[must_use = "the subscription is cancelled when dropped"]
+pub struct Subscription {
+    subscriber: &'static Subscriber,
+}
+
+impl Subscription {
+    /// Returns the filter selecting the events received.
+    pub fn filter(&self) -> EventFilter {
+        // The subscriber remains active, and its filter unchanged, until the
+        // subscription is dropped.
+        unsafe { *self.subscriber.filter.get() }
+    }
+
+    /// Stops receiving events.
+    pub fn cancel(self) {}
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let subscriber = self.subscriber;
+        subscriber.state.store(IDLE, Ordering::SeqCst);
+        // Free the callback now unless another thread is delivering to it,
+        // in which case it is freed when the subscriber is reused.
+        if subscriber.claim() {
+            let callback = unsafe { (*subscriber.callback.get()).take() };
+            subscriber.state.store(IDLE, Ordering::Release);
+            drop(callback);
+        }
+    }
+}
+
+impl fmt::Debug for Subscription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscription").field("filter", &self.filter()).finish()
+    }
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Runs `callback` with each successful operation passing `filter`, until
+    /// the returned subscription is dropped.
+    ///
+    /// Any number of subscribers may be registered, each with its own
+    /// filter, so that each receives only the events of interest rather than
+    /// every small allocation. Callbacks run like an [`AllocHook`]: on the
+    /// allocating thread, within the allocator, and not for operations made by
+    /// callbacks themselves. They should be quick and must not panic. Every
+    /// event is delivered, even while [sampling]. Cancelling a subscription
+    /// frees its callback unless another thread is running it, and the few
+    /// bytes it took are reused by a later subscription.
+    ///
+    /// ```
+    /// # use stats_alloc::{EventFilter, StatsAlloc};
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// static LARGE: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// let subscription = alloc.subscribe(EventFilter::default().min_size(4096), |_| {
+    ///     LARGE.fetch_add(1, Ordering::SeqCst);
+    /// });
+    /// for &size in &[16, 4096] {
+    ///     let layout = Layout::from_size_align(size, 8).unwrap();
+    ///     unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    /// }
+    /// assert_eq!(LARGE.load(Ordering::SeqCst), 2);
+    /// subscription.cancel();
+    /// ```
+    ///
+    /// [`AllocHook`]: crate::AllocHook
+    /// [sampling]: StatsAlloc::set_sampling
+    pub fn subscribe<F>(&self, filter: EventFilter, callback: F) -> Subscription
+    where
+        F: Fn(&AllocEvent) + Send + Sync + 'static,
+    {
+        let callback: Callback = Box::new(callback);
+        let mut next = unsafe { self.subscribers.load(Ordering::Acquire).as_ref() };
+        while let Some(subscriber) = next {
+            if subscriber.claim() {
+                // Free any callback left by a cancellation during delivery.
+                let previous = unsafe { (*subscriber.callback.get()).take() };
+                subscriber.activate(filter, callback);
+                drop(previous);
+                return Subscription { subscriber };
+            }
+            next = subscriber.next;
+        }
+        let subscriber = Box::into_raw(Box::new(Subscriber {
+            state: AtomicU8::new(ACTIVE),
+            delivering: AtomicUsize::new(0),
+            filter: UnsafeCell::new(filter),
+            callback: UnsafeCell::new(Some(callback)),
+            next: None,
+        }));
+        let mut head = self.subscribers.load(Ordering::Acquire);
+        loop {
+            // The subscriber is not yet shared, and every subscriber in the
+            // list is leaked, so the reference remains valid.
+            unsafe { (*subscriber).next = head.as_ref() };
+            match self
+                .subscribers
+                .compare_exchange_weak(head, subscriber, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        Subscription {
+            subscriber: unsafe { &*subscriber },
+        }
+    }
+
+    /// Delivers `event` to every active subscriber whose filter it passes.
+    #[inline]
+    pub(crate) fn publish(&self, event: &AllocEvent) {
+        let mut next = unsafe { self.subscribers.load(Ordering::Acquire).as_ref() };
+        while let Some(subscriber) = next {
+            subscriber.deliver(event);
+            next = subscriber.next;
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! A process-wide registry of the statistics of measured tests.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    os::raw::c_int,
+    sync::{Mutex, Once},
+};
+
+use crate::Stats;
+
+extern "C" {
+    fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+static TESTS: Mutex<Vec<(String, Stats)>> = Mutex::new(Vec::new());
+static PRINT_AT_EXIT: Once = Once::new();
+
+/// Records the change in statistics over the test `name` in the process-wide
+/// registry, for inclusion in the [`TestSummary`].
+///
+/// Tests measured with `#[stats_alloc::test]` are recorded automatically.
+pub fn record_test(name: &str, change: Stats) {
+    TESTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push((name.to_owned(), change));
+}
+
+/// Arranges for the [`TestSummary`] to be written to standard error when the
+/// process exits, as a test binary does once all of its tests have run.
+///
+/// Calling this more than once has no further effect, and nothing is written
+/// if no tests were recorded.
+pub fn print_summary_at_exit() {
+    PRINT_AT_EXIT.call_once(|| unsafe {
+        atexit(print_summary);
+    });
+}
+
+extern "C" fn print_summary() {
+    let summary = TestSummary::collect();
+    if !summary.tests.is_empty() {
+        let _ = write!(io::stderr(), "{}", summary);
+    }
+}
+
+/// The statistics of every test recorded with [`record_test`], most
+/// allocating first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TestSummary {
+    /// Name and change in statistics of each test, ordered by allocations and
+    /// then by bytes allocated, both descending
+    pub tests: Vec<(String, Stats)>,
+}
+
+impl TestSummary {
+    /// Takes a summary of the tests recorded so far.
+    pub fn collect() -> Self {
+        let mut tests = TESTS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        tests.sort_by(|(a_name, a), (b_name, b)| {
+            (b.allocations, b.bytes_allocated, a_name).cmp(&(a.allocations, a.bytes_allocated, b_name))
+        });
+        TestSummary { tests }
+    }
+}
+
+/// Writes a table with a row per test and a column for each of the
+/// allocations, bytes allocated and deallocations.
+impl fmt::Display for TestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.tests.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(4);
+        writeln!(
+            f,
+            "{:<width$} {:>12} {:>16} {:>14}",
+            "test",
+            "allocations",
+            "bytes",
+            "deallocations",
+            width = width
+        )?;
+        for (name, stats) in &self.tests {
+            writeln!(
+                f,
+                "{:<width$} {:>12} {:>16} {:>14}",
+                name,
+                stats.allocations,
+                stats.bytes_allocated,
+                stats.deallocations,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
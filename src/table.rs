@@ -0,0 +1,333 @@
+//! Collections which may be updated from within the allocator.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Smallest number of slots allocated for a table.
+const MIN_CAPACITY: usize = 64;
+
+/// The contents of a slot of a [`Table`], identified by a key.
+pub(crate) trait Keyed: Copy {
+    /// The key identifying a slot.
+    type Key: Copy + PartialEq;
+
+    /// Returns a slot holding nothing.
+    fn vacant() -> Self;
+
+    /// Marks the slot as holding nothing.
+    fn vacate(&mut self);
+
+    /// Returns `true` if the slot holds nothing.
+    fn is_vacant(&self) -> bool;
+
+    /// Returns the key of an occupied slot.
+    fn key(&self) -> Self::Key;
+
+    /// Returns a well-mixed hash of `key`.
+    fn hash(key: &Self::Key) -> u64;
+}
+
+/// Returns a well-mixed hash of the address of a block.
+pub(crate) fn hash_address(address: usize) -> u64 {
+    // Blocks are at least 8-byte aligned in practice, so the low bits carry
+    // no information.
+    ((address >> 3) as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+}
+
+/// An open-addressing hash table with linear probing.
+///
+/// Its storage is obtained directly from the system allocator, so that
+/// updating the table never recurses into the instrumented allocator.
+pub(crate) struct Table<S> {
+    slots: *mut S,
+    capacity: usize,
+    len: usize,
+}
+
+// The table owns the storage its pointer refers to.
+unsafe impl<S: Send> Send for Table<S> {}
+
+impl<S: Keyed> Table<S> {
+    pub(crate) const EMPTY: Table<S> = Table {
+        slots: ptr::null_mut(),
+        capacity: 0,
+        len: 0,
+    };
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    fn slots(&self) -> &[S] {
+        if self.slots.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.slots, self.capacity) }
+        }
+    }
+
+    fn slots_mut(&mut self) -> &mut [S] {
+        if self.slots.is_null() {
+            &mut []
+        } else {
+            unsafe { std::slice::from_raw_parts_mut(self.slots, self.capacity) }
+        }
+    }
+
+    /// Returns the occupied slots, in no particular order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &S> {
+        self.slots().iter().filter(|slot| !slot.is_vacant())
+    }
+
+    /// Returns the occupied slots for updating, in no particular order.
+    #[cfg_attr(not(feature = "backtrace"), allow(dead_code))]
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut S> {
+        self.slots_mut().iter_mut().filter(|slot| !slot.is_vacant())
+    }
+
+    /// Returns the slot at which a probe for `key` starts.
+    fn start(&self, key: &S::Key) -> usize {
+        (S::hash(key) >> 16) as usize % self.capacity
+    }
+
+    /// Returns the index of the slot holding `key`, or of the vacant slot at
+    /// which it would be inserted.
+    fn probe(&self, key: &S::Key) -> usize {
+        let slots = self.slots();
+        let mut i = self.start(key);
+        while !slots[i].is_vacant() && slots[i].key() != *key {
+            i = (i + 1) % self.capacity;
+        }
+        i
+    }
+
+    /// Makes room for one more slot, returning `false` if the table cannot
+    /// grow to hold it.
+    fn reserve_one(&mut self) -> bool {
+        (self.len + 1) * 4 <= self.capacity * 3 || self.grow()
+    }
+
+    /// Inserts `slot`, returning whichever slot is not kept: one it replaced
+    /// with the same key, or `slot` itself if the table cannot grow to hold
+    /// it.
+    #[must_use]
+    pub(crate) fn insert(&mut self, slot: S) -> Option<S> {
+        if !self.reserve_one() {
+            return Some(slot);
+        }
+        let i = self.probe(&slot.key());
+        let replaced = std::mem::replace(&mut self.slots_mut()[i], slot);
+        if replaced.is_vacant() {
+            self.len += 1;
+            None
+        } else {
+            Some(replaced)
+        }
+    }
+
+    /// Returns the slot for `key`, first inserting the slot made by `vacant`
+    /// if there is none, or `None` if the table cannot grow to hold it.
+    #[cfg_attr(not(feature = "backtrace"), allow(dead_code))]
+    pub(crate) fn get_or_insert_with<F: FnOnce() -> S>(&mut self, key: S::Key, vacant: F) -> Option<&mut S> {
+        if !self.reserve_one() {
+            let i = if self.capacity == 0 {
+                None
+            } else {
+                Some(self.probe(&key))
+            };
+            return i
+                .filter(|&i| !self.slots()[i].is_vacant())
+                .map(move |i| &mut self.slots_mut()[i]);
+        }
+        let i = self.probe(&key);
+        if self.slots()[i].is_vacant() {
+            self.slots_mut()[i] = vacant();
+            self.len += 1;
+        }
+        Some(&mut self.slots_mut()[i])
+    }
+
+    /// Removes and returns the slot for `key`, if present.
+    pub(crate) fn remove(&mut self, key: &S::Key) -> Option<S> {
+        if self.len == 0 {
+            return None;
+        }
+        let capacity = self.capacity;
+        let i = self.probe(key);
+        if self.slots()[i].is_vacant() {
+            return None;
+        }
+        let starts: fn(&S, usize) -> usize = |slot, capacity| (S::hash(&slot.key()) >> 16) as usize % capacity;
+        let slots = self.slots_mut();
+        let removed = slots[i];
+        // Shift later slots of the same probe sequence back into the gap, so
+        // that lookups never stop early at it.
+        let mut gap = i;
+        let mut j = (i + 1) % capacity;
+        while !slots[j].is_vacant() {
+            let displacement = (j + capacity - starts(&slots[j], capacity)) % capacity;
+            if displacement >= (j + capacity - gap) % capacity {
+                slots[gap] = slots[j];
+                gap = j;
+            }
+            j = (j + 1) % capacity;
+        }
+        slots[gap].vacate();
+        self.len -= 1;
+        Some(removed)
+    }
+
+    /// Doubles the capacity of the table, returning `false` if the system
+    /// allocator could not provide the storage.
+    fn grow(&mut self) -> bool {
+        let capacity = (self.capacity * 2).max(MIN_CAPACITY);
+        let layout = match Layout::array::<S>(capacity) {
+            Ok(layout) => layout,
+            Err(_) => return false,
+        };
+        let slots = unsafe { System.alloc(layout) } as *mut S;
+        if slots.is_null() {
+            return false;
+        }
+        let vacant = S::vacant();
+        for i in 0..capacity {
+            unsafe { slots.add(i).write(vacant) };
+        }
+        let old = std::mem::replace(
+            self,
+            Table {
+                slots,
+                capacity,
+                len: 0,
+            },
+        );
+        for &slot in old.iter() {
+            // Keys are distinct and the capacity suffices, so every slot is
+            // kept.
+            let _ = self.insert(slot);
+        }
+        old.release();
+        true
+    }
+
+    /// Returns the storage of the table to the system allocator.
+    pub(crate) fn release(self) {
+        if !self.slots.is_null() {
+            let layout = Layout::array::<S>(self.capacity).expect("layout of an existing table");
+            unsafe { System.dealloc(self.slots as *mut u8, layout) };
+        }
+    }
+}
+
+/// A list which grows by doubling, with storage obtained directly from the
+/// system allocator like that of a [`Table`].
+#[cfg_attr(not(feature = "backtrace"), allow(dead_code))]
+pub(crate) struct Log<T> {
+    items: *mut T,
+    capacity: usize,
+    len: usize,
+}
+
+// The log owns the storage its pointer refers to.
+unsafe impl<T: Send> Send for Log<T> {}
+
+#[cfg_attr(not(feature = "backtrace"), allow(dead_code))]
+impl<T: Copy> Log<T> {
+    pub(crate) const EMPTY: Log<T> = Log {
+        items: ptr::null_mut(),
+        capacity: 0,
+        len: 0,
+    };
+
+    pub(crate) fn as_slice(&self) -> &[T] {
+        if self.items.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.items, self.len) }
+        }
+    }
+
+    /// Appends `item`, returning `false` if the log cannot grow to hold it.
+    pub(crate) fn push(&mut self, item: T) -> bool {
+        if self.len == self.capacity && !self.grow() {
+            return false;
+        }
+        unsafe { self.items.add(self.len).write(item) };
+        self.len += 1;
+        true
+    }
+
+    fn grow(&mut self) -> bool {
+        let capacity = (self.capacity * 2).max(MIN_CAPACITY);
+        let layout = match Layout::array::<T>(capacity) {
+            Ok(layout) => layout,
+            Err(_) => return false,
+        };
+        let items = unsafe { System.alloc(layout) } as *mut T;
+        if items.is_null() {
+            return false;
+        }
+        let old = std::mem::replace(
+            self,
+            Log {
+                items,
+                capacity,
+                len: 0,
+            },
+        );
+        if old.len > 0 {
+            unsafe { ptr::copy_nonoverlapping(old.items, items, old.len) };
+        }
+        self.len = old.len;
+        old.release();
+        true
+    }
+
+    /// Returns the storage of the log to the system allocator.
+    pub(crate) fn release(self) {
+        if !self.items.is_null() {
+            let layout = Layout::array::<T>(self.capacity).expect("layout of an existing log");
+            unsafe { System.dealloc(self.items as *mut u8, layout) };
+        }
+    }
+}
+
+/// A value behind a spin lock, for use where blocking on a mutex could
+/// allocate.
+pub(crate) struct Locked<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+// The value is only accessed while holding the lock.
+unsafe impl<T: Send> Sync for Locked<T> {}
+
+impl<T> Locked<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Locked {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Runs `f` with exclusive access to the value.
+    ///
+    /// `f` must not allocate through the instrumented allocator, which could
+    /// try to take the same lock.
+    pub(crate) fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            std::hint::spin_loop();
+        }
+        let result = f(unsafe { &mut *self.value.get() });
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
@@ -0,0 +1,108 @@
+//! Callbacks run when the bytes in use rise past a level.
+
+use std::{
+    alloc::GlobalAlloc,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{hook, StatsAlloc};
+
+/// A level of bytes in use, with the callback run on reaching it.
+pub(crate) struct Threshold {
+    bytes: usize,
+    /// Level below which the callback may run again, if it may
+    rearm_below: Option<usize>,
+    armed: AtomicBool,
+    callback: Box<dyn Fn(i64) + Send + Sync>,
+}
+
+impl<T: GlobalAlloc> StatsAlloc<T> {
+    /// Runs `callback` with the bytes in use the first time they reach
+    /// `bytes`, in place of any threshold set before.
+    ///
+    /// This allows a program to raise an alert, dump its statistics or shed
+    /// caches before it runs out of memory. The callback runs on the thread
+    /// whose allocation or reallocation reached the level, within the
+    /// allocator, so it should be quick and must not panic. It may allocate,
+    /// but operations it makes do not run it again. The level is checked
+    /// against `bytes_current`, so it is only approximate while other threads
+    /// allocate concurrently, and is not checked while counting is paused or
+    /// compiled out.
+    ///
+    /// ```
+    /// # use stats_alloc::StatsAlloc;
+    /// # use std::alloc::{GlobalAlloc, Layout};
+    /// # use std::sync::atomic::{AtomicI64, Ordering};
+    /// static REACHED: AtomicI64 = AtomicI64::new(0);
+    ///
+    /// # if cfg!(feature = "noop") { return; }
+    /// let alloc = StatsAlloc::system();
+    /// alloc.on_threshold(1024, |bytes| REACHED.store(bytes, Ordering::SeqCst));
+    /// let layout = Layout::from_size_align(4096, 8).unwrap();
+    /// unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    /// assert_eq!(REACHED.load(Ordering::SeqCst), 4096);
+    /// ```
+    pub fn on_threshold<F>(&self, bytes: usize, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.set_threshold(bytes, None, Box::new(callback));
+    }
+
+    /// Runs `callback` with the bytes in use each time they reach `bytes`,
+    /// having fallen below `rearm_below` since it last ran, in place of any
+    /// threshold set before.
+    ///
+    /// The gap between the two levels keeps the callback from running over
+    /// and over while the bytes in use hover around `bytes`. Otherwise this
+    /// behaves as [`on_threshold`](StatsAlloc::on_threshold).
+    pub fn on_threshold_with_hysteresis<F>(&self, bytes: usize, rearm_below: usize, callback: F)
+    where
+        F: Fn(i64) + Send + Sync + 'static,
+    {
+        self.set_threshold(bytes, Some(rearm_below), Box::new(callback));
+    }
+
+    /// Removes the threshold set with
+    /// [`on_threshold`](StatsAlloc::on_threshold) or
+    /// [`on_threshold_with_hysteresis`](StatsAlloc::on_threshold_with_hysteresis).
+    pub fn clear_threshold(&self) {
+        self.threshold.store(ptr::null_mut(), Ordering::Release);
+    }
+
+    fn set_threshold(&self, bytes: usize, rearm_below: Option<usize>, callback: Box<dyn Fn(i64) + Send + Sync>) {
+        // A threshold which is replaced may still be checked on other
+        // threads, so it is never freed.
+        let threshold = Box::leak(Box::new(Threshold {
+            bytes,
+            rearm_below,
+            armed: AtomicBool::new(true),
+            callback,
+        }));
+        self.threshold.store(threshold, Ordering::Release);
+    }
+
+    /// Runs the threshold's callback if the bytes in use have reached it, or
+    /// rearms it if they have fallen far enough.
+    #[inline]
+    pub(crate) fn check_threshold(&self) {
+        let threshold = self.threshold.load(Ordering::Acquire);
+        if threshold.is_null() {
+            return;
+        }
+        let threshold = unsafe { &*threshold };
+        let current = self.bytes_in_use();
+        if current as i128 >= threshold.bytes as i128 {
+            hook::exclusive(|| {
+                if threshold.armed.swap(false, Ordering::AcqRel) {
+                    (threshold.callback)(current);
+                }
+            });
+        } else if let Some(rearm_below) = threshold.rearm_below {
+            if (current as i128) < rearm_below as i128 && !threshold.armed.load(Ordering::Relaxed) {
+                threshold.armed.store(true, Ordering::Release);
+            }
+        }
+    }
+}
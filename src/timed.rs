@@ -0,0 +1,90 @@
+//! Regions measuring elapsed time together with allocations.
+
+use std::{
+    alloc::GlobalAlloc,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{report::write_bytes, Region, Stats, StatsAlloc};
+
+/// The wall-clock time taken over a [`TimedRegion`] and the change in
+/// allocator statistics over the same span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimedReport {
+    /// Wall-clock time elapsed
+    pub elapsed: Duration,
+    /// Change in statistics
+    pub change: Stats,
+}
+
+/// Summarizes the report, e.g. `took 12.0ms and allocated 80 KiB in 14
+/// allocations`.
+impl fmt::Display for TimedReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "took {:.1?} and allocated ", self.elapsed)?;
+        write_bytes(f, self.change.bytes_allocated as i128, false)?;
+        write!(f, " in {} allocations", self.change.allocations)
+    }
+}
+
+/// A region which also measures the wall-clock time since it was created.
+///
+/// ```
+/// # use stats_alloc::{StatsAlloc, TimedRegion, INSTRUMENTED_SYSTEM};
+/// # use std::alloc::System;
+/// #[global_allocator]
+/// static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+///
+/// fn main() {
+///     let region = TimedRegion::new(GLOBAL);
+///     let _ = vec![0u8; 1024];
+///     println!("{}", region.report());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TimedRegion<'a, T: GlobalAlloc + 'a> {
+    region: Region<'a, T>,
+    started: Instant,
+}
+
+impl<'a, T: GlobalAlloc + 'a> TimedRegion<'a, T> {
+    /// Creates a new region using statistics from the given instrumented
+    /// allocator, starting the clock.
+    #[inline]
+    pub fn new(alloc: &'a StatsAlloc<T>) -> Self {
+        TimedRegion {
+            region: Region::new(alloc),
+            started: Instant::now(),
+        }
+    }
+
+    /// Returns the region being measured.
+    #[inline]
+    pub fn region(&self) -> &Region<'a, T> {
+        &self.region
+    }
+
+    /// Returns the time elapsed and the change in statistics since creation
+    /// or the last reset.
+    #[inline]
+    pub fn report(&self) -> TimedReport {
+        TimedReport {
+            elapsed: self.started.elapsed(),
+            change: self.region.change(),
+        }
+    }
+
+    /// Returns the report since creation or the last reset, restarting both
+    /// the clock and the region.
+    #[inline]
+    pub fn report_and_reset(&mut self) -> TimedReport {
+        let now = Instant::now();
+        let report = TimedReport {
+            elapsed: now - self.started,
+            change: self.region.change_and_reset(),
+        };
+        self.started = now;
+        report
+    }
+}
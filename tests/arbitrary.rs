@@ -0,0 +1,39 @@
+#![cfg(any(feature = "proptest", feature = "quickcheck"))]
+
+#[cfg(feature = "proptest")]
+#[macro_use]
+extern crate proptest;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+extern crate stats_alloc;
+
+use stats_alloc::Stats;
+
+/// Returns `stats` without the maxima, which are combined rather than summed.
+fn counters(mut stats: Stats) -> Stats {
+    stats.peak_live_allocations = 0;
+    stats.max_allocation_size = 0;
+    stats
+}
+
+fn addition_inverts_subtraction(a: Stats, b: Stats) -> bool {
+    counters((a + b) - b) == counters(a)
+        && counters((a - b) + b) == counters(a)
+        && (a - b).max_allocation_size == a.max_allocation_size
+        && (a + b).max_allocation_size == a.max_allocation_size.max(b.max_allocation_size)
+}
+
+#[cfg(feature = "proptest")]
+proptest! {
+    #[test]
+    fn proptest_stats_wrap_consistently(a: Stats, b: Stats) {
+        prop_assert!(addition_inverts_subtraction(a, b));
+        prop_assert_eq!(a.delta(&a), Default::default());
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+#[test]
+fn quickcheck_stats_wrap_consistently() {
+    quickcheck::quickcheck(addition_inverts_subtraction as fn(Stats, Stats) -> bool);
+}
@@ -0,0 +1,29 @@
+extern crate stats_alloc;
+
+use stats_alloc::{Config, ConfigError, ReallocAccounting, ReportDestination, Template};
+use std::time::Duration;
+
+#[test]
+fn config_is_parsed_from_settings() {
+    let config = Config::parse(
+        "report_interval=500ms; report_to=/tmp/alloc.log;realloc_accounting=pair;sampling=100;huge_warning=1048576;report_format={allocations}",
+    )
+    .unwrap();
+    assert_eq!(config.report_interval, Some(Duration::from_millis(500)));
+    assert_eq!(config.report_to, ReportDestination::File("/tmp/alloc.log".into()));
+    assert_eq!(config.realloc_accounting, Some(ReallocAccounting::Pair));
+    assert_eq!(config.sampling, Some(100));
+    assert_eq!(config.huge_warning, Some(1 << 20));
+    assert_eq!(config.report_format, Some(Template::parse("{allocations}").unwrap()));
+    assert_eq!(
+        Config::parse("huge_threshold=lots"),
+        Err(ConfigError::InvalidValue {
+            key: "huge_threshold".to_owned(),
+            value: "lots".to_owned()
+        })
+    );
+    assert_eq!(
+        Config::parse("colour=blue"),
+        Err(ConfigError::UnknownKey("colour".to_owned()))
+    );
+}
@@ -0,0 +1,1183 @@
+#![cfg(not(feature = "noop"))]
+
+extern crate stats_alloc;
+
+use stats_alloc::{
+    assert_no_alloc, bench_allocs, AllocHook, AllocTag, DenyAllocGuard, EventFilter, EventKind, FaultGuard, LeakAction,
+    Overhead, ReallocAccounting, Region, Stats, StatsAlloc, StatsBuilder, StatsCounters, StatsDelta, TimedRegion,
+    PAGE_SIZE,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+#[test]
+fn huge_and_page_aligned_allocations_are_counted_separately() {
+    let alloc = StatsAlloc::system();
+    alloc.set_huge_threshold(64 * 1024);
+    unsafe {
+        let small = Layout::from_size_align(64, 8).unwrap();
+        let aligned = Layout::from_size_align(64, PAGE_SIZE).unwrap();
+        let huge = Layout::from_size_align(64 * 1024, 8).unwrap();
+        let a = alloc.alloc(small);
+        let b = alloc.alloc(aligned);
+        let c = alloc.alloc(huge);
+        let a = alloc.realloc(a, small, 128 * 1024);
+        alloc.dealloc(a, Layout::from_size_align(128 * 1024, 8).unwrap());
+        alloc.dealloc(b, aligned);
+        alloc.dealloc(c, huge);
+    }
+    let stats = alloc.stats();
+    assert_eq!(stats.page_aligned_allocations, 1);
+    assert_eq!(stats.allocations_align_8, 2);
+    assert_eq!(stats.huge_allocations, 2);
+    assert_eq!(stats.huge_bytes_allocated, 192 * 1024);
+    assert_eq!(stats.max_allocation_size, 128 * 1024);
+    assert_eq!(alloc.max_allocation_size(), 128 * 1024);
+    let _ = StatsAlloc::<System>::default();
+}
+
+#[test]
+fn reallocations_can_be_counted_as_pairs() {
+    let alloc = StatsAlloc::system();
+    alloc.set_realloc_accounting(ReallocAccounting::Pair);
+    unsafe {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let ptr = alloc.alloc(layout);
+        let ptr = alloc.realloc(ptr, layout, 32);
+        alloc.dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+    let stats = alloc.stats();
+    assert_eq!(stats.allocations, 2);
+    assert_eq!(stats.deallocations, 2);
+    assert_eq!(stats.reallocations, 1);
+    assert_eq!(stats.bytes_allocated, 96);
+    assert_eq!(stats.bytes_deallocated, 96);
+    assert_eq!(stats.bytes_reallocated, -32);
+    assert_eq!(stats.reallocations_shrink, 1);
+    assert_eq!(stats.bytes_shrunk, 32);
+}
+
+#[test]
+fn sampled_counts_are_scaled_estimates() {
+    let alloc = StatsAlloc::system();
+    alloc.set_sampling(10);
+    assert_eq!(alloc.sampling(), Some(10));
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let mut held = Vec::new();
+    for i in 0..20_000 {
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            if i % 2 == 0 {
+                held.push(ptr);
+            } else {
+                alloc.dealloc(ptr, layout);
+            }
+        }
+    }
+    let stats = alloc.stats();
+    assert_eq!(stats.allocations % 10, 0);
+    assert!((18_000..22_000).contains(&stats.allocations), "{}", stats.allocations);
+    assert_eq!(stats.bytes_allocated, stats.allocations * 64);
+    assert!(
+        (8_000..12_000).contains(&stats.deallocations),
+        "{}",
+        stats.deallocations
+    );
+    assert_eq!(stats.bytes_current, 10_000 * 64);
+    assert_eq!(stats.live_allocations, 10_000);
+    for ptr in held {
+        unsafe { alloc.dealloc(ptr, layout) };
+    }
+    assert_eq!(alloc.bytes_in_use(), 0);
+    alloc.set_sampling(1);
+    assert_eq!(alloc.sampling(), None);
+}
+
+#[test]
+fn calibration_measures_every_mode() {
+    let overhead = Overhead::calibrate(1_000);
+    assert_eq!(overhead.iterations, 1_000);
+    assert!(overhead.per_operation() <= overhead.net);
+    let table = overhead.to_string();
+    assert!(table.contains("uninstrumented"));
+    assert!(table.contains("pair"));
+    assert!(table.contains("sampled 1/100"));
+}
+
+#[test]
+fn bytes_in_use_follows_every_operation() {
+    let alloc = StatsAlloc::system();
+    unsafe {
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let ptr = alloc.alloc(layout);
+        assert_eq!(alloc.bytes_in_use(), 64);
+        let ptr = alloc.realloc(ptr, layout, 256);
+        assert_eq!(alloc.bytes_in_use(), 256);
+        let before = alloc.stats();
+        let ptr = alloc.realloc(ptr, Layout::from_size_align(256, 8).unwrap(), 16);
+        let change = alloc.stats() - before;
+        assert_eq!(change.bytes_in_use(), -240);
+        assert_eq!((change.reallocations_grow, change.reallocations_shrink), (0, 1));
+        assert_eq!(change.bytes_shrunk, 240);
+        alloc.dealloc(ptr, Layout::from_size_align(16, 8).unwrap());
+    }
+    assert_eq!(alloc.bytes_in_use(), 0);
+    let stats = alloc.stats();
+    assert_eq!(stats.bytes_current, 0);
+    assert_eq!((stats.reallocations_grow, stats.bytes_grown), (1, 192));
+}
+
+#[test]
+fn live_allocations_and_their_peak_are_tracked() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    unsafe {
+        let ptrs: Vec<_> = (0..3).map(|_| alloc.alloc(layout)).collect();
+        assert_eq!(alloc.live_allocations(), 3);
+        let grown = alloc.realloc(ptrs[0], layout, 32);
+        assert_eq!(alloc.live_allocations(), 3);
+        alloc.dealloc(grown, Layout::from_size_align(32, 8).unwrap());
+        for &ptr in &ptrs[1..] {
+            alloc.dealloc(ptr, layout);
+        }
+        let ptr = alloc.alloc_zeroed(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.live_allocations(), 0);
+    // With stripes, the peak is only raised when loaded.
+    let peak = if cfg!(feature = "sharded") { 0 } else { 3 };
+    assert_eq!(alloc.peak_live_allocations(), peak);
+}
+
+#[cfg(feature = "sharded")]
+#[test]
+fn sharded_peak_of_live_allocations_is_raised_when_loaded() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let ptrs: Vec<_> = thread::scope(|scope| {
+        (0..4)
+            .map(|_| scope.spawn(|| unsafe { alloc.alloc(layout) } as usize))
+            .map(|handle| handle.join().unwrap() as *mut u8)
+            .collect()
+    });
+    unsafe {
+        assert_eq!(alloc.live_allocations(), 4);
+        assert_eq!(alloc.peak_live_allocations(), 4);
+        for ptr in ptrs {
+            alloc.dealloc(ptr, layout);
+        }
+        let ptr = alloc.alloc(layout);
+        alloc.dealloc(ptr, layout);
+    }
+    assert_eq!(alloc.live_allocations(), 0);
+    assert_eq!(alloc.peak_live_allocations(), 4);
+}
+
+struct Exhausted;
+
+unsafe impl GlobalAlloc for Exhausted {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+
+    unsafe fn realloc(&self, _ptr: *mut u8, _layout: Layout, _new_size: usize) -> *mut u8 {
+        ptr::null_mut()
+    }
+}
+
+#[test]
+fn failed_allocations_are_counted_without_bytes() {
+    let alloc = StatsAlloc::new(Exhausted);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        assert!(alloc.alloc(layout).is_null());
+        assert!(alloc.alloc_zeroed(layout).is_null());
+        assert!(alloc.realloc(ptr::NonNull::dangling().as_ptr(), layout, 128).is_null());
+    }
+    let stats = alloc.stats();
+    assert_eq!(stats.allocation_failures, 3);
+    assert_eq!(stats.zero_size_allocations, 0);
+    assert_eq!(stats.allocations, 0);
+    assert_eq!(stats.reallocations, 0);
+    assert_eq!(stats.bytes_allocated, 0);
+    assert_eq!(stats.bytes_current, 0);
+    assert_eq!(stats.live_allocations, 0);
+}
+
+#[test]
+fn allocations_are_counted_by_alignment() {
+    let alloc = StatsAlloc::system();
+    unsafe {
+        for &align in &[1, 8, 16, 32, 64, 128, 2048, PAGE_SIZE] {
+            let layout = Layout::from_size_align(64, align).unwrap();
+            alloc.dealloc(alloc.alloc(layout), layout);
+        }
+    }
+    let stats = alloc.stats();
+    assert_eq!(stats.allocations_align_8, 2);
+    assert_eq!(stats.allocations_align_16, 1);
+    assert_eq!(stats.allocations_align_32, 1);
+    assert_eq!(stats.allocations_align_64, 1);
+    assert_eq!(stats.allocations_align_over_64, 2);
+    assert_eq!(stats.page_aligned_allocations, 1);
+}
+
+#[test]
+fn zero_size_requests_are_counted() {
+    let alloc = StatsAlloc::new(Exhausted);
+    unsafe {
+        alloc.alloc(Layout::from_size_align(0, 1).unwrap());
+        alloc.alloc_zeroed(Layout::from_size_align(0, 8).unwrap());
+    }
+    assert_eq!(alloc.stats().zero_size_allocations, 2);
+}
+
+#[cfg(feature = "cache-padded")]
+#[test]
+fn counters_are_padded_to_cache_lines() {
+    assert_eq!(std::mem::align_of::<stats_alloc::StatsCounters>(), 64);
+}
+
+#[test]
+fn counts_from_many_threads_are_totalled() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| unsafe {
+                for _ in 0..100 {
+                    alloc.dealloc(alloc.alloc(layout), layout);
+                }
+            });
+        }
+    });
+    let stats = alloc.stats();
+    assert_eq!(stats.allocations, 800);
+    assert_eq!(stats.bytes_deallocated, 800 * 32);
+    assert_eq!(stats.bytes_current, 0);
+    assert_eq!(stats.max_allocation_size, 32);
+}
+
+#[test]
+fn consistent_snapshots_never_observe_partial_updates() {
+    let alloc = StatsAlloc::system();
+    alloc.set_consistent_snapshots(true);
+    assert!(alloc.consistent_snapshots());
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| unsafe {
+                for _ in 0..2_000 {
+                    alloc.dealloc(alloc.alloc(layout), layout);
+                }
+            });
+        }
+        scope.spawn(|| {
+            while !done.load(Ordering::Relaxed) {
+                let stats = alloc.stats();
+                assert_eq!(stats.bytes_allocated, stats.allocations * 32);
+                assert_eq!(stats.bytes_deallocated, stats.deallocations * 32);
+                assert!(stats.deallocations <= stats.allocations);
+            }
+        });
+        thread::sleep(Duration::from_millis(20));
+        done.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(alloc.stats().allocations, 8_000);
+}
+
+#[test]
+fn paused_allocators_pass_requests_through() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    alloc.pause();
+    assert!(alloc.is_paused());
+    unsafe {
+        alloc.dealloc(alloc.alloc(layout), layout);
+    }
+    assert_eq!(alloc.stats(), Default::default());
+    alloc.resume();
+    unsafe {
+        alloc.dealloc(alloc.alloc(layout), layout);
+    }
+    assert_eq!(alloc.stats().allocations, 1);
+}
+
+static HOOKED: StatsAlloc<System> = StatsAlloc::system();
+
+struct Logging {
+    calls: AtomicUsize,
+    last_bytes_current: AtomicI64,
+}
+
+impl AllocHook for Logging {
+    fn on_alloc(&self, layout: Layout, counters: &StatsCounters) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.last_bytes_current
+            .store(counters.bytes_current(), Ordering::SeqCst);
+        // Allocating from within the hook must not run it again.
+        unsafe { HOOKED.dealloc(HOOKED.alloc(layout), layout) };
+    }
+
+    fn on_realloc(&self, _: Layout, new_size: usize, counters: &StatsCounters) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(new_size, 64);
+        self.last_bytes_current
+            .store(counters.bytes_current(), Ordering::SeqCst);
+    }
+
+    fn on_dealloc(&self, _: Layout, counters: &StatsCounters) {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.last_bytes_current
+            .store(counters.bytes_current(), Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn hooks_run_after_each_counted_operation() {
+    static HOOK: Logging = Logging {
+        calls: AtomicUsize::new(0),
+        last_bytes_current: AtomicI64::new(0),
+    };
+    HOOKED.set_hook(&HOOK);
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    unsafe {
+        let ptr = HOOKED.alloc(layout);
+        assert_eq!(HOOK.last_bytes_current.load(Ordering::SeqCst), 16);
+        let ptr = HOOKED.realloc(ptr, layout, 64);
+        assert_eq!(HOOK.last_bytes_current.load(Ordering::SeqCst), 64);
+        HOOKED.dealloc(ptr, Layout::from_size_align(64, 8).unwrap());
+        assert_eq!(HOOK.last_bytes_current.load(Ordering::SeqCst), 0);
+    }
+    assert_eq!(HOOK.calls.load(Ordering::SeqCst), 3);
+    assert_eq!(HOOKED.stats().allocations, 2);
+    HOOKED.clear_hook();
+    unsafe { HOOKED.dealloc(HOOKED.alloc(layout), layout) };
+    assert_eq!(HOOK.calls.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn thresholds_rearm_once_bytes_fall_far_enough() {
+    static CROSSINGS: AtomicUsize = AtomicUsize::new(0);
+    let alloc = StatsAlloc::system();
+    alloc.on_threshold_with_hysteresis(1000, 500, |bytes| {
+        assert!(bytes >= 1000);
+        CROSSINGS.fetch_add(1, Ordering::SeqCst);
+    });
+    let large = Layout::from_size_align(800, 8).unwrap();
+    let small = Layout::from_size_align(300, 8).unwrap();
+    unsafe {
+        let a = alloc.alloc(large);
+        let b = alloc.alloc(small);
+        assert_eq!(CROSSINGS.load(Ordering::SeqCst), 1);
+        // Hovering around the threshold does not run the callback again.
+        alloc.dealloc(b, small);
+        let b = alloc.alloc(small);
+        assert_eq!(CROSSINGS.load(Ordering::SeqCst), 1);
+        alloc.dealloc(a, large);
+        let a = alloc.alloc(large);
+        assert_eq!(CROSSINGS.load(Ordering::SeqCst), 2);
+        alloc.clear_threshold();
+        alloc.dealloc(a, large);
+        alloc.dealloc(alloc.alloc(large), large);
+        alloc.dealloc(b, small);
+    }
+    assert_eq!(CROSSINGS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn huge_allocations_run_the_warning_callback() {
+    static WARNED: AtomicUsize = AtomicUsize::new(0);
+    let alloc = StatsAlloc::system();
+    alloc.on_huge_allocation(64 * 1024, |event| {
+        assert!(event.new_size >= 64 * 1024);
+        WARNED.fetch_add(1, Ordering::SeqCst);
+    });
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let huge = Layout::from_size_align(128 * 1024, 8).unwrap();
+    unsafe {
+        alloc.dealloc(alloc.alloc(huge), huge);
+        let ptr = alloc.alloc(small);
+        let ptr = alloc.realloc(ptr, small, 128 * 1024);
+        // Growing a block which is already huge is not warned of again.
+        let ptr = alloc.realloc(ptr, huge, 256 * 1024);
+        alloc.dealloc(ptr, Layout::from_size_align(256 * 1024, 8).unwrap());
+    }
+    assert_eq!(WARNED.load(Ordering::SeqCst), 2);
+    alloc.clear_huge_allocation_warning();
+    unsafe { alloc.dealloc(alloc.alloc(huge), huge) };
+    assert_eq!(WARNED.load(Ordering::SeqCst), 2);
+}
+
+struct Stalling;
+
+unsafe impl GlobalAlloc for Stalling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() >= 4096 {
+            thread::sleep(Duration::from_millis(20));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[test]
+fn slow_calls_are_timed_and_reported() {
+    static SLOW: AtomicUsize = AtomicUsize::new(0);
+    let alloc = StatsAlloc::new(Stalling);
+    alloc.on_slow_allocation(Duration::from_millis(10), |event| {
+        assert_eq!(event.layout.size(), 4096);
+        assert!(event.elapsed.unwrap() >= Duration::from_millis(20));
+        SLOW.fetch_add(1, Ordering::SeqCst);
+    });
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let large = Layout::from_size_align(4096, 8).unwrap();
+    unsafe { alloc.dealloc(alloc.alloc(large), large) };
+    assert_eq!(SLOW.load(Ordering::SeqCst), 0);
+    alloc.set_latency_tracking(true);
+    assert!(alloc.latency_tracking());
+    unsafe {
+        for _ in 0..8 {
+            alloc.dealloc(alloc.alloc(small), small);
+        }
+        alloc.dealloc(alloc.alloc(large), large);
+    }
+    assert_eq!(SLOW.load(Ordering::SeqCst), 1);
+    let latency = alloc.latency_histogram();
+    assert_eq!(latency.count(), 18);
+    assert_eq!(latency.buckets().iter().map(|&(_, n)| n).sum::<u64>(), 18);
+    assert!(latency.quantile(0.5).unwrap() < Duration::from_millis(10));
+    assert!(latency.quantile(1.0).unwrap() > Duration::from_millis(20));
+    alloc.set_latency_tracking(false);
+    unsafe { alloc.dealloc(alloc.alloc(large), large) };
+    assert_eq!(alloc.latency_histogram().count(), 18);
+    alloc.set_latency_tracking(true);
+    assert_eq!(alloc.latency_histogram().quantile(0.5), None);
+}
+
+#[test]
+fn subscribers_receive_only_the_events_passing_their_filters() {
+    static LARGE: AtomicUsize = AtomicUsize::new(0);
+    static REALLOCS: AtomicUsize = AtomicUsize::new(0);
+    let alloc = StatsAlloc::system();
+    let large = alloc.subscribe(EventFilter::default().min_size(64 * 1024), |event| {
+        assert!(event.size() >= 64 * 1024);
+        LARGE.fetch_add(1, Ordering::SeqCst);
+    });
+    let reallocs = alloc.subscribe(EventFilter::default().only(&[EventKind::Realloc]), |event| {
+        assert_eq!((event.kind, event.new_size), (EventKind::Realloc, 128 * 1024));
+        REALLOCS.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(reallocs.filter(), EventFilter::default().only(&[EventKind::Realloc]));
+    let small = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        alloc.dealloc(alloc.alloc(small), small);
+        let ptr = alloc.alloc(small);
+        let ptr = alloc.realloc(ptr, small, 128 * 1024);
+        alloc.dealloc(ptr, Layout::from_size_align(128 * 1024, 8).unwrap());
+    }
+    assert_eq!(LARGE.load(Ordering::SeqCst), 2);
+    assert_eq!(REALLOCS.load(Ordering::SeqCst), 1);
+    large.cancel();
+    drop(reallocs);
+    let huge = Layout::from_size_align(128 * 1024, 8).unwrap();
+    unsafe { alloc.dealloc(alloc.alloc(huge), huge) };
+    assert_eq!(LARGE.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn cancelled_subscriptions_free_their_callbacks() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let received = Arc::new(AtomicUsize::new(0));
+    for round in 1..=3 {
+        let counter = Arc::clone(&received);
+        let subscription = alloc.subscribe(EventFilter::default().only(&[EventKind::Alloc]), move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+        assert_eq!(received.load(Ordering::SeqCst), round);
+        drop(subscription);
+        assert_eq!(Arc::strong_count(&received), 1);
+    }
+    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    assert_eq!(received.load(Ordering::SeqCst), 3);
+}
+
+struct Unbacked;
+
+unsafe impl GlobalAlloc for Unbacked {
+    unsafe fn alloc(&self, _layout: Layout) -> *mut u8 {
+        ptr::NonNull::dangling().as_ptr()
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[test]
+fn wrapped_totals_are_detected_and_differences_stay_exact() {
+    let alloc = StatsAlloc::new(Unbacked);
+    alloc.set_huge_threshold(usize::MAX);
+    let layout = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+    let mut before = alloc.stats();
+    for _ in 0..3 {
+        before = alloc.stats();
+        unsafe {
+            alloc.alloc(layout);
+        }
+    }
+    let after = alloc.stats();
+    assert_eq!(after.overflows, 1);
+    assert!(after.bytes_allocated < before.bytes_allocated);
+    assert_eq!((after - before).bytes_allocated, isize::MAX as u64);
+}
+
+#[test]
+fn stats_can_be_subtracted_without_wrapping() {
+    let larger = Stats::builder().allocations(5).bytes_reallocated(-8).build();
+    let smaller = Stats::builder().allocations(2).bytes_reallocated(8).build();
+    let change = larger.checked_sub(smaller).unwrap();
+    assert_eq!((change.allocations, change.bytes_reallocated), (3, -16));
+    assert_eq!(smaller.checked_sub(larger), None);
+    let saturated = smaller.saturating_sub(larger);
+    assert_eq!((saturated.allocations, saturated.bytes_reallocated), (0, 16));
+}
+
+#[test]
+fn deltas_are_signed() {
+    let earlier = Stats::builder().allocations(2).bytes_allocated(64).build();
+    let later = Stats::builder().allocations(5).bytes_allocated(96).build();
+    assert_eq!(later.delta(&earlier).allocations, 3);
+    let backwards = earlier.delta(&later);
+    assert_eq!((backwards.allocations, backwards.bytes_allocated), (-3, -32));
+    assert_eq!(later.delta(&later), StatsDelta::default());
+}
+
+#[test]
+fn derived_metrics_are_computed_from_fields() {
+    let stats = Stats::builder()
+        .allocations(4)
+        .deallocations(1)
+        .reallocations(2)
+        .bytes_allocated(100)
+        .bytes_current(60)
+        .build();
+    assert_eq!(stats.bytes_in_use(), 60);
+    assert_eq!(stats.outstanding_allocations(), 3);
+    assert_eq!(stats.average_allocation_size(), Some(25.0));
+    assert_eq!(stats.total_operations(), 7);
+    assert_eq!(Stats::default().average_allocation_size(), None);
+}
+
+#[test]
+fn balanced_regions_have_no_leaked_bytes() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    unsafe {
+        let ptr = alloc.realloc(alloc.alloc(layout), layout, 80);
+        let change = alloc.stats();
+        assert!(!change.is_balanced());
+        assert_eq!(change.leaked_bytes(), 80);
+        alloc.dealloc(ptr, Layout::from_size_align(80, 8).unwrap());
+    }
+    assert!(alloc.stats().is_balanced());
+    assert_eq!(alloc.stats().leaked_bytes(), 0);
+}
+
+#[test]
+fn regions_carry_maxima_through_rather_than_differencing() {
+    let alloc = StatsAlloc::system();
+    let small = Layout::from_size_align(10 * 1024, 8).unwrap();
+    let large = Layout::from_size_align(70 * 1024, 8).unwrap();
+    let budget = StatsBuilder::from(Stats::unlimited())
+        .max_allocation_size(64 * 1024)
+        .build();
+    unsafe {
+        let block = alloc.alloc(small);
+        let region = Region::new(&alloc);
+        assert_eq!(region.change().max_allocation_size, 10 * 1024);
+        assert_eq!(region.change().peak_live_allocations, 1);
+        alloc.dealloc(alloc.alloc(large), large);
+        let change = region.change();
+        assert_eq!(change.max_allocation_size, 70 * 1024);
+        let peak = if cfg!(feature = "sharded") { 1 } else { 2 };
+        assert_eq!(change.peak_live_allocations, peak);
+        assert!(!change.fits_within(&budget));
+        assert_eq!(change.checked_sub(Stats::default()), Some(change));
+        assert_eq!((change + region.initial()).max_allocation_size, 70 * 1024);
+        alloc.dealloc(block, small);
+    }
+}
+
+#[test]
+fn budgets_report_every_violation() {
+    let budget = StatsBuilder::from(Stats::unlimited())
+        .allocations(3)
+        .bytes_allocated(4096)
+        .build();
+    let within = Stats::builder().allocations(3).bytes_allocated(1024).build();
+    assert!(within.fits_within(&budget));
+    assert_eq!(within.check_budget(&budget), Ok(()));
+
+    let over = Stats::builder().allocations(5).bytes_allocated(8192).build();
+    assert!(!over.fits_within(&budget));
+    let exceeded = over.check_budget(&budget).unwrap_err();
+    let fields: Vec<_> = exceeded.violations.iter().map(|v| v.field).collect();
+    assert_eq!(fields, ["allocations", "bytes_allocated"]);
+    assert_eq!(
+        exceeded.to_string(),
+        "allocation budget exceeded: allocations was 5, exceeding the budget of 3; \
+         bytes_allocated was 8192, exceeding the budget of 4096"
+    );
+}
+
+#[test]
+fn checked_regions_accept_released_allocations() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let guard = Region::enter_checked(&alloc);
+    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    assert_eq!(guard.region().change().allocations, 1);
+}
+
+#[test]
+#[should_panic(expected = "region leaked 1 allocations and 32 bytes")]
+fn checked_regions_panic_on_leaks() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let _guard = Region::enter_checked(&alloc);
+    let leaked = unsafe { alloc.alloc(layout) };
+    assert!(!leaked.is_null());
+}
+
+#[test]
+fn checked_regions_can_log_leaks() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let guard = Region::enter_checked(&alloc).on_leak(LeakAction::Log);
+    let leaked = unsafe { alloc.alloc(layout) };
+    drop(guard);
+    assert_eq!(alloc.stats().live_allocations, 1);
+    unsafe { alloc.dealloc(leaked, layout) };
+}
+
+#[test]
+fn nested_regions_report_to_their_parent() {
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    let outer = Region::nested(&alloc, "deserialize");
+    for &(name, size) in &[("header", 2048), ("body", 38 * 1024)] {
+        let _child = Region::nested(&alloc, name);
+        unsafe { alloc.dealloc(alloc.alloc(layout(size)), layout(size)) };
+    }
+    let breakdown = outer.breakdown();
+    assert_eq!(breakdown.change.allocations, 2);
+    let children: Vec<_> = breakdown
+        .children
+        .iter()
+        .map(|c| (c.name, c.change.bytes_allocated))
+        .collect();
+    assert_eq!(children, [("header", 2048), ("body", 38 * 1024)]);
+    assert_eq!(
+        breakdown.to_string(),
+        "deserialize: 40 KiB, of which header: 2.0 KiB, body: 38 KiB"
+    );
+}
+
+#[test]
+fn paused_regions_exclude_spans() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let cycle = || unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    let mut region = Region::new(&alloc);
+    cycle();
+    region.pause();
+    assert!(region.is_paused());
+    cycle();
+    cycle();
+    assert_eq!(region.change().allocations, 1);
+    region.resume();
+    cycle();
+    assert_eq!(region.change().allocations, 2);
+    assert_eq!(region.change().bytes_deallocated, 32);
+    assert_eq!(alloc.stats().allocations, 4);
+}
+
+#[test]
+fn timed_regions_measure_time_and_allocations() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let mut region = TimedRegion::new(&alloc);
+    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    thread::sleep(Duration::from_millis(5));
+    let report = region.report_and_reset();
+    assert_eq!(report.change.allocations, 1);
+    assert!(report.elapsed >= Duration::from_millis(5));
+    assert_eq!(region.report().change.allocations, 0);
+}
+
+#[test]
+fn regions_track_their_peak_on_this_thread() {
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    let outer = Region::new(&alloc);
+    unsafe {
+        alloc.dealloc(alloc.alloc(layout(1000)), layout(1000));
+        let kept = alloc.alloc(layout(100));
+        {
+            let inner = Region::new(&alloc);
+            alloc.dealloc(alloc.alloc(layout(200)), layout(200));
+            assert_eq!(inner.peak(), 200);
+        }
+        assert_eq!(outer.peak(), 1000);
+        assert_eq!(outer.change().bytes_current, 100);
+
+        // Allocations by other threads are not counted.
+        let alloc = &alloc;
+        thread::scope(|s| {
+            s.spawn(|| alloc.dealloc(alloc.alloc(layout(4096)), layout(4096)));
+        });
+        assert_eq!(outer.peak(), 1000);
+        alloc.dealloc(kept, layout(100));
+    }
+}
+
+#[test]
+fn regions_moved_to_another_thread_leave_its_marks_alone() {
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    unsafe { alloc.dealloc(alloc.alloc(layout(4096)), layout(4096)) };
+    let moved = Region::new(&alloc);
+    thread::scope(|s| {
+        s.spawn(|| {
+            let local = Region::new(&alloc);
+            unsafe { alloc.dealloc(alloc.alloc(layout(300)), layout(300)) };
+            assert_eq!(moved.peak(), 0);
+            drop(moved);
+            assert_eq!(local.peak(), 300);
+        });
+    });
+}
+
+#[test]
+fn no_alloc_scopes_permit_other_work() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let ptr = unsafe { alloc.alloc(layout) };
+    let sum = assert_no_alloc(|| {
+        unsafe { alloc.dealloc(ptr, layout) };
+        (1..=4).sum::<u32>()
+    });
+    assert_eq!(sum, 10);
+}
+
+#[test]
+#[should_panic(expected = "allocation of 24 bytes in a no-allocation scope")]
+fn no_alloc_scopes_panic_on_allocation() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    assert_no_alloc(|| unsafe { alloc.dealloc(alloc.alloc(layout), layout) });
+}
+
+#[test]
+fn no_alloc_scopes_end_with_their_guard() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    drop(DenyAllocGuard::new());
+    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+}
+
+#[test]
+fn no_alloc_scopes_outlive_the_panic_of_a_nested_one() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let outer = DenyAllocGuard::new();
+    let inner = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _inner = DenyAllocGuard::new();
+        unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    }));
+    assert!(inner.is_err());
+    unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(outer))).is_err());
+}
+
+#[test]
+fn byte_limits_fail_allocations_past_the_ceiling() {
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    alloc.set_byte_limit(Some(100));
+    assert_eq!(alloc.byte_limit(), Some(100));
+    unsafe {
+        let first = alloc.alloc(layout(64));
+        assert!(!first.is_null());
+        assert!(alloc.alloc(layout(64)).is_null());
+        assert!(alloc.realloc(first, layout(64), 128).is_null());
+        let shrunk = alloc.realloc(first, layout(64), 32);
+        assert!(!shrunk.is_null());
+        let second = alloc.alloc_zeroed(layout(64));
+        assert!(!second.is_null());
+        alloc.dealloc(shrunk, layout(32));
+        alloc.dealloc(second, layout(64));
+    }
+    assert_eq!(alloc.stats().allocation_failures, 2);
+
+    // While paused, requests pass straight through without being limited.
+    alloc.pause();
+    unsafe {
+        let large = alloc.alloc(layout(256));
+        assert!(!large.is_null());
+        alloc.dealloc(large, layout(256));
+    }
+    alloc.resume();
+    assert!(unsafe { alloc.alloc(layout(256)) }.is_null());
+    alloc.set_byte_limit(None);
+    assert_eq!(alloc.byte_limit(), None);
+}
+
+#[test]
+fn injected_failures_exercise_every_allocation() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    // Makes three requests, returning how many succeeded before the first
+    // failure.
+    let attempt = || unsafe {
+        let first = alloc.alloc(layout);
+        if first.is_null() {
+            return 0;
+        }
+        let grown = alloc.realloc(first, layout, 32);
+        if grown.is_null() {
+            alloc.dealloc(first, layout);
+            return 1;
+        }
+        let second = alloc.alloc_zeroed(layout);
+        alloc.dealloc(grown, Layout::from_size_align(32, 8).unwrap());
+        if second.is_null() {
+            return 2;
+        }
+        alloc.dealloc(second, layout);
+        3
+    };
+    for n in 1..=4 {
+        alloc.fail_allocation(n);
+        assert_eq!(alloc.pending_failure(), Some(n));
+        assert_eq!(attempt(), (n - 1).min(3));
+    }
+    assert_eq!(alloc.pending_failure(), Some(1));
+    alloc.fail_allocation(0);
+    assert_eq!(alloc.pending_failure(), None);
+    assert_eq!(alloc.stats().allocation_failures, 3);
+}
+
+#[test]
+fn random_failures_are_reproducible() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let pattern = |probability, seed| {
+        alloc.set_failure_probability(probability, seed);
+        (0..1000)
+            .map(|_| unsafe {
+                let ptr = alloc.alloc(layout);
+                if !ptr.is_null() {
+                    alloc.dealloc(ptr, layout);
+                }
+                ptr.is_null()
+            })
+            .collect::<Vec<_>>()
+    };
+    let first = pattern(0.25, 42);
+    assert_eq!(alloc.failure_probability(), 0.25);
+    let failures = first.iter().filter(|&&failed| failed).count();
+    assert!(failures > 150 && failures < 350, "{} failures", failures);
+    assert_eq!(pattern(0.25, 42), first);
+    assert_ne!(pattern(0.25, 7), first);
+    assert!(pattern(1.0, 0).iter().all(|&failed| failed));
+    assert!(!pattern(0.0, 0).iter().any(|&failed| failed));
+}
+
+#[test]
+fn fault_guards_affect_only_their_thread_and_scope() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    let succeeds = |alloc: &StatsAlloc<System>| unsafe {
+        let ptr = alloc.alloc(layout);
+        if !ptr.is_null() {
+            alloc.dealloc(ptr, layout);
+        }
+        !ptr.is_null()
+    };
+    {
+        let _faults = FaultGuard::fail_with_probability(1.0, 0);
+        assert!(!succeeds(&alloc));
+        thread::scope(|s| assert!(s.spawn(|| succeeds(&alloc)).join().unwrap()));
+        {
+            let _inner = FaultGuard::fail_nth(2);
+            assert!(succeeds(&alloc));
+            assert!(!succeeds(&alloc));
+            assert!(succeeds(&alloc));
+        }
+        assert!(!succeeds(&alloc));
+    }
+    assert!(succeeds(&alloc));
+    assert_eq!(alloc.stats().allocation_failures, 3);
+}
+
+#[test]
+fn benchmarks_report_per_iteration_counts() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    let mut calls = 0;
+    let report = bench_allocs(&alloc, 5, || {
+        calls += 1;
+        unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    });
+    assert_eq!(calls, 6);
+    assert_eq!(report.iterations, 5);
+    let allocations = report.counter("allocations").unwrap();
+    assert_eq!((allocations.min, allocations.median), (1, 1));
+    assert_eq!(report.counter("bytes_allocated").unwrap().median, 48);
+    assert!(report.baseline.iter().all(|&(_, value)| value == 0));
+    assert!(report.counter("bogus").is_none());
+}
+
+#[test]
+fn live_tracking_enumerates_outstanding_allocations() {
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    unsafe {
+        let untracked = alloc.alloc(layout(8));
+        alloc.set_live_tracking(true);
+        assert!(alloc.live_tracking());
+        let mut ptrs: Vec<_> = (1..=1_000).map(|size| (alloc.alloc(layout(size)), size)).collect();
+        let tagged = {
+            let _tag = AllocTag::new("tagged");
+            alloc.alloc(layout(4_096))
+        };
+        for (ptr, size) in ptrs.drain(10..) {
+            alloc.dealloc(ptr, layout(size));
+        }
+        ptrs[0].0 = alloc.realloc(ptrs[0].0, layout(1), 2_000);
+        ptrs[0].1 = 2_000;
+        alloc.dealloc(untracked, layout(8));
+
+        let mut live: Vec<_> = alloc.outstanding().map(|a| (a.size, a.tag)).collect();
+        live.sort();
+        let mut expected: Vec<_> = (2..=10).chain(Some(2_000)).map(|size| (size, None)).collect();
+        expected.push((4_096, Some("tagged")));
+        assert_eq!(live, expected);
+        assert!(alloc
+            .outstanding()
+            .all(|a| a.align == 8 && a.age() < Duration::from_secs(60)));
+
+        for (ptr, size) in ptrs {
+            alloc.dealloc(ptr, layout(size));
+        }
+        alloc.dealloc(tagged, layout(4_096));
+    }
+    assert_eq!(alloc.outstanding().count(), 0);
+    alloc.set_live_tracking(false);
+    assert!(!alloc.live_tracking());
+}
+
+#[test]
+fn largest_outstanding_allocations_come_first() {
+    let alloc = StatsAlloc::system();
+    alloc.set_live_tracking(true);
+    let sizes = [64, 4096, 16, 4096, 512];
+    let blocks: Vec<_> = sizes
+        .iter()
+        .map(|&size| {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            (unsafe { alloc.alloc(layout) }, layout)
+        })
+        .collect();
+    let largest = alloc.largest_outstanding(3);
+    assert_eq!(largest.iter().map(|a| a.size).collect::<Vec<_>>(), [4096, 4096, 512]);
+    assert_eq!(alloc.largest_outstanding(10).len(), 5);
+    for (ptr, layout) in blocks {
+        unsafe { alloc.dealloc(ptr, layout) };
+    }
+    assert!(alloc.largest_outstanding(3).is_empty());
+    alloc.set_live_tracking(false);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn sampled_backtraces_are_kept_with_live_allocations() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    alloc.set_live_tracking(true);
+    alloc.set_backtrace_sampling(2);
+    assert_eq!(alloc.backtrace_sampling(), Some(2));
+    let ptrs: Vec<_> = (0..4).map(|_| unsafe { alloc.alloc(layout) }).collect();
+    let live: Vec<_> = alloc.outstanding().collect();
+    assert_eq!(live.len(), 4);
+    let sampled: Vec<_> = live.iter().filter_map(|a| a.backtrace.as_ref()).collect();
+    assert_eq!(sampled.len(), 2);
+    let shown = sampled[0].to_string();
+    assert!(
+        shown.starts_with("   0: counters::sampled_backtraces_are_kept_with_live_allocations"),
+        "{}",
+        shown
+    );
+    assert!(sampled.iter().all(|stack| stack.frames().len() > 1));
+    for ptr in ptrs {
+        unsafe { alloc.dealloc(ptr, layout) };
+    }
+    alloc.set_backtrace_sampling(0);
+    assert_eq!(alloc.backtrace_sampling(), None);
+    alloc.set_live_tracking(false);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn allocations_are_attributed_to_call_sites() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    alloc.set_call_site_tracking(true);
+    assert!(alloc.call_site_tracking());
+    let mut ptrs = Vec::new();
+    for _ in 0..4 {
+        ptrs.push(unsafe { alloc.alloc(layout) });
+    }
+    let grown = unsafe { alloc.realloc(ptrs.pop().unwrap(), layout, 48) };
+    let sites = alloc.call_sites();
+    assert_eq!(sites.len(), 2);
+    assert_eq!(
+        (sites[0].allocations, sites[0].reallocations, sites[0].bytes_allocated),
+        (4, 0, 128)
+    );
+    assert_eq!(
+        (sites[1].allocations, sites[1].reallocations, sites[1].bytes_allocated),
+        (0, 1, 16)
+    );
+    let shown = sites[0].to_string();
+    assert!(
+        shown.starts_with(
+            "128 bytes in 4 allocations and 0 reallocations at:\n   0: counters::allocations_are_attributed_to_call_sites"
+        ),
+        "{}",
+        shown
+    );
+    unsafe { alloc.dealloc(grown, Layout::from_size_align(48, 8).unwrap()) };
+    for ptr in ptrs {
+        unsafe { alloc.dealloc(ptr, layout) };
+    }
+    alloc.set_call_site_tracking(false);
+    assert!(alloc.call_sites().is_empty());
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn call_sites_are_written_as_dhat_profiles() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    alloc.set_call_site_tracking(true);
+    let ptrs: Vec<_> = (0..2).map(|_| unsafe { alloc.alloc(layout) }).collect();
+    unsafe { alloc.dealloc(ptrs[0], layout) };
+    let sites = alloc.call_sites();
+    assert_eq!(
+        (sites[0].live_allocations, sites[0].bytes_current, sites[0].peak_bytes),
+        (1, 32, 64)
+    );
+    let mut out = Vec::new();
+    alloc.write_dhat(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("{\"dhatFileVersion\":2\n"), "{}", out);
+    assert!(out.contains("{\"tb\":64,\"tbk\":2,\"tl\":"), "{}", out);
+    assert!(
+        out.contains(",\"mb\":64,\"mbk\":2,\"gb\":64,\"gbk\":2,\"eb\":32,\"ebk\":1,\"fs\":["),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("counters::call_sites_are_written_as_dhat_profiles"),
+        "{}",
+        out
+    );
+    assert!(out.ends_with("]\n}\n"), "{}", out);
+    unsafe { alloc.dealloc(ptrs[1], layout) };
+    alloc.set_call_site_tracking(false);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn call_sites_are_written_as_speedscope_profiles() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    alloc.set_call_site_tracking(true);
+    let ptrs: Vec<_> = (0..2).map(|_| unsafe { alloc.alloc(layout) }).collect();
+    unsafe { alloc.dealloc(ptrs[0], layout) };
+    let mut out = Vec::new();
+    alloc.write_speedscope(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(
+        out.starts_with("{\"$schema\":\"https://www.speedscope.app/file-format-schema.json\""),
+        "{}",
+        out
+    );
+    assert!(
+        out.contains("{\"name\":\"counters::call_sites_are_written_as_speedscope_profiles"),
+        "{}",
+        out
+    );
+    let profiles: Vec<_> = out
+        .lines()
+        .filter(|line| line.contains("\"type\":\"sampled\""))
+        .collect();
+    assert_eq!(profiles.len(), 3);
+    assert!(profiles[0].contains("\"name\":\"bytes allocated\",\"unit\":\"bytes\",\"startValue\":0,\"endValue\":64"));
+    assert!(profiles[1].contains("\"name\":\"bytes outstanding\",\"unit\":\"bytes\",\"startValue\":0,\"endValue\":32"));
+    assert!(out.contains("],\"weights\":[64]}"), "{}", out);
+    assert!(out.contains("],\"weights\":[2]}"), "{}", out);
+    unsafe { alloc.dealloc(ptrs[1], layout) };
+    alloc.set_call_site_tracking(false);
+}
+
+#[cfg(feature = "backtrace")]
+#[test]
+fn heaptrack_recorder_writes_allocations_and_releases() {
+    use stats_alloc::HeaptrackRecorder;
+    use std::{env, fs};
+
+    let alloc: &'static StatsAlloc<System> = Box::leak(Box::new(StatsAlloc::system()));
+    let path = env::temp_dir().join(format!("stats_alloc-{}.heaptrack", std::process::id()));
+    let recorder = HeaptrackRecorder::start(alloc, &path, Duration::from_secs(60)).unwrap();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    let ptrs: Vec<_> = (0..2).map(|_| unsafe { alloc.alloc(layout) }).collect();
+    unsafe { alloc.dealloc(ptrs[0], layout) };
+    recorder.stop();
+    let trace = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(lines[0], "v 10200 2");
+    assert!(lines[1].starts_with("X "));
+    let kinds: Vec<_> = lines.iter().filter(|line| line.starts_with("a ")).collect();
+    assert_eq!(kinds.len(), 1);
+    assert!(kinds[0].starts_with("a 20 "), "{}", trace);
+    assert_eq!(lines.iter().filter(|&&line| line == "+ 0").count(), 2);
+    assert_eq!(lines.iter().filter(|&&line| line == "- 0").count(), 1);
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("s counters::heaptrack_recorder_writes_allocations_and_releases")),
+        "{}",
+        trace
+    );
+    unsafe { alloc.dealloc(ptrs[1], layout) };
+}
@@ -0,0 +1,23 @@
+extern crate libtest_mimic;
+extern crate stats_alloc;
+
+use libtest_mimic::Arguments;
+use stats_alloc::{run_tests, AllocTest, StatsAlloc, INSTRUMENTED_SYSTEM};
+use std::alloc::System;
+
+#[global_allocator]
+static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+
+fn main() {
+    let tests = vec![
+        AllocTest::new("within_limits", || {
+            let x: Vec<u8> = Vec::with_capacity(1_024);
+            let _ = ::std::mem::size_of_val(&x);
+            Ok(())
+        })
+        .max_allocations(1)
+        .max_bytes_allocated(1_024),
+        AllocTest::new("no_allocations", || Ok(())).max_allocations(0),
+    ];
+    run_tests(GLOBAL, &Arguments::from_args(), tests).exit();
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "log")]
+
+extern crate log;
+extern crate stats_alloc;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use stats_alloc::{Region, StatsAlloc};
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    sync::{Mutex, Once},
+};
+
+static LOGGED: Mutex<Vec<(Level, String)>> = Mutex::new(Vec::new());
+static INIT: Once = Once::new();
+
+struct Capture;
+
+impl Log for Capture {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target() == "stats_alloc" && metadata.level() <= Level::Info
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            LOGGED.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the capturing logger, once for all tests.
+fn capture() {
+    INIT.call_once(|| {
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(LevelFilter::Info);
+    });
+}
+
+/// Returns the messages logged at `level`.
+fn logged(level: Level) -> Vec<String> {
+    LOGGED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|&&(logged, _)| logged == level)
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+#[test]
+fn named_regions_log_their_change() {
+    capture();
+
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(16, 8).unwrap();
+    {
+        let region = Region::named(&alloc, "parse_phase");
+        assert_eq!(region.name(), Some("parse_phase"));
+        unsafe { alloc.dealloc(alloc.alloc(layout), layout) };
+    }
+    drop(Region::new(&alloc));
+
+    let logged = logged(Level::Info);
+    assert_eq!(logged.len(), 1);
+    assert!(logged[0].starts_with("region parse_phase: alloc: 1 ops / 16 B"));
+}
+
+#[test]
+fn huge_allocations_are_logged_as_warnings() {
+    capture();
+
+    let alloc = StatsAlloc::system();
+    alloc.warn_on_huge_allocation(64 * 1024);
+    let huge = Layout::from_size_align(128 * 1024, 8).unwrap();
+    unsafe { alloc.dealloc(alloc.alloc(huge), huge) };
+
+    let logged = logged(Level::Warn);
+    assert_eq!(logged.len(), 1);
+    assert!(logged[0].starts_with("huge allocation of 131072 bytes"));
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "macros")]
+
+extern crate stats_alloc;
+
+use stats_alloc::{StatsAlloc, INSTRUMENTED_SYSTEM};
+use std::{alloc::System, thread, time::Duration};
+
+#[global_allocator]
+static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+
+#[stats_alloc::test]
+fn measures_without_a_budget() {
+    let _ = vec![0u8; 1024];
+}
+
+#[stats_alloc::test(alloc = GLOBAL, max_allocs = 1_000, reallocations = 1_000)]
+fn passes_within_budget() -> Result<(), String> {
+    let mut buffer = Vec::with_capacity(1);
+    buffer.extend_from_slice(&[1u8; 64]);
+    Ok(())
+}
+
+#[stats_alloc::test(max_allocs = 0)]
+#[should_panic(expected = "exceeds_budget: allocation budget exceeded: allocations was")]
+fn exceeds_budget() {
+    let _ = vec![0u8; 16];
+}
+
+#[stats_alloc::test(max_allocs = 100)]
+fn waits_while_others_allocate() {
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[stats_alloc::test(max_allocs = 2_000)]
+fn allocates_while_others_wait() {
+    for _ in 0..1_000 {
+        drop(Box::new(0u64));
+        thread::sleep(Duration::from_micros(100));
+    }
+}
+
+#[test]
+fn concurrent_tests_do_not_count_each_other() {
+    let waiting = thread::spawn(waits_while_others_allocate);
+    let allocating = thread::spawn(allocates_while_others_wait);
+    assert!(waiting.join().is_ok());
+    assert!(allocating.join().is_ok());
+}
@@ -0,0 +1,18 @@
+#![cfg(feature = "noop")]
+
+extern crate stats_alloc;
+
+use stats_alloc::{Stats, StatsAlloc};
+use std::alloc::{GlobalAlloc, Layout};
+
+#[test]
+fn nothing_is_counted() {
+    let alloc = StatsAlloc::system();
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = alloc.alloc(layout);
+        assert!(!ptr.is_null());
+        alloc.dealloc(alloc.realloc(ptr, layout, 64), Layout::from_size_align(64, 8).unwrap());
+    }
+    assert_eq!(alloc.stats(), Stats::default());
+}
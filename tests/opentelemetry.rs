@@ -0,0 +1,102 @@
+#![cfg(feature = "opentelemetry")]
+
+extern crate opentelemetry;
+extern crate stats_alloc;
+
+use opentelemetry::{
+    metrics::{AsyncInstrument, AsyncInstrumentBuilder, InstrumentProvider, Meter, ObservableCounter, ObservableGauge},
+    KeyValue,
+};
+use stats_alloc::{register_instruments, StatsAlloc};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{Arc, Mutex},
+};
+
+static ALLOC: StatsAlloc<System> = StatsAlloc::system();
+
+/// An instrument's name, unit, kind and the value its callback observed.
+type Observation = (String, Option<String>, &'static str, i128);
+
+#[derive(Default)]
+struct Capture(Mutex<Vec<Observation>>);
+
+struct Observer<'a>(&'a Mutex<Option<i128>>);
+
+impl<'a, T: Into<i128>> AsyncInstrument<T> for Observer<'a> {
+    fn observe(&self, measurement: T, _: &[KeyValue]) {
+        *self.0.lock().unwrap() = Some(measurement.into());
+    }
+}
+
+impl Capture {
+    fn observe<I, M: Into<i128>>(&self, builder: AsyncInstrumentBuilder<'_, I, M>, kind: &'static str) {
+        let observed = Mutex::new(None);
+        for callback in &builder.callbacks {
+            callback(&Observer(&observed));
+        }
+        let value = observed.into_inner().unwrap().expect("callback observed a value");
+        let unit = builder.unit.map(|unit| unit.into_owned());
+        self.0
+            .lock()
+            .unwrap()
+            .push((builder.name.into_owned(), unit, kind, value));
+    }
+}
+
+impl InstrumentProvider for Capture {
+    fn u64_observable_counter(
+        &self,
+        builder: AsyncInstrumentBuilder<'_, ObservableCounter<u64>, u64>,
+    ) -> ObservableCounter<u64> {
+        self.observe(builder, "counter");
+        ObservableCounter::new()
+    }
+
+    fn i64_observable_gauge(
+        &self,
+        builder: AsyncInstrumentBuilder<'_, ObservableGauge<i64>, i64>,
+    ) -> ObservableGauge<i64> {
+        self.observe(builder, "gauge");
+        ObservableGauge::new()
+    }
+}
+
+#[test]
+fn instruments_observe_current_stats() {
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    let ptr = unsafe { ALLOC.alloc(layout) };
+
+    let capture = Arc::new(Capture::default());
+    register_instruments(&ALLOC, &Meter::new(capture.clone()));
+    unsafe { ALLOC.dealloc(ptr, layout) };
+
+    let observed = capture.0.lock().unwrap();
+    let find = |name: &str| observed.iter().find(|o| o.0 == name).cloned().unwrap();
+    assert_eq!(
+        find("stats_alloc.allocations"),
+        ("stats_alloc.allocations".to_owned(), None, "counter", 1)
+    );
+    assert_eq!(
+        find("stats_alloc.bytes_allocated"),
+        (
+            "stats_alloc.bytes_allocated".to_owned(),
+            Some("By".to_owned()),
+            "counter",
+            48
+        )
+    );
+    assert_eq!(
+        find("stats_alloc.bytes_current"),
+        (
+            "stats_alloc.bytes_current".to_owned(),
+            Some("By".to_owned()),
+            "gauge",
+            48
+        )
+    );
+    assert_eq!(
+        find("stats_alloc.live_allocations"),
+        ("stats_alloc.live_allocations".to_owned(), None, "gauge", 1)
+    );
+}
@@ -0,0 +1,358 @@
+extern crate stats_alloc;
+
+use stats_alloc::{
+    record_test, write_results, ChromeTraceExporter, ChurnMeter, CsvRecorder, ExitReport, InfluxExporter,
+    JsonLinesExporter, MassifExporter, MeasurementResult, PrometheusExporter, RegionReport, Reporter, Snapshot, Stats,
+    StatsAccumulator, StatsExporter, StatsdExporter, Template, TemplateError, TestSummary, TimedReport, UdpWriter,
+    INSTRUMENTED_SYSTEM,
+};
+use std::{
+    env, fs,
+    io::{self, Write},
+    iter::FromIterator,
+    net::UdpSocket,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[test]
+fn template_renders_totals_and_rates() {
+    let template: Template = "{{{allocations}}} total, {allocations/s}/s".parse().unwrap();
+    let previous = Stats::builder().allocations(10).build();
+    let current = Stats::builder().allocations(30).build();
+    assert_eq!(
+        template.render(&current, &previous, Duration::from_secs(2)),
+        "{30} total, 10/s"
+    );
+    assert_eq!(
+        Template::parse("{bogus}"),
+        Err(TemplateError::UnknownField("bogus".to_owned()))
+    );
+    assert_eq!(Template::parse("{allocations"), Err(TemplateError::Unclosed));
+
+    let template: Template = "{bytes_in_use} live, {allocations}/s".parse().unwrap();
+    let current = Stats::builder().allocations(30).bytes_current(3072).build();
+    assert_eq!(
+        template.render(&current, &previous, Duration::from_secs(2)),
+        "3072 live, 30/s"
+    );
+}
+
+#[test]
+#[cfg(not(feature = "noop"))]
+fn templates_follow_reallocations_in_bytes_in_use() {
+    use stats_alloc::StatsAlloc;
+    use std::alloc::{GlobalAlloc, Layout};
+
+    let template: Template = "{bytes_in_use} live, {allocations}/s".parse().unwrap();
+    let alloc = StatsAlloc::system();
+    let layout = |size| Layout::from_size_align(size, 8).unwrap();
+    let stats = unsafe {
+        let ptr = alloc.realloc(alloc.alloc(layout(1024)), layout(1024), 4096);
+        let ptr = alloc.realloc(ptr, layout(4096), 512);
+        let stats = alloc.stats();
+        alloc.dealloc(ptr, layout(512));
+        stats
+    };
+    assert_eq!(stats.allocations, 1);
+    assert_eq!(
+        template.render(&stats, &Stats::default(), Duration::from_secs(1)),
+        "512 live, 1/s"
+    );
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn reporter_writes_periodic_lines() {
+    let buffer = SharedBuffer::default();
+    let template = Template::parse("allocs: {allocations}").unwrap();
+    let reporter = Reporter::spawn(
+        &INSTRUMENTED_SYSTEM,
+        Duration::from_millis(10),
+        template,
+        buffer.clone(),
+    )
+    .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    reporter.stop();
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert!(output.lines().count() >= 1);
+    assert!(output.lines().all(|line| line.starts_with("allocs: ")));
+}
+
+#[test]
+fn churn_meter_tracks_current_and_max_rates() {
+    let mut meter = ChurnMeter::new();
+    let mut stats = Stats::default();
+    assert_eq!(meter.sample(&stats), 0.0);
+    thread::sleep(Duration::from_millis(10));
+    stats.bytes_allocated = 4_096;
+    stats.bytes_deallocated = 4_096;
+    let first = meter.sample(&stats);
+    assert!(first > 0.0);
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(meter.sample(&stats), 0.0);
+    assert_eq!(meter.max_rate(), first);
+}
+
+#[test]
+fn results_are_written_as_versioned_json() {
+    let stats = Stats::builder().allocations(2).bytes_allocated(64).build();
+    let mut out = Vec::new();
+    write_results(&mut out, &[MeasurementResult::new("a \"quoted\" id", stats)]).unwrap();
+    let json = String::from_utf8(out).unwrap();
+    assert!(json.starts_with("{\"schema\":\"stats_alloc.results\",\"version\":1,\"results\":[{"));
+    assert!(json.contains("\"id\":\"a \\\"quoted\\\" id\",\"iterations\":1"));
+    assert!(json.contains("\"counters\":{\"allocations\":2,\"deallocations\":0,"));
+    assert!(json.ends_with("}}]}"));
+    let counters = stats.to_json();
+    assert!(json.contains(&format!("\"counters\":{}}}", counters)));
+    assert!(counters.ends_with(",\"overflows\":0}"));
+}
+
+#[test]
+fn accumulator_summarizes_runs() {
+    let mut runs = StatsAccumulator::new();
+    for &allocations in &[2, 4, 6] {
+        runs.push(Stats::builder().allocations(allocations).build());
+    }
+    let summary = runs.counter("allocations").unwrap();
+    assert_eq!((summary.min, summary.max), (2, 6));
+    assert_eq!(summary.mean, 4.0);
+    assert_eq!(summary.stddev, 2.0);
+    assert!(runs.counter("bogus").is_none());
+    assert!(runs.counter("max_allocation_size").is_none());
+    assert!(runs.counters().iter().all(|&(name, _)| name != "peak_live_allocations"));
+}
+
+#[test]
+fn exporters_share_a_common_interface() {
+    let snapshot = Snapshot {
+        stats: Stats::builder().allocations(3).bytes_reallocated(-8).build(),
+        previous: Stats::default(),
+        elapsed: Duration::from_secs(1),
+        time: UNIX_EPOCH + Duration::from_secs(10),
+    };
+    let mut exporters: Vec<Box<dyn StatsExporter>> = vec![
+        Box::new(Template::parse("{allocations/s}/s").unwrap()),
+        Box::new(JsonLinesExporter),
+        Box::new(PrometheusExporter::new("app")),
+        Box::new(
+            InfluxExporter::new("app mem")
+                .tag("thread", "main,1")
+                .tag("path", "a\\b"),
+        ),
+    ];
+    let mut outputs = Vec::new();
+    for exporter in &mut exporters {
+        let mut out = Vec::new();
+        exporter.begin(&mut out).unwrap();
+        exporter.export(&mut out, &snapshot).unwrap();
+        exporter.finish(&mut out).unwrap();
+        outputs.push(String::from_utf8(out).unwrap());
+    }
+    assert_eq!(outputs[0], "3/s\n");
+    assert!(outputs[1].starts_with("{\"time\":10,\"elapsed\":1,\"counters\":{\"allocations\":3,"));
+    assert!(outputs[1].ends_with("}}\n"));
+    assert!(outputs[2].contains("# TYPE app_allocations counter\napp_allocations 3\n"));
+    assert!(outputs[2].contains("# TYPE app_bytes_reallocated gauge\napp_bytes_reallocated -8\n"));
+    assert!(outputs[3].starts_with("app\\ mem,thread=main\\,1,path=a\\b allocations=3u,deallocations=0u,"));
+    assert!(outputs[3].contains(",bytes_reallocated=-8i,"));
+    assert!(outputs[3].ends_with("=0u 10000000000\n"));
+
+    let mut out = Vec::new();
+    let wrapped = Snapshot::new(Stats::builder().allocations(u64::MAX).build());
+    InfluxExporter::default().export(&mut out, &wrapped).unwrap();
+    assert!(String::from_utf8(out)
+        .unwrap()
+        .contains(" allocations=18446744073709551615u,"));
+}
+
+#[test]
+fn exit_report_exports_when_dropped() {
+    let buffer = SharedBuffer::default();
+    let report = ExitReport::new(
+        &INSTRUMENTED_SYSTEM,
+        Template::parse("final: {allocations}").unwrap(),
+        buffer.clone(),
+    );
+    assert!(buffer.0.lock().unwrap().is_empty());
+    drop(report);
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert!(output.starts_with("final: "));
+    assert!(output.ends_with('\n'));
+}
+
+#[test]
+fn stats_display_scales_units() {
+    let stats = Stats::builder()
+        .allocations(1_024)
+        .deallocations(1_020)
+        .reallocations(12)
+        .bytes_allocated(3_355_443 + 98_304)
+        .bytes_deallocated(3_355_443)
+        .build();
+    assert_eq!(
+        stats.to_string(),
+        "alloc: 1 024 ops / 3.3 MiB, dealloc: 1 020 ops / 3.2 MiB, realloc: 12 ops, net: +96 KiB"
+    );
+    assert_eq!(
+        Stats::default().to_string(),
+        "alloc: 0 ops / 0 B, dealloc: 0 ops / 0 B, realloc: 0 ops, net: +0 B"
+    );
+}
+
+#[test]
+fn timed_reports_combine_time_and_allocations() {
+    let report = TimedReport {
+        elapsed: Duration::from_millis(12),
+        change: Stats::builder().allocations(14).bytes_allocated(80 * 1024).build(),
+    };
+    assert_eq!(report.to_string(), "took 12.0ms and allocated 80 KiB in 14 allocations");
+}
+
+#[test]
+fn region_reports_merge_totals() {
+    let change = |allocations, bytes| Stats::builder().allocations(allocations).bytes_allocated(bytes).build();
+    let total: Stats = [change(1, 16), change(3, 48)].iter().sum();
+    assert_eq!(total, change(4, 64));
+    assert_eq!(change(1, 16) + change(3, 48) - change(3, 48), change(1, 16));
+
+    let mut report: RegionReport = vec![change(1, 16), change(3, 48)].into_iter().collect();
+    report.merge(&RegionReport::from_iter(vec![change(2, 32)]));
+    assert_eq!(report.len(), 3);
+    assert_eq!(report.total(), change(6, 96));
+    assert_eq!(report.mean("allocations"), Some(2.0));
+    assert_eq!(RegionReport::new().mean("allocations"), None);
+    assert!(report.to_string().ends_with(", mean 2.0 allocs / 32 B"));
+}
+
+#[test]
+fn test_summaries_list_the_most_allocating_first() {
+    let change = |allocations| Stats::builder().allocations(allocations).build();
+    record_test("summary_light", change(1));
+    record_test("summary_heavy", change(90));
+    let summary = TestSummary::collect();
+    let names: Vec<_> = summary
+        .tests
+        .iter()
+        .map(|(name, _)| &name[..])
+        .filter(|name| name.starts_with("summary_"))
+        .collect();
+    assert_eq!(names, ["summary_heavy", "summary_light"]);
+    let table = summary.to_string();
+    assert!(table.starts_with("test "));
+    assert!(table
+        .lines()
+        .any(|line| line.starts_with("summary_heavy") && line.contains(" 90 ")));
+}
+
+#[test]
+fn statsd_sends_counter_changes_and_gauges() {
+    let snapshot = Snapshot {
+        stats: Stats::builder().allocations(5).bytes_current(-8).build(),
+        previous: Stats::builder().allocations(2).build(),
+        elapsed: Duration::from_secs(1),
+        time: UNIX_EPOCH,
+    };
+    let mut out = Vec::new();
+    StatsdExporter::new("app").export(&mut out, &snapshot).unwrap();
+    let lines = String::from_utf8(out).unwrap();
+    assert!(lines.starts_with("app.allocations:3|c\n"));
+    assert!(lines.contains("app.bytes_current:0|g\napp.bytes_current:-8|g\n"));
+
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut writer = UdpWriter::connect(server.local_addr().unwrap()).unwrap();
+    writer.write_all(lines.as_bytes()).unwrap();
+    writer.flush().unwrap();
+    let mut received = String::new();
+    let mut buf = [0; 2048];
+    while received.len() < lines.len() {
+        let len = server.recv(&mut buf).unwrap();
+        assert!(len <= 1_432 && buf[len - 1] == b'\n');
+        received.push_str(std::str::from_utf8(&buf[..len]).unwrap());
+    }
+    assert_eq!(received, lines);
+}
+
+#[test]
+fn csv_recorder_appends_rows_under_a_single_header() {
+    let path = env::temp_dir().join(format!("stats_alloc-{}.csv", std::process::id()));
+    let _ = fs::remove_file(&path);
+    for _ in 0..2 {
+        let recorder = CsvRecorder::start(&INSTRUMENTED_SYSTEM, &path, Duration::from_millis(10)).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        recorder.stop();
+    }
+    let csv = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let mut lines = csv.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with("time,allocations,deallocations,"));
+    let rows: Vec<_> = lines.collect();
+    assert!(rows.len() >= 2);
+    assert!(rows
+        .iter()
+        .all(|row| !row.starts_with("time") && row.split(',').count() == header.split(',').count()));
+}
+
+#[test]
+fn chrome_traces_interleave_counters_and_regions() {
+    let mut exporter = ChromeTraceExporter::new();
+    let snapshot = Snapshot {
+        stats: Stats::builder().allocations(3).build(),
+        previous: Stats::default(),
+        elapsed: Duration::from_secs(1),
+        time: UNIX_EPOCH + Duration::from_millis(1_500),
+    };
+    let mut out = Vec::new();
+    exporter.begin(&mut out).unwrap();
+    exporter.begin_region(&mut out, "parse \"body\"").unwrap();
+    exporter.export(&mut out, &snapshot).unwrap();
+    exporter.end_region(&mut out, "parse \"body\"").unwrap();
+    exporter.finish(&mut out).unwrap();
+    let trace = String::from_utf8(out).unwrap();
+    let events: Vec<_> = trace.lines().skip(1).take_while(|line| *line != "]").collect();
+    assert!(events[0].starts_with("{\"name\":\"parse \\\"body\\\"\",\"cat\":\"stats_alloc\",\"ph\":\"B\","));
+    assert!(events[1].starts_with("{\"name\":\"allocations\",\"cat\":\"stats_alloc\",\"ph\":\"C\",\"ts\":1500000,"));
+    assert!(events[1].ends_with(",\"tid\":0,\"args\":{\"value\":3}},"));
+    assert!(events.last().unwrap().contains("\"ph\":\"E\""));
+    assert!(events.iter().rev().skip(1).all(|event| event.ends_with("},")));
+    assert!(trace.starts_with("[\n{") && trace.ends_with("}\n]\n"));
+}
+
+#[test]
+fn massif_snapshots_record_current_bytes() {
+    let snapshot = |millis, bytes_current| Snapshot {
+        stats: Stats::builder().bytes_current(bytes_current).build(),
+        previous: Stats::default(),
+        elapsed: Duration::from_secs(0),
+        time: UNIX_EPOCH + Duration::from_millis(millis),
+    };
+    let mut exporter = MassifExporter::new();
+    let mut out = Vec::new();
+    exporter.begin(&mut out).unwrap();
+    exporter.export(&mut out, &snapshot(1_000, 64)).unwrap();
+    exporter.export(&mut out, &snapshot(1_250, -8)).unwrap();
+    exporter.finish(&mut out).unwrap();
+    let massif = String::from_utf8(out).unwrap();
+    assert!(massif.starts_with("desc: (none)\ncmd: "));
+    assert!(massif.contains("\ntime_unit: ms\n#-----------\nsnapshot=0\n#-----------\ntime=0\nmem_heap_B=64\n"));
+    assert!(massif.ends_with(
+        "snapshot=1\n#-----------\ntime=250\nmem_heap_B=0\nmem_heap_extra_B=0\nmem_stacks_B=0\nheap_tree=empty\n"
+    ));
+}
@@ -0,0 +1,98 @@
+#![cfg(not(feature = "noop"))]
+
+extern crate stats_alloc;
+
+use stats_alloc::{Comparison, Region, ScopedAlloc, ScopedAllocGuard, StatsAlloc, INSTRUMENTED_SYSTEM};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[global_allocator]
+static GLOBAL: &StatsAlloc<System> = &INSTRUMENTED_SYSTEM;
+
+const ARENA_SIZE: usize = 64 * 1024;
+
+#[repr(align(4096))]
+struct Arena {
+    buffer: UnsafeCell<[u8; ARENA_SIZE]>,
+    used: AtomicUsize,
+}
+
+unsafe impl Sync for Arena {}
+
+unsafe impl GlobalAlloc for Arena {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let base = self.buffer.get() as *mut u8;
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            let start = (used + layout.align() - 1) & !(layout.align() - 1);
+            let end = start + layout.size();
+            if end > ARENA_SIZE {
+                return ptr::null_mut();
+            }
+            match self
+                .used
+                .compare_exchange(used, end, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return base.add(start),
+                Err(actual) => used = actual,
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+unsafe impl ScopedAlloc for Arena {
+    fn owns(&self, ptr: *mut u8) -> bool {
+        let base = self.buffer.get() as *mut u8 as usize;
+        (base..base + ARENA_SIZE).contains(&(ptr as usize))
+    }
+}
+
+static ARENA: Arena = Arena {
+    buffer: UnsafeCell::new([0; ARENA_SIZE]),
+    used: AtomicUsize::new(0),
+};
+
+#[test]
+fn scoped_allocations_use_the_backing_allocator() {
+    let before: Vec<u8> = Vec::with_capacity(16);
+    let reg = Region::new(GLOBAL);
+    {
+        let _guard = unsafe { ScopedAllocGuard::new(&ARENA) };
+        let inside: Vec<u8> = Vec::with_capacity(1_024);
+        assert!(ARENA.owns(inside.as_ptr() as *mut u8));
+        assert!(ARENA.used.load(Ordering::SeqCst) >= 1_024);
+        drop(before);
+    }
+    let after: Vec<u8> = Vec::with_capacity(16);
+    assert!(!ARENA.owns(after.as_ptr() as *mut u8));
+    assert!(reg.change().bytes_allocated >= 1_024);
+}
+
+#[test]
+fn comparison_measures_each_candidate_separately() {
+    let arena: &'static StatsAlloc<Arena> = Box::leak(Box::new(StatsAlloc::new(Arena {
+        buffer: UnsafeCell::new([0; ARENA_SIZE]),
+        used: AtomicUsize::new(0),
+    })));
+    let report = unsafe {
+        Comparison::new()
+            .baseline("system", GLOBAL)
+            .candidate("arena", arena)
+            .run(|| {
+                let buffer: Vec<u8> = Vec::with_capacity(1_024);
+                let _ = ::std::mem::size_of_val(&buffer);
+            })
+    };
+    assert_eq!(report.measurements.len(), 2);
+    assert_eq!(report.measurements[1].name, "arena");
+    assert_eq!(report.measurements[1].stats.allocations, 1);
+    assert_eq!(report.measurements[1].stats.bytes_allocated, 1_024);
+    assert_eq!(arena.stats().allocations, 1);
+    println!("{}", report);
+}
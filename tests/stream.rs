@@ -0,0 +1,25 @@
+#![cfg(feature = "async")]
+
+extern crate futures_core;
+extern crate futures_executor;
+extern crate stats_alloc;
+
+use futures_core::Stream;
+use futures_executor::block_on;
+use stats_alloc::INSTRUMENTED_SYSTEM;
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn snapshots_are_yielded_once_per_interval() {
+    let mut snapshots = INSTRUMENTED_SYSTEM.snapshots(Duration::from_millis(20));
+    let start = Instant::now();
+    for _ in 0..3 {
+        let next = block_on(poll_fn(|cx| Pin::new(&mut snapshots).poll_next(cx)));
+        assert!(next.is_some());
+    }
+    assert!(start.elapsed() >= Duration::from_millis(40));
+}